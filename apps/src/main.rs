@@ -4,7 +4,8 @@ use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
-use alloy::primitives::Address;
+use alloy::primitives::{Address, Bytes, FixedBytes};
+use alloy::providers::ProviderBuilder;
 use alloy::signers::local::PrivateKeySigner;
 use alloy::sol_types::SolValue;
 use anyhow::{Context, Result};
@@ -12,16 +13,19 @@ use boundless_market::{
     request_builder::RequirementParams, Client, Deployment, GuestEnv, StorageProviderConfig,
 };
 use clap::Parser;
-use csv::ReaderBuilder;
-use guests::ORDER_BOOK_ELF;
+use csv::{ReaderBuilder, StringRecord};
+use guests::{ORDER_BOOK_ELF, ORDER_BOOK_ID};
 use orderbook::{
-    build_utxo_merkle_tree, generate_utxo_proof, BatchInput, Order, Side, SolJournal, Utxo,
-    UtxoWithProof,
+    match_orders, Amendment, BatchInput, BatchOutput, CancelReason, Cancellation, EngineKind,
+    FeeSchedule, IntentKind, IntentRejection, MarketParams, MatchError, OracleRound, Order,
+    OrderType, PostOnlyOutcome, PostOnlyPolicy, PriceBand, SelfTradePrevention, Side, SolJournal,
+    SolMultiBatchInput, TimeInForce, Utxo, UtxoTree, UtxoWithProof,
 };
 use risc0_steel::{
     ethereum::{EthEvmEnv, ETH_SEPOLIA_CHAIN_SPEC},
-    Contract,
+    Contract, EvmBlockHeader,
 };
+use risc0_zkvm::sha::Digest;
 use serde::{Deserialize, Serialize};
 use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
 use url::Url;
@@ -31,7 +35,43 @@ alloy::sol! {
     #[sol(rpc)]
     interface IOrderBook {
         function utxoMerkleRoot() external view returns (bytes32);
+        function utxoLeafCount() external view returns (uint64);
         function currentBatchIndex() external view returns (uint64);
+        function selfTradePrevention() external view returns (uint8);
+        function matchingEngine() external view returns (uint8);
+        function postOnlyPolicy() external view returns (uint8);
+        function lastTradePrice() external view returns (uint64);
+        function oracle() external view returns (address);
+        function makerFeeBps() external view returns (uint16);
+        function takerFeeBps() external view returns (uint16);
+        function marketParams()
+            external
+            view
+            returns (
+                uint64 tickSize,
+                uint64 lotSize,
+                uint64 minQuantity,
+                uint128 minNotional,
+                int8 priceExponent
+            );
+        function priceBand() external view returns (uint16 bandBps, uint8 policy);
+        function handleProof(bytes32 imageId, bytes calldata journal, bytes calldata seal) external;
+    }
+
+    /// Chainlink price feed interface
+    #[sol(rpc)]
+    interface IAggregatorV3 {
+        function latestRoundData()
+            external
+            view
+            returns (
+                uint80 roundId,
+                int256 answer,
+                uint256 startedAt,
+                uint256 updatedAt,
+                uint80 answeredInRound
+            );
+        function decimals() external view returns (uint8);
     }
 }
 
@@ -43,10 +83,18 @@ struct Args {
     #[clap(short, long, env = "ORDERS", default_value = "orders.csv")]
     orders: PathBuf,
 
-    /// Path to JSON file containing existing UTXOs
+    /// Path to JSON file containing existing UTXOs, each at its leaf in its market's UTXO tree
     #[clap(short, long, env = "UTXO_FILE", default_value = "utxos.json")]
     utxo_file: Option<PathBuf>,
 
+    /// Path to JSON file containing owner-signed cancellations of existing UTXOs
+    #[clap(long, env = "CANCELLATIONS")]
+    cancellations: Option<PathBuf>,
+
+    /// Path to JSON file containing owner-signed amendments of existing UTXOs
+    #[clap(long, env = "AMENDMENTS")]
+    amendments: Option<PathBuf>,
+
     /// URL of the Ethereum RPC endpoint
     #[clap(short, long, env = "RPC_URL")]
     rpc_url: Url,
@@ -55,7 +103,7 @@ struct Args {
     #[clap(long, env = "PRIVATE_KEY")]
     private_key: PrivateKeySigner,
 
-    /// OrderBook contract address
+    /// OrderBook contract Boundless calls back, and the market of orders that name none
     #[clap(long, env = "ORDER_BOOK_ADDRESS")]
     order_book: Address,
 
@@ -68,31 +116,136 @@ struct Args {
     deployment: Option<Deployment>,
 }
 
+/// A UTXO at its leaf in its market's UTXO tree, for JSON storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SerializableLeaf {
+    leaf_index: u64,
+    #[serde(flatten)]
+    utxo: SerializableUtxo,
+}
+
 /// Serializable UTXO for JSON storage
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SerializableUtxo {
     id: String,
+    market: String,
     side: String,
     price: u64,
+    #[serde(default, skip_serializing_if = "is_zero_exponent")]
+    price_exponent: i8,
     quantity: u64,
+    #[serde(default, skip_serializing_if = "is_zero_budget")]
+    quote_quantity: u128,
     owner: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    counterparties: Vec<String>,
     nonce: u64,
     expiry_batch: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    expiry_timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    self_trade_prevention: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    post_only: bool,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    min_fill_quantity: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    all_or_none: bool,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    hidden_quantity: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    display_quantity: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time_in_force: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    trigger_price: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    triggered: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pegged: bool,
+    #[serde(default, skip_serializing_if = "is_zero_offset")]
+    peg_offset: i64,
+    /// Blinding salt of the UTXO ID, kept private by the host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_utxo_id: Option<String>,
+    /// Defaults to the order's quantity and reserve, as for a new order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_quantity: Option<u64>,
+    /// Defaults to the budget of a quote buy, as for a new order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_quote_quantity: Option<u128>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    filled_quantity: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_zero_budget(value: &u128) -> bool {
+    *value == 0
+}
+
+fn is_zero_offset(value: &i64) -> bool {
+    *value == 0
+}
+
+fn is_zero_exponent(value: &i8) -> bool {
+    *value == 0
 }
 
 impl From<&Utxo> for SerializableUtxo {
     fn from(utxo: &Utxo) -> Self {
         SerializableUtxo {
             id: format!("0x{}", hex::encode(utxo.id)),
+            market: format!("{}", utxo.order.market),
             side: match utxo.order.side {
                 Side::Buy => "buy".to_string(),
                 Side::Sell => "sell".to_string(),
             },
             price: utxo.order.price,
+            price_exponent: utxo.order.price_exponent,
             quantity: utxo.order.quantity,
+            quote_quantity: utxo.order.quote_quantity,
             owner: format!("{}", utxo.order.owner),
+            counterparties: utxo
+                .order
+                .counterparties
+                .iter()
+                .map(|counterparty| format!("{}", counterparty))
+                .collect(),
             nonce: utxo.order.nonce,
             expiry_batch: utxo.order.expiry_batch,
+            expiry_timestamp: utxo.order.expiry_timestamp,
+            self_trade_prevention: utxo
+                .order
+                .self_trade_prevention
+                .map(|mode| format_self_trade_prevention(mode).to_string()),
+            post_only: utxo.order.post_only,
+            min_fill_quantity: utxo.order.min_fill_quantity,
+            all_or_none: utxo.order.all_or_none,
+            hidden_quantity: utxo.order.hidden_quantity,
+            display_quantity: utxo.order.display_quantity,
+            // Only stop orders rest with a market type or a short time in force
+            order_type: (utxo.order.order_type != OrderType::Limit)
+                .then(|| format_order_type(utxo.order.order_type).to_string()),
+            time_in_force: (utxo.order.time_in_force != TimeInForce::GoodTillCancel)
+                .then(|| format_time_in_force(utxo.order.time_in_force).to_string()),
+            trigger_price: utxo.order.trigger_price,
+            triggered: utxo.order.triggered,
+            pegged: utxo.order.pegged,
+            peg_offset: utxo.order.peg_offset,
+            salt: (utxo.order.salt != FixedBytes::ZERO)
+                .then(|| format!("0x{}", hex::encode(utxo.order.salt))),
+            parent_utxo_id: (utxo.parent_utxo_id != FixedBytes::ZERO)
+                .then(|| format!("0x{}", hex::encode(utxo.parent_utxo_id))),
+            original_quantity: Some(utxo.original_quantity),
+            original_quote_quantity: Some(utxo.original_quote_quantity),
+            filled_quantity: utxo.filled_quantity,
         }
     }
 }
@@ -102,20 +255,122 @@ impl TryFrom<&SerializableUtxo> for Utxo {
 
     fn try_from(s: &SerializableUtxo) -> Result<Self, Self::Error> {
         let order = Order {
+            market: s.market.parse()?,
             side: match s.side.as_str() {
                 "buy" | "Buy" | "BUY" => Side::Buy,
                 "sell" | "Sell" | "SELL" => Side::Sell,
                 _ => anyhow::bail!("Invalid side: {}", s.side),
             },
             price: s.price,
+            price_exponent: s.price_exponent,
             quantity: s.quantity,
+            quote_quantity: s.quote_quantity,
             owner: s.owner.parse()?,
+            counterparties: s
+                .counterparties
+                .iter()
+                .map(|counterparty| counterparty.parse::<Address>())
+                .collect::<Result<_, _>>()?,
             nonce: s.nonce,
             expiry_batch: s.expiry_batch,
+            expiry_timestamp: s.expiry_timestamp,
+            self_trade_prevention: s
+                .self_trade_prevention
+                .as_deref()
+                .map(parse_self_trade_prevention)
+                .transpose()?,
+            time_in_force: s
+                .time_in_force
+                .as_deref()
+                .map(parse_time_in_force)
+                .transpose()?
+                .unwrap_or_default(),
+            post_only: s.post_only,
+            order_type: s
+                .order_type
+                .as_deref()
+                .map(parse_order_type)
+                .transpose()?
+                .unwrap_or_default(),
+            min_fill_quantity: s.min_fill_quantity,
+            all_or_none: s.all_or_none,
+            hidden_quantity: s.hidden_quantity,
+            display_quantity: s.display_quantity,
+            trigger_price: s.trigger_price,
+            triggered: s.triggered,
+            pegged: s.pegged,
+            peg_offset: s.peg_offset,
+            salt: s
+                .salt
+                .as_deref()
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default(),
         };
 
-        // Always compute ID from order data to ensure consistency
-        Ok(Utxo::new(order))
+        let parent_utxo_id: FixedBytes<32> = s
+            .parent_utxo_id
+            .as_deref()
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+        // A new order's lineage, unless the file records the order's own
+        let first = Utxo::new(order);
+        let original_quantity = s.original_quantity.unwrap_or(first.original_quantity);
+        let original_quote_quantity = s
+            .original_quote_quantity
+            .unwrap_or(first.original_quote_quantity);
+
+        // Always compute ID from order data and lineage to ensure consistency
+        Ok(Utxo::with_lineage(
+            first.order,
+            parent_utxo_id,
+            original_quantity,
+            original_quote_quantity,
+            s.filled_quantity,
+        ))
+    }
+}
+
+/// Serializable owner-signed cancellation for JSON input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SerializableCancellation {
+    utxo_id: String,
+    /// 65-byte EIP-191 signature of the UTXO ID, hex encoded
+    signature: String,
+}
+
+impl TryFrom<&SerializableCancellation> for Cancellation {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &SerializableCancellation) -> Result<Self> {
+        Ok(Cancellation {
+            utxo_id: s.utxo_id.parse()?,
+            signature: s.signature.parse()?,
+        })
+    }
+}
+
+/// Serializable owner-signed amendment for JSON input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SerializableAmendment {
+    utxo_id: String,
+    price: u64,
+    quantity: u64,
+    /// 65-byte EIP-191 signature of `abi.encodePacked(utxoId, price, quantity)`, hex encoded
+    signature: String,
+}
+
+impl TryFrom<&SerializableAmendment> for Amendment {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &SerializableAmendment) -> Result<Self> {
+        Ok(Amendment {
+            utxo_id: s.utxo_id.parse()?,
+            price: s.price,
+            quantity: s.quantity,
+            signature: s.signature.parse()?,
+        })
     }
 }
 
@@ -152,6 +407,9 @@ async fn run(args: Args) -> Result<()> {
     tracing::info!("Batch size: {}", batch_size);
     tracing::info!("OrderBook contract: {}", args.order_book);
 
+    // The same key delivers the proof to every market but the callback one
+    let signer = args.private_key.clone();
+
     // Create a Boundless client from the provided parameters
     let client = Client::builder()
         .with_rpc_url(args.rpc_url.clone())
@@ -167,10 +425,10 @@ async fn run(args: Args) -> Result<()> {
         if utxo_path.exists() {
             let file = File::open(utxo_path)?;
             let reader = BufReader::new(file);
-            let serializable: Vec<SerializableUtxo> = serde_json::from_reader(reader)?;
+            let serializable: Vec<SerializableLeaf> = serde_json::from_reader(reader)?;
             serializable
                 .iter()
-                .map(|s| Utxo::try_from(s))
+                .map(|s| Ok((s.leaf_index, Utxo::try_from(&s.utxo)?)))
                 .collect::<Result<Vec<_>>>()?
         } else {
             Vec::new()
@@ -180,10 +438,63 @@ async fn run(args: Args) -> Result<()> {
     };
     tracing::info!("Loaded {} existing UTXOs", existing_utxos.len());
 
-    // Parse new orders from CSV
-    let new_orders = parse_orders_csv(&args.orders, batch_size)?;
+    // Load owner-signed cancellations from JSON file if provided
+    let cancellations = if let Some(ref cancellations_path) = args.cancellations {
+        let file = File::open(cancellations_path)?;
+        let serializable: Vec<SerializableCancellation> =
+            serde_json::from_reader(BufReader::new(file))?;
+        // A malformed entry is skipped so it cannot hold up the rest of the batch
+        serializable
+            .iter()
+            .filter_map(|s| {
+                Cancellation::try_from(s)
+                    .inspect_err(|e| {
+                        tracing::warn!("Skipping cancellation of {}: {}", s.utxo_id, e)
+                    })
+                    .ok()
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    tracing::info!("Loaded {} cancellations", cancellations.len());
+
+    // Load owner-signed amendments from JSON file if provided
+    let amendments = if let Some(ref amendments_path) = args.amendments {
+        let file = File::open(amendments_path)?;
+        let serializable: Vec<SerializableAmendment> =
+            serde_json::from_reader(BufReader::new(file))?;
+        serializable
+            .iter()
+            .filter_map(|s| {
+                Amendment::try_from(s)
+                    .inspect_err(|e| tracing::warn!("Skipping amendment of {}: {}", s.utxo_id, e))
+                    .ok()
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    tracing::info!("Loaded {} amendments", amendments.len());
+
+    // Parse new orders from CSV, each in the market its row names
+    let new_orders = parse_orders_csv(&args.orders, batch_size, args.order_book)?;
     tracing::info!("Parsed {} new orders", new_orders.len());
 
+    // One proof clears every market with a new order or an existing UTXO. Boundless
+    // calls back `--order-book`, so it comes first.
+    let mut markets = vec![args.order_book];
+    for market in existing_utxos
+        .iter()
+        .map(|(_, utxo)| utxo.order.market)
+        .chain(new_orders.iter().map(|(order, _)| order.market))
+    {
+        if !markets.contains(&market) {
+            markets.push(market);
+        }
+    }
+    tracing::info!("Clearing {} markets", markets.len());
+
     // Create Steel EVM environment for on-chain state verification
     tracing::info!("Creating Steel EVM environment...");
     let mut evm_env = EthEvmEnv::builder()
@@ -191,61 +502,229 @@ async fn run(args: Args) -> Result<()> {
         .chain_spec(&ETH_SEPOLIA_CHAIN_SPEC)
         .build()
         .await?;
+    // Good-till-time orders expire against the timestamp of the block Steel commits to
+    let block_timestamp = evm_env.header().timestamp();
 
-    // Preflight: query on-chain state via Steel
-    let mut contract = Contract::preflight(args.order_book, &mut evm_env);
-
-    let on_chain_merkle_root = contract
-        .call_builder(&IOrderBook::utxoMerkleRootCall {})
-        .call()
-        .await?;
-    let on_chain_batch_index = contract
-        .call_builder(&IOrderBook::currentBatchIndexCall {})
-        .call()
-        .await?;
+    // Each market's batch input, its local run and the UTXO set it started from
+    let mut cleared: Vec<(BatchInput, BatchOutput, Vec<(u64, Utxo)>)> = Vec::new();
+    for &market in &markets {
+        // Preflight: query the market's on-chain state via Steel, in the same
+        // environment for every market, as the guest reads it
+        let mut contract = Contract::preflight(market, &mut evm_env);
 
-    tracing::info!("On-chain batch index: {}", on_chain_batch_index);
-    tracing::info!(
-        "On-chain UTXO Merkle root: 0x{}",
-        hex::encode(on_chain_merkle_root)
-    );
+        let on_chain_merkle_root = contract
+            .call_builder(&IOrderBook::utxoMerkleRootCall {})
+            .call()
+            .await?;
+        let on_chain_leaf_count = contract
+            .call_builder(&IOrderBook::utxoLeafCountCall {})
+            .call()
+            .await?;
+        let on_chain_batch_index = contract
+            .call_builder(&IOrderBook::currentBatchIndexCall {})
+            .call()
+            .await?;
+        let self_trade_prevention: SelfTradePrevention = contract
+            .call_builder(&IOrderBook::selfTradePreventionCall {})
+            .call()
+            .await?
+            .try_into()
+            .map_err(anyhow::Error::msg)?;
+        let matching_engine: EngineKind = contract
+            .call_builder(&IOrderBook::matchingEngineCall {})
+            .call()
+            .await?
+            .try_into()
+            .map_err(anyhow::Error::msg)?;
+        let post_only_policy: PostOnlyPolicy = contract
+            .call_builder(&IOrderBook::postOnlyPolicyCall {})
+            .call()
+            .await?
+            .try_into()
+            .map_err(anyhow::Error::msg)?;
+        let last_trade_price = contract
+            .call_builder(&IOrderBook::lastTradePriceCall {})
+            .call()
+            .await?;
+        let fee_schedule = FeeSchedule {
+            maker_fee_bps: contract
+                .call_builder(&IOrderBook::makerFeeBpsCall {})
+                .call()
+                .await?,
+            taker_fee_bps: contract
+                .call_builder(&IOrderBook::takerFeeBpsCall {})
+                .call()
+                .await?,
+        };
+        let market_params = contract
+            .call_builder(&IOrderBook::marketParamsCall {})
+            .call()
+            .await?;
+        let market_params = MarketParams {
+            tick_size: market_params.tickSize,
+            lot_size: market_params.lotSize,
+            min_quantity: market_params.minQuantity,
+            min_notional: market_params.minNotional,
+            price_exponent: market_params.priceExponent,
+        };
+        let price_band = contract
+            .call_builder(&IOrderBook::priceBandCall {})
+            .call()
+            .await?;
+        let price_band = PriceBand {
+            band_bps: price_band.bandBps,
+            policy: price_band.policy.try_into().map_err(anyhow::Error::msg)?,
+        };
+        let oracle_address = contract
+            .call_builder(&IOrderBook::oracleCall {})
+            .call()
+            .await?;
 
-    // Build Merkle tree and proofs for existing UTXOs
-    let (tree, computed_root) = build_utxo_merkle_tree(&existing_utxos);
+        // Read the latest oracle round in the same EVM environment, as the guest does. A
+        // stale or unusable round leaves pegged orders dormant for this batch.
+        let oracle_round = if oracle_address == Address::ZERO {
+            None
+        } else {
+            let round = Contract::preflight(oracle_address, &mut evm_env)
+                .call_builder(&IAggregatorV3::latestRoundDataCall {})
+                .call()
+                .await?;
+            let decimals = Contract::preflight(oracle_address, &mut evm_env)
+                .call_builder(&IAggregatorV3::decimalsCall {})
+                .call()
+                .await?;
+            OracleRound::from_answer(
+                round.roundId,
+                round.answer,
+                round.updatedAt,
+                decimals,
+                market_params.price_exponent,
+                block_timestamp,
+            )
+            .inspect_err(|reason| {
+                tracing::warn!("Oracle round {} not used: {}", round.roundId, reason)
+            })
+            .ok()
+        };
 
-    // Verify computed root matches on-chain root (for first batch with no UTXOs, both are zero)
-    if existing_utxos.is_empty() {
-        tracing::info!("First batch - no existing UTXOs to verify");
-    } else {
-        assert_eq!(
-            computed_root, on_chain_merkle_root,
-            "Computed Merkle root does not match on-chain root"
+        tracing::info!("Market {}", market);
+        tracing::info!("On-chain batch index: {}", on_chain_batch_index);
+        tracing::info!(
+            "On-chain UTXO Merkle root: 0x{}",
+            hex::encode(on_chain_merkle_root)
         );
-        tracing::info!("Merkle root verified!");
-    }
 
-    // Build UTXOs with proofs
-    let existing_utxos_with_proofs: Vec<UtxoWithProof> = existing_utxos
-        .iter()
-        .enumerate()
-        .map(|(i, utxo)| {
-            let proof_hashes = generate_utxo_proof(&tree, i).unwrap_or_default();
-            UtxoWithProof {
+        // Build the market's UTXO tree, a tombstone at every leaf without a UTXO, and
+        // the proofs of its existing UTXOs
+        let market_utxos: Vec<(u64, Utxo)> = existing_utxos
+            .iter()
+            .filter(|(_, utxo)| utxo.order.market == market)
+            .cloned()
+            .collect();
+        let mut leaves = vec![FixedBytes::ZERO; on_chain_leaf_count as usize];
+        for (leaf_index, utxo) in &market_utxos {
+            let leaf = leaves.get_mut(*leaf_index as usize).with_context(|| {
+                format!(
+                    "UTXO {} at leaf {} is past the {} leaves on chain",
+                    utxo.id, leaf_index, on_chain_leaf_count
+                )
+            })?;
+            *leaf = utxo.id;
+        }
+        let tree = UtxoTree::from_leaves(&leaves);
+        let computed_root = tree.root();
+
+        // Verify computed root matches on-chain root (for first batch with no UTXOs, both are zero)
+        if market_utxos.is_empty() {
+            tracing::info!("First batch - no existing UTXOs to verify");
+        } else {
+            anyhow::ensure!(
+                computed_root == on_chain_merkle_root,
+                "{}",
+                MatchError::RootMismatch {
+                    expected: on_chain_merkle_root,
+                    actual: computed_root,
+                }
+            );
+            tracing::info!("Merkle root verified!");
+        }
+
+        // Build UTXOs with proofs
+        let existing_utxos_with_proofs: Vec<UtxoWithProof> = market_utxos
+            .iter()
+            .map(|(leaf_index, utxo)| UtxoWithProof {
                 utxo: utxo.clone(),
-                proof_hashes,
-                leaf_index: i,
-            }
-        })
-        .collect();
-
-    // Create batch input
-    let batch_input = BatchInput {
-        batch_index: on_chain_batch_index,
-        utxo_merkle_root: on_chain_merkle_root,
-        existing_utxos_with_proofs,
-        new_orders,
-    };
-    let input_bytes = batch_input.to_sol().abi_encode();
+                proof_hashes: tree.proof(*leaf_index),
+                leaf_index: *leaf_index as usize,
+            })
+            .collect();
+
+        // Owner intents go to the market holding their UTXO. One naming no known UTXO
+        // goes to the callback market, whose guest run rejects it as not open.
+        let routes_here = |utxo_id: &FixedBytes<32>| match existing_utxos
+            .iter()
+            .find(|(_, utxo)| utxo.id == *utxo_id)
+        {
+            Some((_, utxo)) => utxo.order.market == market,
+            None => market == args.order_book,
+        };
+
+        // Create batch input
+        let batch_input = BatchInput {
+            market,
+            batch_index: on_chain_batch_index,
+            block_timestamp,
+            utxo_merkle_root: on_chain_merkle_root,
+            utxo_leaf_count: on_chain_leaf_count,
+            append_proof: tree.proof(on_chain_leaf_count),
+            existing_utxos_with_proofs,
+            // An order whose row sets no price exponent is in the market's
+            new_orders: new_orders
+                .iter()
+                .filter(|(order, _)| order.market == market)
+                .map(|(order, price_exponent)| Order {
+                    price_exponent: price_exponent.unwrap_or(market_params.price_exponent),
+                    ..order.clone()
+                })
+                .collect(),
+            cancellations: cancellations
+                .iter()
+                .filter(|cancellation| routes_here(&cancellation.utxo_id))
+                .cloned()
+                .collect(),
+            amendments: amendments
+                .iter()
+                .filter(|amendment| routes_here(&amendment.utxo_id))
+                .cloned()
+                .collect(),
+            self_trade_prevention,
+            matching_engine,
+            post_only_policy,
+            last_trade_price,
+            oracle_round,
+            fee_schedule,
+            market_params,
+            price_band,
+        };
+
+        // Dry-run the matching engine locally so a bad order or UTXO is reported here
+        // instead of failing the proof inside the guest
+        let local_output = match match_orders(batch_input.clone()) {
+            Ok(output) => output,
+            Err(err) => anyhow::bail!(
+                "batch for market {} rejected by matching engine: {}",
+                market,
+                describe_match_error(&err, &batch_input)
+            ),
+        };
+        cleared.push((batch_input, local_output, market_utxos));
+    }
+
+    // The guest clears every market in one proof
+    let input_bytes = SolMultiBatchInput {
+        markets: cleared.iter().map(|(input, _, _)| input.to_sol()).collect(),
+    }
+    .abi_encode();
 
     tracing::info!("Preparing proof request for Boundless Market...");
 
@@ -253,11 +732,8 @@ async fn run(args: Args) -> Result<()> {
     let evm_input = evm_env.into_input().await?;
 
     // Build guest environment with all inputs
-    // The guest reads: evm_input, order_book_address, input_bytes
-    let guest_env = GuestEnv::builder()
-        .write(&evm_input)?
-        .write(&args.order_book)?
-        .write(&input_bytes)?;
+    // The guest reads: evm_input, input_bytes
+    let guest_env = GuestEnv::builder().write(&evm_input)?.write(&input_bytes)?;
 
     // Create a request with a callback to the OrderBook contract
     let request = client
@@ -299,41 +775,161 @@ async fn run(args: Args) -> Result<()> {
         .context("fulfillment has no journal")?;
     let journal = <SolJournal>::abi_decode(journal_bytes).context("failed to decode journal")?;
 
-    tracing::info!("=== Batch Execution Summary ===");
-    tracing::info!("Batch index: {}", journal.batchIndex);
-    tracing::info!("Fills executed: {}", journal.fills.len());
-    tracing::info!("New UTXOs created: {}", journal.newUtxos.len());
-    tracing::info!("UTXOs consumed: {}", journal.consumedUtxoIds.len());
-    tracing::info!(
-        "New UTXO Merkle root: 0x{}",
-        hex::encode(journal.newUtxoMerkleRoot)
-    );
+    // Boundless only called back `--order-book`. Every other market verifies the seal
+    // itself and settles its own entry of the same journal.
+    let image_id = FixedBytes::<32>::from_slice(Digest::from(ORDER_BOOK_ID).as_bytes());
+    let provider = ProviderBuilder::new()
+        .wallet(signer)
+        .connect_http(args.rpc_url.clone());
+    for &market in &markets[1..] {
+        let receipt = IOrderBook::new(market, &provider)
+            .handleProof(
+                image_id,
+                Bytes::copy_from_slice(journal_bytes),
+                fulfillment.seal.clone(),
+            )
+            .send()
+            .await?
+            .get_receipt()
+            .await?;
+        anyhow::ensure!(
+            receipt.status(),
+            "market {} rejected the proof in {}",
+            market,
+            receipt.transaction_hash
+        );
+        tracing::info!("Delivered proof to market {}", market);
+    }
 
-    // Print fill details
-    for (i, fill) in journal.fills.iter().enumerate() {
+    let mut next_utxos: Vec<SerializableLeaf> = Vec::new();
+    for (batch_input, local_output, market_utxos) in &cleared {
+        let batch = journal
+            .markets
+            .iter()
+            .find(|batch| batch.market == batch_input.market)
+            .context("journal has no batch for this market")?;
+
+        tracing::info!("=== Batch Execution Summary: {} ===", batch.market);
+        tracing::info!("Batch index: {}", batch.batchIndex);
+        tracing::info!("Fills executed: {}", batch.fills.len());
+        tracing::info!("New UTXOs created: {}", batch.newUtxos.len());
+        tracing::info!("UTXOs consumed: {}", batch.consumedUtxoIds.len());
+        tracing::info!("Orders cancelled: {}", batch.cancelledOrders.len());
+        tracing::info!("Last trade price: {}", batch.lastTradePrice);
+        if batch.halted {
+            tracing::warn!("Matching halted: a fill would have been outside the price band");
+        }
+        if let Some(round) = batch_input.oracle_round {
+            tracing::info!(
+                "Oracle round {} priced pegged orders at {}",
+                round.round_id,
+                round.price
+            );
+        }
         tracing::info!(
-            "Fill {}: {} -> {} @ {} for {} units",
-            i,
-            fill.maker,
-            fill.taker,
-            fill.price,
-            fill.quantity
+            "New UTXO Merkle root: 0x{}",
+            hex::encode(batch.newUtxoMerkleRoot)
+        );
+
+        // Print fill details
+        for (i, fill) in batch.fills.iter().enumerate() {
+            tracing::info!(
+                "Fill {}: {} -> {} @ {}e{} for {} units, notional {} (fees: maker {}, taker {})",
+                i,
+                fill.maker,
+                fill.taker,
+                fill.price,
+                fill.priceExponent,
+                fill.quantity,
+                fill.notional,
+                fill.makerFee,
+                fill.takerFee
+            );
+        }
+
+        // Print post-only outcomes so traders can see which quotes were repriced or rejected
+        for result in &batch.postOnlyResults {
+            let outcome = PostOnlyOutcome::try_from(result.outcome).map_err(anyhow::Error::msg)?;
+            tracing::info!(
+                "Post-only order 0x{} from {}: {:?} @ {} as 0x{}",
+                hex::encode(result.utxoId),
+                result.owner,
+                outcome,
+                result.price,
+                hex::encode(result.newUtxoId)
+            );
+        }
+
+        // Print cancellations with their reason, including new orders rejected by the market parameters
+        for cancelled in &batch.cancelledOrders {
+            let reason = CancelReason::try_from(cancelled.reason).map_err(anyhow::Error::msg)?;
+            tracing::info!(
+                "Cancelled order 0x{} from {}: {} units ({:?})",
+                hex::encode(cancelled.utxoId),
+                cancelled.owner,
+                cancelled.quantity,
+                reason
+            );
+        }
+
+        // Print amendment lineage so traders can follow their orders
+        for amended in &batch.amendedOrders {
+            tracing::info!(
+                "Amended order 0x{} from {} -> 0x{} (priority kept: {})",
+                hex::encode(amended.oldUtxoId),
+                amended.owner,
+                hex::encode(amended.newUtxoId),
+                amended.priorityKept
+            );
+        }
+
+        // Print the cancellations and amendments the guest dropped
+        for rejected in &batch.rejectedIntents {
+            let kind = IntentKind::try_from(rejected.kind).map_err(anyhow::Error::msg)?;
+            let reason = IntentRejection::try_from(rejected.reason).map_err(anyhow::Error::msg)?;
+            tracing::warn!(
+                "Rejected {:?} of 0x{}: {:?}",
+                kind,
+                hex::encode(rejected.utxoId),
+                reason
+            );
+        }
+
+        // Print the balance changes the contract settled in place of the fills
+        for transfer in &batch.netTransfers {
+            tracing::info!(
+                "Net transfer for {}: AssetA {}, AssetB {}",
+                transfer.account,
+                transfer.assetA,
+                transfer.assetB
+            );
+        }
+
+        // The next UTXO set of each market: the untouched UTXOs at their leaves and the
+        // new ones at the leaves appended after them.
+        // The journal hides iceberg reserves, so the full UTXOs come from the local run,
+        // which must have produced the proven root.
+        anyhow::ensure!(
+            local_output.new_utxo_merkle_root == batch.newUtxoMerkleRoot,
+            "{}",
+            MatchError::RootMismatch {
+                expected: batch.newUtxoMerkleRoot,
+                actual: local_output.new_utxo_merkle_root,
+            }
         );
+        next_utxos.extend(local_output.next_utxo_set(market_utxos).iter().map(
+            |(leaf_index, utxo)| SerializableLeaf {
+                leaf_index: *leaf_index,
+                utxo: SerializableUtxo::from(utxo),
+            },
+        ));
     }
 
-    // Save new UTXOs to file for next batch
+    // Save the next UTXO set of every market to file for the next batch
     if let Some(ref utxo_path) = args.utxo_file {
-        let new_utxos: Vec<SerializableUtxo> = journal
-            .newUtxos
-            .iter()
-            .map(|sol_utxo| {
-                let utxo = Utxo::from(sol_utxo);
-                SerializableUtxo::from(&utxo)
-            })
-            .collect();
-        let json = serde_json::to_string_pretty(&new_utxos)?;
+        let json = serde_json::to_string_pretty(&next_utxos)?;
         std::fs::write(utxo_path, json)?;
-        tracing::info!("Saved {} new UTXOs to {:?}", new_utxos.len(), utxo_path);
+        tracing::info!("Saved {} UTXOs to {:?}", next_utxos.len(), utxo_path);
     }
 
     tracing::info!("Order book batch processed successfully via Boundless Market!");
@@ -341,10 +937,56 @@ async fn run(args: Args) -> Result<()> {
     Ok(())
 }
 
-/// Parse orders from CSV file
-fn parse_orders_csv(path: &PathBuf, limit: usize) -> Result<Vec<Order>> {
+/// Describe a matching error together with the order or UTXO that caused it
+fn describe_match_error(err: &MatchError, input: &BatchInput) -> String {
+    match err {
+        MatchError::InvalidProof { leaf_index, .. }
+        | MatchError::MalformedUtxo { leaf_index, .. } => {
+            match input
+                .existing_utxos_with_proofs
+                .iter()
+                .find(|uwp| uwp.leaf_index == *leaf_index)
+            {
+                Some(uwp) => format!("{err} ({:?})", uwp.utxo.order),
+                None => err.to_string(),
+            }
+        }
+        MatchError::MalformedOrder { index, .. } => match input.new_orders.get(*index) {
+            Some(order) => format!("{err} ({order:?})"),
+            None => err.to_string(),
+        },
+        MatchError::InvalidCancellation { index, .. } => match input.cancellations.get(*index) {
+            Some(cancellation) => format!("{err} (utxo {})", cancellation.utxo_id),
+            None => err.to_string(),
+        },
+        MatchError::InvalidAmendment { index, .. } => match input.amendments.get(*index) {
+            Some(amendment) => format!("{err} (utxo {})", amendment.utxo_id),
+            None => err.to_string(),
+        },
+        MatchError::InvalidAppendProof { .. }
+        | MatchError::RootMismatch { .. }
+        | MatchError::LeafCountMismatch { .. }
+        | MatchError::BatchIndexMismatch { .. }
+        | MatchError::LastTradePriceMismatch { .. }
+        | MatchError::BlockTimestampMismatch { .. }
+        | MatchError::DuplicateMarket { .. }
+        | MatchError::InMarket { .. }
+        | MatchError::OracleRoundMismatch { .. }
+        | MatchError::MarketParamMismatch { .. } => err.to_string(),
+    }
+}
+
+/// Parse orders from CSV file, placing them in `market` unless a row names another.
+/// Each order comes with the price exponent its row sets, if any; the host prices the
+/// others in their market's `PRICE_EXPONENT` once it has read the market parameters.
+fn parse_orders_csv(
+    path: &PathBuf,
+    limit: usize,
+    market: Address,
+) -> Result<Vec<(Order, Option<i8>)>> {
     let file = File::open(path)?;
     let mut reader = ReaderBuilder::new().has_headers(true).from_reader(file);
+    let headers = reader.headers()?.clone();
 
     let mut orders = Vec::new();
     // good enough for PoC
@@ -386,14 +1028,136 @@ fn parse_orders_csv(path: &PathBuf, limit: usize) -> Result<Vec<Order>> {
             .parse()
             .context("Invalid expiry_batch")?;
 
-        orders.push(Order {
+        let self_trade_prevention = optional_field(&record, &headers, "self_trade_prevention")
+            .map(parse_self_trade_prevention)
+            .transpose()?;
+
+        let time_in_force = optional_field(&record, &headers, "time_in_force")
+            .map(parse_time_in_force)
+            .transpose()?
+            .unwrap_or_default();
+
+        let order_type = optional_field(&record, &headers, "order_type")
+            .map(parse_order_type)
+            .transpose()?
+            .unwrap_or_default();
+
+        let post_only = optional_field(&record, &headers, "post_only")
+            .map(str::parse::<bool>)
+            .transpose()
+            .context("Invalid post_only")?
+            .unwrap_or_default();
+
+        let min_fill_quantity = optional_field(&record, &headers, "min_fill_quantity")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("Invalid min_fill_quantity")?
+            .unwrap_or_default();
+
+        let all_or_none = optional_field(&record, &headers, "all_or_none")
+            .map(str::parse::<bool>)
+            .transpose()
+            .context("Invalid all_or_none")?
+            .unwrap_or_default();
+
+        let hidden_quantity = optional_field(&record, &headers, "hidden_quantity")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("Invalid hidden_quantity")?
+            .unwrap_or_default();
+
+        let display_quantity = optional_field(&record, &headers, "display_quantity")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("Invalid display_quantity")?
+            .unwrap_or_default();
+
+        let trigger_price = optional_field(&record, &headers, "trigger_price")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("Invalid trigger_price")?
+            .unwrap_or_default();
+
+        let pegged = optional_field(&record, &headers, "pegged")
+            .map(str::parse::<bool>)
+            .transpose()
+            .context("Invalid pegged")?
+            .unwrap_or_default();
+
+        let peg_offset = optional_field(&record, &headers, "peg_offset")
+            .map(str::parse::<i64>)
+            .transpose()
+            .context("Invalid peg_offset")?
+            .unwrap_or_default();
+
+        let price_exponent = optional_field(&record, &headers, "price_exponent")
+            .map(str::parse::<i8>)
+            .transpose()
+            .context("Invalid price_exponent")?;
+
+        let market = optional_field(&record, &headers, "market")
+            .map(str::parse::<Address>)
+            .transpose()
+            .context("Invalid market")?
+            .unwrap_or(market);
+
+        let quote_quantity = optional_field(&record, &headers, "quote_quantity")
+            .map(str::parse::<u128>)
+            .transpose()
+            .context("Invalid quote_quantity")?
+            .unwrap_or_default();
+
+        // Semicolon-separated addresses
+        let counterparties = optional_field(&record, &headers, "counterparties")
+            .map(|list| {
+                list.split(';')
+                    .map(str::parse::<Address>)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()
+            .context("Invalid counterparties")?
+            .unwrap_or_default();
+
+        let expiry_timestamp = optional_field(&record, &headers, "expiry_timestamp")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("Invalid expiry_timestamp")?
+            .unwrap_or_default();
+
+        // A fresh random salt unless the order's owner chose one
+        let salt = optional_field(&record, &headers, "salt")
+            .map(str::parse::<FixedBytes<32>>)
+            .transpose()
+            .context("Invalid salt")?
+            .unwrap_or_else(FixedBytes::random);
+
+        let order = Order {
+            market,
             side,
             price,
+            price_exponent: price_exponent.unwrap_or_default(),
             quantity,
+            quote_quantity,
             owner,
+            counterparties,
             nonce,
             expiry_batch,
-        });
+            expiry_timestamp,
+            self_trade_prevention,
+            time_in_force,
+            post_only,
+            order_type,
+            min_fill_quantity,
+            all_or_none,
+            hidden_quantity,
+            display_quantity,
+            trigger_price,
+            triggered: false,
+            pegged,
+            peg_offset,
+            salt,
+        };
+        orders.push((order, price_exponent));
 
         nonce += 1;
     }
@@ -401,10 +1165,72 @@ fn parse_orders_csv(path: &PathBuf, limit: usize) -> Result<Vec<Order>> {
     Ok(orders)
 }
 
+/// Get a non-empty value from an optional CSV column, looked up by header name
+fn optional_field<'a>(
+    record: &'a StringRecord,
+    headers: &StringRecord,
+    name: &str,
+) -> Option<&'a str> {
+    let index = headers.iter().position(|header| header == name)?;
+    record.get(index).filter(|value| !value.is_empty())
+}
+
+fn parse_self_trade_prevention(s: &str) -> Result<SelfTradePrevention> {
+    match s {
+        "cancel_newest" => Ok(SelfTradePrevention::CancelNewest),
+        "cancel_oldest" => Ok(SelfTradePrevention::CancelOldest),
+        "cancel_both" => Ok(SelfTradePrevention::CancelBoth),
+        "decrement_and_cancel" => Ok(SelfTradePrevention::DecrementAndCancel),
+        _ => anyhow::bail!("Invalid self_trade_prevention: {}", s),
+    }
+}
+
+fn format_self_trade_prevention(mode: SelfTradePrevention) -> &'static str {
+    match mode {
+        SelfTradePrevention::CancelNewest => "cancel_newest",
+        SelfTradePrevention::CancelOldest => "cancel_oldest",
+        SelfTradePrevention::CancelBoth => "cancel_both",
+        SelfTradePrevention::DecrementAndCancel => "decrement_and_cancel",
+    }
+}
+
+fn parse_time_in_force(s: &str) -> Result<TimeInForce> {
+    match s {
+        "gtc" => Ok(TimeInForce::GoodTillCancel),
+        "ioc" => Ok(TimeInForce::ImmediateOrCancel),
+        "fok" => Ok(TimeInForce::FillOrKill),
+        _ => anyhow::bail!("Invalid time_in_force: {}", s),
+    }
+}
+
+fn format_time_in_force(time_in_force: TimeInForce) -> &'static str {
+    match time_in_force {
+        TimeInForce::GoodTillCancel => "gtc",
+        TimeInForce::ImmediateOrCancel => "ioc",
+        TimeInForce::FillOrKill => "fok",
+    }
+}
+
+fn parse_order_type(s: &str) -> Result<OrderType> {
+    match s {
+        "limit" => Ok(OrderType::Limit),
+        "market" => Ok(OrderType::Market),
+        _ => anyhow::bail!("Invalid order_type: {}", s),
+    }
+}
+
+fn format_order_type(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Limit => "limit",
+        OrderType::Market => "market",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use alloy::sol_types::SolValue;
+    use guests::MATCH_BENCH_ELF;
     use risc0_zkvm::{default_executor, ExecutorEnv};
 
     /// Benchmark test that measures ZKVM cycle count for order matching
@@ -437,68 +1263,84 @@ mod tests {
 
         let new_orders = vec![
             Order {
+                market: order_book_address,
                 side: Side::Buy,
                 price: 105,
                 quantity: 100,
                 owner: alice,
                 nonce: base_nonce,
                 expiry_batch: 100,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Buy,
                 price: 103,
                 quantity: 50,
                 owner: alice,
                 nonce: base_nonce + 1,
                 expiry_batch: 100,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Buy,
                 price: 100,
                 quantity: 200,
                 owner: alice,
                 nonce: base_nonce + 2,
                 expiry_batch: 50,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Sell,
                 price: 99,
                 quantity: 75,
                 owner: bob,
                 nonce: base_nonce + 3,
                 expiry_batch: 100,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Sell,
                 price: 101,
                 quantity: 150,
                 owner: bob,
                 nonce: base_nonce + 4,
                 expiry_batch: 100,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Sell,
                 price: 104,
                 quantity: 80,
                 owner: bob,
                 nonce: base_nonce + 5,
                 expiry_batch: 100,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Buy,
                 price: 102,
                 quantity: 60,
                 owner: alice,
                 nonce: base_nonce + 6,
                 expiry_batch: 100,
+                ..Default::default()
             },
             Order {
+                market: order_book_address,
                 side: Side::Sell,
                 price: 100,
                 quantity: 40,
                 owner: bob,
                 nonce: base_nonce + 7,
                 expiry_batch: 100,
+                ..Default::default()
             },
         ];
 
@@ -510,6 +1352,7 @@ mod tests {
             .chain_spec(&ETH_SEPOLIA_CHAIN_SPEC)
             .build()
             .await?;
+        let block_timestamp = evm_env.header().timestamp();
 
         // Preflight: query on-chain state
         let mut contract = Contract::preflight(order_book_address, &mut evm_env);
@@ -517,10 +1360,90 @@ mod tests {
             .call_builder(&IOrderBook::utxoMerkleRootCall {})
             .call()
             .await?;
+        let on_chain_leaf_count = contract
+            .call_builder(&IOrderBook::utxoLeafCountCall {})
+            .call()
+            .await?;
         let on_chain_batch_index = contract
             .call_builder(&IOrderBook::currentBatchIndexCall {})
             .call()
             .await?;
+        let self_trade_prevention: SelfTradePrevention = contract
+            .call_builder(&IOrderBook::selfTradePreventionCall {})
+            .call()
+            .await?
+            .try_into()
+            .map_err(anyhow::Error::msg)?;
+        let matching_engine: EngineKind = contract
+            .call_builder(&IOrderBook::matchingEngineCall {})
+            .call()
+            .await?
+            .try_into()
+            .map_err(anyhow::Error::msg)?;
+        let post_only_policy: PostOnlyPolicy = contract
+            .call_builder(&IOrderBook::postOnlyPolicyCall {})
+            .call()
+            .await?
+            .try_into()
+            .map_err(anyhow::Error::msg)?;
+        let last_trade_price = contract
+            .call_builder(&IOrderBook::lastTradePriceCall {})
+            .call()
+            .await?;
+        let fee_schedule = FeeSchedule {
+            maker_fee_bps: contract
+                .call_builder(&IOrderBook::makerFeeBpsCall {})
+                .call()
+                .await?,
+            taker_fee_bps: contract
+                .call_builder(&IOrderBook::takerFeeBpsCall {})
+                .call()
+                .await?,
+        };
+        let market_params = contract
+            .call_builder(&IOrderBook::marketParamsCall {})
+            .call()
+            .await?;
+        let market_params = MarketParams {
+            tick_size: market_params.tickSize,
+            lot_size: market_params.lotSize,
+            min_quantity: market_params.minQuantity,
+            min_notional: market_params.minNotional,
+            price_exponent: market_params.priceExponent,
+        };
+        let price_band = contract
+            .call_builder(&IOrderBook::priceBandCall {})
+            .call()
+            .await?;
+        let price_band = PriceBand {
+            band_bps: price_band.bandBps,
+            policy: price_band.policy.try_into().map_err(anyhow::Error::msg)?,
+        };
+        let oracle_address = contract
+            .call_builder(&IOrderBook::oracleCall {})
+            .call()
+            .await?;
+        let oracle_round = if oracle_address == Address::ZERO {
+            None
+        } else {
+            let round = Contract::preflight(oracle_address, &mut evm_env)
+                .call_builder(&IAggregatorV3::latestRoundDataCall {})
+                .call()
+                .await?;
+            let decimals = Contract::preflight(oracle_address, &mut evm_env)
+                .call_builder(&IAggregatorV3::decimalsCall {})
+                .call()
+                .await?;
+            OracleRound::from_answer(
+                round.roundId,
+                round.answer,
+                round.updatedAt,
+                decimals,
+                market_params.price_exponent,
+                block_timestamp,
+            )
+            .ok()
+        };
 
         println!("On-chain batch index: {}", on_chain_batch_index);
         println!(
@@ -528,14 +1451,31 @@ mod tests {
             hex::encode(on_chain_merkle_root)
         );
 
-        // Create batch input (no existing UTXOs for simplicity)
+        // Create batch input (a fresh market without UTXOs, for simplicity)
         let batch_input = BatchInput {
+            market: order_book_address,
             batch_index: on_chain_batch_index,
+            block_timestamp,
             utxo_merkle_root: on_chain_merkle_root,
+            utxo_leaf_count: on_chain_leaf_count,
+            append_proof: vec![],
             existing_utxos_with_proofs: vec![],
             new_orders,
+            cancellations: vec![],
+            amendments: vec![],
+            self_trade_prevention,
+            matching_engine,
+            post_only_policy,
+            last_trade_price,
+            oracle_round,
+            fee_schedule,
+            market_params,
+            price_band,
         };
-        let input_bytes = batch_input.to_sol().abi_encode();
+        let input_bytes = SolMultiBatchInput {
+            markets: vec![batch_input.to_sol()],
+        }
+        .abi_encode();
 
         // Convert Steel environment to input
         let evm_input = evm_env.into_input().await?;
@@ -543,7 +1483,6 @@ mod tests {
         // Build executor environment
         let env = ExecutorEnv::builder()
             .write(&evm_input)?
-            .write(&order_book_address)?
             .write(&input_bytes)?
             .build()?;
 
@@ -563,4 +1502,119 @@ mod tests {
 
         Ok(())
     }
+
+    /// Benchmark that measures ZKVM cycle count for matching against a deep book.
+    /// Each run rests the given number of UTXOs, split between bids and asks over
+    /// 100 price levels per side, and crosses them with 8 new orders that sweep the
+    /// best levels. Runs the matching-only guest, so no chain state is needed.
+    ///
+    /// Run with: cargo test --release benchmark_resting_book -- --ignored --nocapture
+    #[test]
+    #[ignore = "executes the zkVM against books of thousands of resting UTXOs"]
+    fn benchmark_resting_book() -> Result<()> {
+        let market = Address::repeat_byte(0x0b);
+        let buyer = Address::repeat_byte(0xaa);
+        let seller = Address::repeat_byte(0xbb);
+
+        println!("\n=== Resting Book Benchmark ===");
+        let mut cycles_per_utxo = Vec::new();
+        for resting in [1_000u64, 2_000, 4_000] {
+            let existing_utxos: Vec<Utxo> = (0..resting)
+                .map(|i| {
+                    let level = (i / 2) % 100;
+                    let (side, price) = if i % 2 == 0 {
+                        (Side::Buy, 1_000 - level)
+                    } else {
+                        (Side::Sell, 1_001 + level)
+                    };
+                    Utxo::new(Order {
+                        market,
+                        side,
+                        price,
+                        quantity: 10,
+                        owner: Address::with_last_byte((i % 200 + 1) as u8),
+                        nonce: i,
+                        expiry_batch: 100,
+                        ..Default::default()
+                    })
+                })
+                .collect();
+
+            let ids: Vec<FixedBytes<32>> = existing_utxos.iter().map(|utxo| utxo.id).collect();
+            let tree = UtxoTree::from_leaves(&ids);
+            let existing_utxos_with_proofs = existing_utxos
+                .into_iter()
+                .enumerate()
+                .map(|(i, utxo)| UtxoWithProof {
+                    utxo,
+                    proof_hashes: tree.proof(i as u64),
+                    leaf_index: i,
+                })
+                .collect();
+
+            let new_orders = (0..8)
+                .map(|i| {
+                    let (side, price, owner) = if i % 2 == 0 {
+                        (Side::Buy, 1_003, buyer)
+                    } else {
+                        (Side::Sell, 998, seller)
+                    };
+                    Order {
+                        market,
+                        side,
+                        price,
+                        quantity: 200,
+                        owner,
+                        nonce: resting + i,
+                        expiry_batch: 100,
+                        ..Default::default()
+                    }
+                })
+                .collect();
+
+            let batch_input = BatchInput {
+                market,
+                batch_index: 1,
+                utxo_merkle_root: tree.root(),
+                utxo_leaf_count: tree.leaf_count(),
+                append_proof: tree.proof(tree.leaf_count()),
+                existing_utxos_with_proofs,
+                new_orders,
+                ..Default::default()
+            };
+            let input_bytes = SolMultiBatchInput {
+                markets: vec![batch_input.to_sol()],
+            }
+            .abi_encode();
+
+            let env = ExecutorEnv::builder().write(&input_bytes)?.build()?;
+            let session = default_executor().execute(env, MATCH_BENCH_ELF)?;
+            let fills: u64 = session.journal.decode()?;
+
+            let total_cycles = session.cycles();
+            println!(
+                "Resting UTXOs: {}, fills: {}, total cycles: {}, cycles per resting UTXO: {}",
+                resting,
+                fills,
+                total_cycles,
+                total_cycles / resting
+            );
+            assert!(fills > 0, "the crossing orders should trade");
+            cycles_per_utxo.push(total_cycles / resting);
+        }
+
+        // Near-linear scaling: proofs grow by a level per doubling, so allow for that
+        // but not for a cost per UTXO that grows with the book
+        let baseline = cycles_per_utxo[0];
+        for &cycles in &cycles_per_utxo[1..] {
+            assert!(
+                cycles <= baseline * 3 / 2,
+                "cycles per resting UTXO grew from {} to {}",
+                baseline,
+                cycles
+            );
+        }
+
+        Ok(())
+    }
 }
@@ -45,6 +45,18 @@ contract Deploy is Script {
         // Optional: use existing tokens or deploy new ones
         bool deployNewTokens = vm.envOr("DEPLOY_NEW_TOKENS", true);
 
+        // Market default self-trade prevention mode (1 = cancel newest)
+        uint8 selfTradePrevention = uint8(vm.envOr("SELF_TRADE_PREVENTION", uint256(1)));
+
+        // Market matching engine (0 = price-time, 1 = uniform-price batch auction, 2 = pro-rata)
+        uint8 matchingEngine = uint8(vm.envOr("MATCHING_ENGINE", uint256(0)));
+
+        // Handling of crossing post-only orders (0 = reject, 1 = reprice one tick away)
+        uint8 postOnlyPolicy = uint8(vm.envOr("POST_ONLY_POLICY", uint256(0)));
+
+        // Chainlink price feed for pegged orders (address(0) disables pegging)
+        address oracle = vm.envOr("ORACLE_ADDRESS", address(0));
+
         vm.startBroadcast(deployerKey);
 
         MockERC20 assetA;
@@ -76,9 +88,24 @@ contract Deploy is Script {
         }
 
         // Deploy OrderBook
+        // Maker and taker fees in basis points (default none) are read inline to keep the stack shallow;
+        // FEE_RECIPIENT is required when either is set
         IRiscZeroVerifier verifier = IRiscZeroVerifier(verifierAddress);
-        OrderBook orderBook =
-            new OrderBook(verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)));
+        OrderBook orderBook = new OrderBook(
+            verifier,
+            boundlessMarket,
+            imageId,
+            IERC20(address(assetA)),
+            IERC20(address(assetB)),
+            selfTradePrevention,
+            matchingEngine,
+            postOnlyPolicy,
+            oracle,
+            uint16(vm.envOr("MAKER_FEE_BPS", uint256(0))),
+            uint16(vm.envOr("TAKER_FEE_BPS", uint256(0))),
+            vm.envOr("FEE_RECIPIENT", address(0)),
+            _marketParams()
+        );
 
         console2.log("Deployed OrderBook to", address(orderBook));
         console2.log("  - AssetA:", address(assetA));
@@ -101,6 +128,19 @@ contract Deploy is Script {
         console2.log("BOB:", bob);
     }
 
+    /// @notice Order size parameters and price exponent from env, each 0 (no constraint, integer prices) by default
+    function _marketParams() internal view returns (OrderBook.MarketParams memory) {
+        return OrderBook.MarketParams({
+            tickSize: uint64(vm.envOr("TICK_SIZE", uint256(0))),
+            lotSize: uint64(vm.envOr("LOT_SIZE", uint256(0))),
+            minQuantity: uint64(vm.envOr("MIN_QUANTITY", uint256(0))),
+            minNotional: uint128(vm.envOr("MIN_NOTIONAL", uint256(0))),
+            priceExponent: int8(vm.envOr("PRICE_EXPONENT", int256(0))),
+            priceBandBps: uint16(vm.envOr("PRICE_BAND_BPS", uint256(0))),
+            priceBandPolicy: uint8(vm.envOr("PRICE_BAND_POLICY", uint256(0)))
+        });
+    }
+
     function _setupApprovals(address orderBook, address assetA, address assetB) internal {
         // Load test private keys from env
         uint256 aliceKey = vm.envUint("ALICE_PRIVATE_KEY");
@@ -168,7 +208,24 @@ contract DeployLocal is Script {
             mockBoundlessMarket,
             mockImageId,
             IERC20(address(assetA)),
-            IERC20(address(assetB))
+            IERC20(address(assetB)),
+            1, // cancel newest
+            0, // price-time
+            0, // reject crossing post-only orders
+            address(0), // no oracle
+            0, // no maker fee
+            0, // no taker fee
+            address(0), // no fee recipient
+            // any size, integer prices, no price band
+            OrderBook.MarketParams({
+                tickSize: 0,
+                lotSize: 0,
+                minQuantity: 0,
+                minNotional: 0,
+                priceExponent: 0,
+                priceBandBps: 0,
+                priceBandPolicy: 0
+            })
         );
 
         console2.log("Deployed OrderBook to", address(orderBook));
@@ -8,9 +8,12 @@ interface IOrderBook {
         bytes32 indexed takerUtxoId,
         uint64 price,
         uint64 quantity,
+        uint256 notional,
         address maker,
         address taker,
-        bool makerIsSeller
+        bool makerIsSeller,
+        uint256 makerFee,
+        uint256 takerFee
     );
 
     /// @notice Event emitted when a new UTXO is created
@@ -19,6 +22,26 @@ interface IOrderBook {
     /// @notice Event emitted when a UTXO is consumed
     event UTXOConsumed(bytes32 indexed utxoId);
 
+    /// @notice Event emitted when an order's remaining quantity is cancelled instead of resting
+    event OrderCancelled(bytes32 indexed utxoId, address indexed owner, uint64 quantity, uint8 reason);
+
+    /// @notice Event emitted with the outcome of a new post-only order (0 = posted, 1 = repriced, 2 = rejected)
+    event PostOnlyResolved(
+        bytes32 indexed utxoId, bytes32 indexed newUtxoId, address indexed owner, uint8 outcome, uint64 price
+    );
+
+    /// @notice Event emitted when an owner amends a resting order, linking it to its replacement
+    event OrderAmended(bytes32 indexed oldUtxoId, bytes32 indexed newUtxoId, address indexed owner, bool priorityKept);
+
+    /// @notice Event emitted when a cancellation (kind 0) or amendment (kind 1) is dropped, with the reason
+    event IntentRejected(bytes32 indexed utxoId, uint8 kind, uint8 reason);
+
+    /// @notice Event emitted with the oracle round pegged orders in a batch were priced from
+    event OraclePriceUsed(uint64 indexed batchIndex, uint80 roundId, uint64 price);
+
+    /// @notice Event emitted when matching was halted for a fill outside the price band around referencePrice
+    event TradingHalted(uint64 indexed batchIndex, uint64 referencePrice);
+
     /// @notice Event emitted when a batch is executed
     event BatchExecuted(uint64 indexed batchIndex, uint256 fillCount);
 
@@ -28,9 +51,47 @@ interface IOrderBook {
     /// @notice Get the current UTXO Merkle root
     function utxoMerkleRoot() external view returns (bytes32);
 
+    /// @notice Get the number of leaves of the UTXO tree, consumed ones included
+    function utxoLeafCount() external view returns (uint64);
+
+    /// @notice Get the price of the last fill (0 before the first trade)
+    function lastTradePrice() external view returns (uint64);
+
     /// @notice Get the AssetA token address
     function assetA() external view returns (address);
 
     /// @notice Get the AssetB token address
     function assetB() external view returns (address);
+
+    /// @notice Get the market default self-trade prevention mode
+    function selfTradePrevention() external view returns (uint8);
+
+    /// @notice Get the market matching engine (0 = price-time, 1 = batch auction, 2 = pro-rata)
+    function matchingEngine() external view returns (uint8);
+
+    /// @notice Get the market policy for crossing post-only orders (0 = reject, 1 = reprice)
+    function postOnlyPolicy() external view returns (uint8);
+
+    /// @notice Get the Chainlink price feed pegged orders are priced from (address(0) for none)
+    function oracle() external view returns (address);
+
+    /// @notice Get the maker fee in basis points of the notional
+    function makerFeeBps() external view returns (uint16);
+
+    /// @notice Get the taker fee in basis points of the notional
+    function takerFeeBps() external view returns (uint16);
+
+    /// @notice Get the address receiving fees in AssetB
+    function feeRecipient() external view returns (address);
+
+    /// @notice Get the order size parameters new orders must conform to (0 = no constraint) and the
+    ///         decimal exponent of every price
+    function marketParams()
+        external
+        view
+        returns (uint64 tickSize, uint64 lotSize, uint64 minQuantity, uint128 minNotional, int8 priceExponent);
+
+    /// @notice Get the price band around the last trade price in basis points (0 = no band) and what
+    ///         happens to prices outside it (0 = reject orders, 1 = halt matching)
+    function priceBand() external view returns (uint16 bandBps, uint8 policy);
 }
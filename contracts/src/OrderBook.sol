@@ -20,48 +20,231 @@ contract OrderBook is IOrderBook, BoundlessMarketCallback {
     /// @notice ERC20 token B (quote token)
     IERC20 public immutable ASSET_B;
 
+    /// @notice Market default self-trade prevention mode
+    /// @dev 1 = cancel newest, 2 = cancel oldest, 3 = cancel both, 4 = decrement and cancel
+    uint8 public immutable SELF_TRADE_PREVENTION;
+
+    /// @notice Matching engine the guest must clear this market with
+    /// @dev 0 = price-time, 1 = uniform-price batch auction, 2 = pro-rata
+    uint8 public immutable MATCHING_ENGINE;
+
+    /// @notice What the guest does with post-only orders that would take liquidity
+    /// @dev 0 = reject, 1 = reprice one tick behind the best opposite price
+    uint8 public immutable POST_ONLY_POLICY;
+
+    /// @notice Chainlink price feed pegged orders are priced from (address(0) for none)
+    /// @dev The guest reads its latestRoundData via Steel
+    address public immutable ORACLE;
+
+    /// @notice Largest fee rate in basis points: the whole notional
+    uint16 public constant MAX_FEE_BPS = 10_000;
+
+    /// @notice Fee charged to the maker of a fill, in basis points of the notional
+    /// @dev The guest reads it via Steel and commits the fee amounts in each fill
+    uint16 public immutable MAKER_FEE_BPS;
+
+    /// @notice Fee charged to the taker of a fill, in basis points of the notional
+    uint16 public immutable TAKER_FEE_BPS;
+
+    /// @notice Address receiving maker and taker fees in AssetB
+    address public immutable FEE_RECIPIENT;
+
+    /// @notice Prices of new orders must be a multiple of this (0 = any)
+    /// @dev The guest reads the market parameters via Steel and rejects non-conforming orders
+    uint64 public immutable TICK_SIZE;
+
+    /// @notice Quantities of new orders must be a multiple of this (0 = any)
+    uint64 public immutable LOT_SIZE;
+
+    /// @notice Smallest total quantity of a new order
+    uint64 public immutable MIN_QUANTITY;
+
+    /// @notice Smallest value of a new order in AssetB (price times quantity)
+    uint128 public immutable MIN_NOTIONAL;
+
+    /// @notice Largest magnitude of the price exponent
+    int8 public constant MAX_PRICE_EXPONENT = 18;
+
+    /// @notice Decimal exponent of every price: a price p is p * 10^PRICE_EXPONENT base units of AssetB
+    ///         per base unit of AssetA
+    int8 public immutable PRICE_EXPONENT;
+
+    /// @notice Half-width of the price band around the previous batch's last trade, in basis points
+    ///         (0 = no band)
+    /// @dev The guest reads it via Steel together with `lastTradePrice`
+    uint16 public immutable PRICE_BAND_BPS;
+
+    /// @notice What the guest does about prices outside the band (0 = reject orders, 1 = halt matching)
+    uint8 public immutable PRICE_BAND_POLICY;
+
     /// @notice Current batch index (incremented after each batch execution)
     uint64 public currentBatchIndex;
 
     /// @notice Merkle root of valid Order UTXOs
     bytes32 public utxoMerkleRoot;
 
+    /// @notice Leaves of the UTXO tree, consumed ones included: a UTXO keeps its leaf, and new
+    ///         UTXOs are appended after the last one
+    /// @dev The guest reads it via Steel together with `utxoMerkleRoot`
+    uint64 public utxoLeafCount;
+
+    /// @notice Price of the last fill of the latest batch that traded (0 before the first trade)
+    /// @dev The guest triggers stop orders against it
+    uint64 public lastTradePrice;
+
     /// @notice Mapping to track verified proofs.
     /// @dev This is used to prevent a callback is called more than once with the same proof.
     mapping(bytes32 => bool) public verified;
 
     error AlreadyVerified();
+    error InvalidSelfTradePrevention();
+    error InvalidMatchingEngine();
+    error InvalidPostOnlyPolicy();
+    error InvalidFee();
+    error InvalidPriceExponent();
+    error InvalidPriceBand();
+
+    /// @notice Order size parameters (0 = no constraint), price exponent and price band of a market
+    struct MarketParams {
+        uint64 tickSize;
+        uint64 lotSize;
+        uint64 minQuantity;
+        uint128 minNotional;
+        int8 priceExponent;
+        uint16 priceBandBps;
+        uint8 priceBandPolicy;
+    }
 
     /// @notice Fill data struct from journal
     struct FillData {
         bytes32 makerUtxoId;
         bytes32 takerUtxoId;
         uint64 price;
+        int8 priceExponent;
         uint64 quantity;
+        uint256 notional; // AssetB paid, computed by the guest from the fixed-point price
         address maker;
         address taker;
         bool makerIsSeller;
+        uint256 makerFee;
+        uint256 takerFee;
     }
 
-    /// @notice UTXO struct from journal
-    struct UtxoData {
-        bytes32 id;
+    /// @notice Order struct from journal
+    struct OrderData {
+        address market;
         uint8 side; // 0 = Buy, 1 = Sell
         uint64 price;
+        int8 priceExponent;
         uint64 quantity;
+        uint128 quoteQuantity; // budget in AssetB for a quote buy, 0 = quantity in AssetA
         address owner;
+        address[] counterparties; // owners the order may trade with, empty = anyone
         uint64 nonce;
         uint64 expiryBatch;
+        uint64 expiryTimestamp; // 0 = good till batch only
+        uint8 selfTradePrevention; // 0 = market default
+        uint8 timeInForce; // 0 = GTC, 1 = IOC, 2 = FOK
+        bool postOnly;
+        uint8 orderType; // 0 = limit, 1 = market
+        uint64 minFillQuantity; // 0 = no minimum
+        bool allOrNone;
+        uint64 hiddenQuantity; // always 0 in the journal, the reserve stays private
+        uint64 displayQuantity; // always 0 in the journal
+        uint64 triggerPrice; // 0 = not a stop order
+        bool triggered;
+        bool pegged;
+        int64 pegOffset; // pegged price = oracle price + offset
+        bytes32 salt; // always 0 in the journal, blinds the UTXO ID
     }
 
-    /// @notice Journal struct from ZKVM (includes Steel commitment)
-    struct Journal {
-        Steel.Commitment steelCommitment;
+    /// @notice UTXO struct from journal
+    struct UtxoData {
+        bytes32 id;
+        OrderData order;
+        bytes32 parentUtxoId; // UTXO this one replaces, zero for an order's first UTXO
+        uint64 originalQuantity; // 0 while an iceberg reserve is left, and for a quote buy
+        uint128 originalQuoteQuantity; // budget a quote buy was placed with, 0 for any other order
+        uint64 filledQuantity; // across all batches, in AssetA
+    }
+
+    /// @notice Cancelled order struct from journal
+    struct CancelledOrderData {
+        bytes32 utxoId;
+        address owner;
+        uint64 quantity;
+        // 0 = self-trade prevention, 1 = IOC remainder, 2 = FOK not filled, 3 = post-only, 4 = market remainder,
+        // 5 = owner cancellation, 6 = off tick, 7 = off lot, 8 = below min quantity, 9 = below min notional,
+        // 10 = price exponent other than the market's
+        // 11 = limit price outside the price band
+        uint8 reason;
+    }
+
+    /// @notice Post-only result struct from journal
+    struct PostOnlyResultData {
+        bytes32 utxoId; // as submitted
+        bytes32 newUtxoId; // repriced UTXO, the submitted ID if posted, zero if rejected
+        address owner;
+        uint8 outcome; // 0 = posted, 1 = repriced, 2 = rejected
+        uint64 price;
+    }
+
+    /// @notice Amended order struct from journal
+    struct AmendedOrderData {
+        bytes32 oldUtxoId;
+        bytes32 newUtxoId;
+        address owner;
+        bool priorityKept;
+    }
+
+    /// @notice Cancellation or amendment the guest dropped
+    struct RejectedIntentData {
+        bytes32 utxoId;
+        uint8 kind; // 0 = cancellation, 1 = amendment
+        // 0 = not signed by the owner, 1 = no open UTXO with this ID, 2 = amendment changes nothing,
+        // sets a zero quantity or amends a quote buy, 3 = replacement off the market parameters,
+        // 4 = replacement price outside the price band
+        uint8 reason;
+    }
+
+    /// @notice Oracle round struct from journal
+    struct OracleRoundData {
+        uint80 roundId; // 0 = no oracle
+        uint64 price;
+    }
+
+    /// @notice Net balance change of one account over all fills of a batch
+    struct NetTransferData {
+        address account;
+        int256 assetA; // received if positive, paid if negative
+        int256 assetB; // net of fees
+    }
+
+    /// @notice Batch output of one market in the journal
+    struct BatchData {
+        address market; // OrderBook contract the batch settles in
         uint64 batchIndex;
+        uint8 matchingEngine;
         FillData[] fills;
-        UtxoData[] newUtxos;
+        UtxoData[] newUtxos; // created this batch, untouched UTXOs are left out
         bytes32[] consumedUtxoIds;
+        CancelledOrderData[] cancelledOrders;
+        PostOnlyResultData[] postOnlyResults;
         bytes32 newUtxoMerkleRoot;
+        uint64 newUtxoLeafCount;
+        uint64 lastTradePrice;
+        OracleRoundData oracleRound;
+        AmendedOrderData[] amendedOrders;
+        bool halted; // matching halted for a fill outside the price band
+        NetTransferData[] netTransfers;
+        RejectedIntentData[] rejectedIntents;
+    }
+
+    /// @notice Journal struct from ZKVM: the Steel commitment and the batch of every market cleared
+    ///         in one proof
+    struct Journal {
+        Steel.Commitment steelCommitment;
+        BatchData[] markets;
     }
 
     /// @notice Constructor
@@ -70,11 +253,67 @@ contract OrderBook is IOrderBook, BoundlessMarketCallback {
     /// @param imageId Image ID of the order book guest program
     /// @param _assetA ERC20 token A (base token)
     /// @param _assetB ERC20 token B (quote token)
-    constructor(IRiscZeroVerifier verifier, address boundlessMarket, bytes32 imageId, IERC20 _assetA, IERC20 _assetB)
-        BoundlessMarketCallback(verifier, boundlessMarket, imageId)
-    {
+    /// @param _selfTradePrevention Market default self-trade prevention mode
+    /// @param _matchingEngine Market matching engine
+    /// @param _postOnlyPolicy Market policy for crossing post-only orders
+    /// @param _oracle Chainlink price feed for pegged orders (address(0) for none)
+    /// @param _makerFeeBps Maker fee in basis points
+    /// @param _takerFeeBps Taker fee in basis points
+    /// @param _feeRecipient Address receiving fees (may be address(0) only without fees)
+    /// @param _marketParams Tick size, lot size, minimum quantity, minimum notional, price exponent
+    ///        and price band
+    constructor(
+        IRiscZeroVerifier verifier,
+        address boundlessMarket,
+        bytes32 imageId,
+        IERC20 _assetA,
+        IERC20 _assetB,
+        uint8 _selfTradePrevention,
+        uint8 _matchingEngine,
+        uint8 _postOnlyPolicy,
+        address _oracle,
+        uint16 _makerFeeBps,
+        uint16 _takerFeeBps,
+        address _feeRecipient,
+        MarketParams memory _marketParams
+    ) BoundlessMarketCallback(verifier, boundlessMarket, imageId) {
+        if (_selfTradePrevention == 0 || _selfTradePrevention > 4) {
+            revert InvalidSelfTradePrevention();
+        }
+        if (_matchingEngine > 2) {
+            revert InvalidMatchingEngine();
+        }
+        if (_postOnlyPolicy > 1) {
+            revert InvalidPostOnlyPolicy();
+        }
+        if (
+            _makerFeeBps > MAX_FEE_BPS || _takerFeeBps > MAX_FEE_BPS
+                || (_feeRecipient == address(0) && (_makerFeeBps != 0 || _takerFeeBps != 0))
+        ) {
+            revert InvalidFee();
+        }
+        if (_marketParams.priceExponent > MAX_PRICE_EXPONENT || _marketParams.priceExponent < -MAX_PRICE_EXPONENT) {
+            revert InvalidPriceExponent();
+        }
+        if (_marketParams.priceBandBps > MAX_FEE_BPS || _marketParams.priceBandPolicy > 1) {
+            revert InvalidPriceBand();
+        }
         ASSET_A = _assetA;
         ASSET_B = _assetB;
+        SELF_TRADE_PREVENTION = _selfTradePrevention;
+        MATCHING_ENGINE = _matchingEngine;
+        POST_ONLY_POLICY = _postOnlyPolicy;
+        ORACLE = _oracle;
+        MAKER_FEE_BPS = _makerFeeBps;
+        TAKER_FEE_BPS = _takerFeeBps;
+        FEE_RECIPIENT = _feeRecipient;
+        TICK_SIZE = _marketParams.tickSize;
+        LOT_SIZE = _marketParams.lotSize;
+        MIN_QUANTITY = _marketParams.minQuantity;
+        MIN_NOTIONAL = _marketParams.minNotional;
+        PRICE_EXPONENT = _marketParams.priceExponent;
+        PRICE_BAND_BPS = _marketParams.priceBandBps;
+        PRICE_BAND_POLICY = _marketParams.priceBandPolicy;
         currentBatchIndex = 0;
     }
 
@@ -102,59 +341,147 @@ contract OrderBook is IOrderBook, BoundlessMarketCallback {
         // Validate the Steel commitment to ensure the proof is based on valid chain state
         require(Steel.validateCommitment(journal.steelCommitment), "OrderBook: invalid Steel commitment");
 
+        // One proof may clear many markets; each settles its own batch and ignores the others.
+        // The same journal and seal can be delivered to every market in it.
+        for (uint256 i = 0; i < journal.markets.length; i++) {
+            if (journal.markets[i].market == address(this)) {
+                _settleBatch(journal.markets[i]);
+                return;
+            }
+        }
+        revert("OrderBook: no batch for this market");
+    }
+
+    /// @notice Settle this market's batch from a verified journal
+    /// @param batch The batch output of this market
+    function _settleBatch(BatchData memory batch) internal {
         // Verify batch index matches (replay protection)
-        require(journal.batchIndex == currentBatchIndex, "OrderBook: invalid batch index");
+        require(batch.batchIndex == currentBatchIndex, "OrderBook: invalid batch index");
+
+        // Verify the batch was cleared with this market's matching engine
+        require(batch.matchingEngine == MATCHING_ENGINE, "OrderBook: invalid matching engine");
 
         // Emit events for consumed UTXOs
-        for (uint256 i = 0; i < journal.consumedUtxoIds.length; i++) {
-            emit UTXOConsumed(journal.consumedUtxoIds[i]);
+        for (uint256 i = 0; i < batch.consumedUtxoIds.length; i++) {
+            emit UTXOConsumed(batch.consumedUtxoIds[i]);
+        }
+
+        // Emit events for cancelled orders
+        for (uint256 i = 0; i < batch.cancelledOrders.length; i++) {
+            CancelledOrderData memory cancelled = batch.cancelledOrders[i];
+            emit OrderCancelled(cancelled.utxoId, cancelled.owner, cancelled.quantity, cancelled.reason);
         }
 
-        // Process fills - execute ERC20 transfers
-        for (uint256 i = 0; i < journal.fills.length; i++) {
-            FillData memory fill = journal.fills[i];
-            _executeFill(fill);
+        // Emit events for post-only outcomes
+        for (uint256 i = 0; i < batch.postOnlyResults.length; i++) {
+            PostOnlyResultData memory result = batch.postOnlyResults[i];
+            emit PostOnlyResolved(result.utxoId, result.newUtxoId, result.owner, result.outcome, result.price);
         }
 
+        // Emit events linking amended orders to their replacements
+        for (uint256 i = 0; i < batch.amendedOrders.length; i++) {
+            AmendedOrderData memory amended = batch.amendedOrders[i];
+            emit OrderAmended(amended.oldUtxoId, amended.newUtxoId, amended.owner, amended.priorityKept);
+        }
+
+        // Emit events for dropped cancellations and amendments
+        for (uint256 i = 0; i < batch.rejectedIntents.length; i++) {
+            RejectedIntentData memory rejected = batch.rejectedIntents[i];
+            emit IntentRejected(rejected.utxoId, rejected.kind, rejected.reason);
+        }
+
+        // Emit fill events and total the fees; the tokens move once per account below
+        uint256 totalFee;
+        for (uint256 i = 0; i < batch.fills.length; i++) {
+            FillData memory fill = batch.fills[i];
+            totalFee += fill.makerFee + fill.takerFee;
+            _emitFill(fill);
+        }
+        _settleNetTransfers(batch.netTransfers, totalFee);
+
         // Emit events for new UTXOs
-        for (uint256 i = 0; i < journal.newUtxos.length; i++) {
-            emit UTXOCreated(journal.newUtxos[i].id);
+        for (uint256 i = 0; i < batch.newUtxos.length; i++) {
+            emit UTXOCreated(batch.newUtxos[i].id);
+        }
+
+        // Report the oracle round pegged orders were priced from
+        if (batch.oracleRound.roundId != 0) {
+            emit OraclePriceUsed(batch.batchIndex, batch.oracleRound.roundId, batch.oracleRound.price);
         }
 
         // Update UTXO Merkle root
-        utxoMerkleRoot = journal.newUtxoMerkleRoot;
+        utxoMerkleRoot = batch.newUtxoMerkleRoot;
+        utxoLeafCount = batch.newUtxoLeafCount;
+
+        if (batch.halted) {
+            emit TradingHalted(batch.batchIndex, lastTradePrice);
+        }
+
+        // Record the last trade price that stop orders trigger against and the next price band
+        // is centred on
+        lastTradePrice = batch.lastTradePrice;
 
         // Increment batch index
         currentBatchIndex++;
 
-        emit BatchExecuted(journal.batchIndex, journal.fills.length);
+        emit BatchExecuted(batch.batchIndex, batch.fills.length);
     }
 
-    /// @notice Execute a single fill - transfer tokens between maker and taker
-    /// @param fill The fill to execute
-    function _executeFill(FillData memory fill) internal {
-        // Calculate amounts
-        // price is in AssetB per AssetA
-        // quantity is amount of AssetA
-        uint256 assetAAmount = fill.quantity;
-        uint256 assetBAmount = uint256(fill.price) * uint256(fill.quantity);
+    /// @notice Settle the net balance changes of a batch. Every paying account is collected from
+    ///         before anyone is paid, so the contract never advances tokens of its own.
+    /// @dev Each side of a fill pays its own maker or taker fee in AssetB. The seller's fee is held
+    ///      back from the notional, so a seller never needs an AssetB allowance, and the buyer's is
+    ///      paid on top of it. The fees are what the accounts pay in excess of what they receive,
+    ///      and go to FEE_RECIPIENT. A list that does not balance, AssetA to zero and AssetB to the
+    ///      fees, is rejected rather than settled out of the contract's own tokens.
+    /// @param transfers Net change per account, computed by the guest from the fills
+    /// @param totalFee Sum of the maker and taker fees of the fills
+    function _settleNetTransfers(NetTransferData[] memory transfers, uint256 totalFee) internal {
+        int256 netA;
+        int256 netB = int256(totalFee);
+        for (uint256 i = 0; i < transfers.length; i++) {
+            netA += transfers[i].assetA;
+            netB += transfers[i].assetB;
+        }
+        require(netA == 0 && netB == 0, "OrderBook: unbalanced net transfers");
 
-        if (fill.makerIsSeller) {
-            // Maker is selling AssetA, Taker is buying AssetA
-            // Maker sends AssetA to Taker
-            // Taker sends AssetB to Maker
-            ASSET_A.safeTransferFrom(fill.maker, fill.taker, assetAAmount);
-            ASSET_B.safeTransferFrom(fill.taker, fill.maker, assetBAmount);
-        } else {
-            // Maker is buying AssetA, Taker is selling AssetA
-            // Taker sends AssetA to Maker
-            // Maker sends AssetB to Taker
-            ASSET_A.safeTransferFrom(fill.taker, fill.maker, assetAAmount);
-            ASSET_B.safeTransferFrom(fill.maker, fill.taker, assetBAmount);
+        for (uint256 i = 0; i < transfers.length; i++) {
+            NetTransferData memory transfer = transfers[i];
+            if (transfer.assetA < 0) {
+                ASSET_A.safeTransferFrom(transfer.account, address(this), uint256(-transfer.assetA));
+            }
+            if (transfer.assetB < 0) {
+                ASSET_B.safeTransferFrom(transfer.account, address(this), uint256(-transfer.assetB));
+            }
+        }
+        for (uint256 i = 0; i < transfers.length; i++) {
+            NetTransferData memory transfer = transfers[i];
+            if (transfer.assetA > 0) {
+                ASSET_A.safeTransfer(transfer.account, uint256(transfer.assetA));
+            }
+            if (transfer.assetB > 0) {
+                ASSET_B.safeTransfer(transfer.account, uint256(transfer.assetB));
+            }
         }
+        if (totalFee > 0) {
+            ASSET_B.safeTransfer(FEE_RECIPIENT, totalFee);
+        }
+    }
 
+    /// @notice Emit the event of a single fill (its tokens are settled with the net transfers)
+    /// @param fill The fill to report
+    function _emitFill(FillData memory fill) internal {
         emit Fill(
-            fill.makerUtxoId, fill.takerUtxoId, fill.price, fill.quantity, fill.maker, fill.taker, fill.makerIsSeller
+            fill.makerUtxoId,
+            fill.takerUtxoId,
+            fill.price,
+            fill.quantity,
+            fill.notional,
+            fill.maker,
+            fill.taker,
+            fill.makerIsSeller,
+            fill.makerFee,
+            fill.takerFee
         );
     }
 
@@ -167,4 +494,53 @@ contract OrderBook is IOrderBook, BoundlessMarketCallback {
     function assetB() external view returns (address) {
         return address(ASSET_B);
     }
+
+    /// @inheritdoc IOrderBook
+    function selfTradePrevention() external view returns (uint8) {
+        return SELF_TRADE_PREVENTION;
+    }
+
+    /// @inheritdoc IOrderBook
+    function matchingEngine() external view returns (uint8) {
+        return MATCHING_ENGINE;
+    }
+
+    /// @inheritdoc IOrderBook
+    function postOnlyPolicy() external view returns (uint8) {
+        return POST_ONLY_POLICY;
+    }
+
+    /// @inheritdoc IOrderBook
+    function oracle() external view returns (address) {
+        return ORACLE;
+    }
+
+    /// @inheritdoc IOrderBook
+    function makerFeeBps() external view returns (uint16) {
+        return MAKER_FEE_BPS;
+    }
+
+    /// @inheritdoc IOrderBook
+    function takerFeeBps() external view returns (uint16) {
+        return TAKER_FEE_BPS;
+    }
+
+    /// @inheritdoc IOrderBook
+    function feeRecipient() external view returns (address) {
+        return FEE_RECIPIENT;
+    }
+
+    /// @inheritdoc IOrderBook
+    function marketParams()
+        external
+        view
+        returns (uint64 tickSize, uint64 lotSize, uint64 minQuantity, uint128 minNotional, int8 priceExponent)
+    {
+        return (TICK_SIZE, LOT_SIZE, MIN_QUANTITY, MIN_NOTIONAL, PRICE_EXPONENT);
+    }
+
+    /// @inheritdoc IOrderBook
+    function priceBand() external view returns (uint16 bandBps, uint8 policy) {
+        return (PRICE_BAND_BPS, PRICE_BAND_POLICY);
+    }
 }
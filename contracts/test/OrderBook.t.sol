@@ -4,8 +4,10 @@ pragma solidity ^0.8.26;
 import {Test} from "forge-std/Test.sol";
 import {RiscZeroCheats} from "risc0/test/RiscZeroCheats.sol";
 import {RiscZeroMockVerifier} from "risc0/test/RiscZeroMockVerifier.sol";
+import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
 import {IERC20} from "openzeppelin/contracts/token/ERC20/IERC20.sol";
 import {ERC20} from "openzeppelin/contracts/token/ERC20/ERC20.sol";
+import {Steel} from "steel/Steel.sol";
 import {OrderBook} from "../src/OrderBook.sol";
 
 /// @notice Simple mock ERC20 for testing
@@ -17,6 +19,45 @@ contract MockERC20 is ERC20 {
     }
 }
 
+/// @notice OrderBook with its settlement internals exposed for testing
+contract OrderBookHarness is OrderBook {
+    constructor(
+        IRiscZeroVerifier verifier,
+        address boundlessMarket,
+        bytes32 imageId,
+        IERC20 _assetA,
+        IERC20 _assetB,
+        uint16 _makerFeeBps,
+        uint16 _takerFeeBps,
+        address _feeRecipient,
+        MarketParams memory _marketParams
+    )
+        OrderBook(
+            verifier,
+            boundlessMarket,
+            imageId,
+            _assetA,
+            _assetB,
+            1,
+            0,
+            0,
+            address(0),
+            _makerFeeBps,
+            _takerFeeBps,
+            _feeRecipient,
+            _marketParams
+        )
+    {}
+
+    function settleNetTransfers(NetTransferData[] memory transfers, uint256 totalFee) external {
+        _settleNetTransfers(transfers, totalFee);
+    }
+
+    function settleBatch(BatchData memory batch) external {
+        _settleBatch(batch);
+    }
+}
+
 contract OrderBookTest is RiscZeroCheats, Test {
     OrderBook public orderBook;
     RiscZeroMockVerifier public verifier;
@@ -24,6 +65,7 @@ contract OrderBookTest is RiscZeroCheats, Test {
     MockERC20 public assetB;
     address public boundlessMarket;
     bytes32 public imageId;
+    OrderBook.MarketParams public noMarketParams;
 
     function setUp() public {
         verifier = new RiscZeroMockVerifier(0);
@@ -33,13 +75,353 @@ contract OrderBookTest is RiscZeroCheats, Test {
         assetA = new MockERC20("Asset A", "ASTA");
         assetB = new MockERC20("Asset B", "ASTB");
 
-        orderBook = new OrderBook(verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)));
+        orderBook =
+            new OrderBook(verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)), 1, 0, 0, address(0), 0, 0, address(0), noMarketParams);
     }
 
     function test_InitialState() public view {
         assertEq(orderBook.currentBatchIndex(), 0);
         assertEq(orderBook.utxoMerkleRoot(), bytes32(0));
+        assertEq(orderBook.utxoLeafCount(), 0);
+        assertEq(orderBook.lastTradePrice(), 0);
+        assertEq(orderBook.oracle(), address(0));
         assertEq(orderBook.assetA(), address(assetA));
         assertEq(orderBook.assetB(), address(assetB));
+        assertEq(orderBook.selfTradePrevention(), 1);
+        assertEq(orderBook.matchingEngine(), 0);
+        assertEq(orderBook.postOnlyPolicy(), 0);
+        assertEq(orderBook.makerFeeBps(), 0);
+        assertEq(orderBook.takerFeeBps(), 0);
+        assertEq(orderBook.feeRecipient(), address(0));
+        (uint64 tickSize, uint64 lotSize, uint64 minQuantity, uint128 minNotional, int8 priceExponent) =
+            orderBook.marketParams();
+        assertEq(tickSize, 0);
+        assertEq(lotSize, 0);
+        assertEq(minQuantity, 0);
+        assertEq(minNotional, 0);
+        assertEq(priceExponent, 0);
+        (uint16 bandBps, uint8 policy) = orderBook.priceBand();
+        assertEq(bandBps, 0);
+        assertEq(policy, 0);
+    }
+
+    function test_MarketParams() public {
+        OrderBook market = new OrderBook(
+            verifier,
+            boundlessMarket,
+            imageId,
+            IERC20(address(assetA)),
+            IERC20(address(assetB)),
+            1,
+            0,
+            0,
+            address(0),
+            0,
+            0,
+            address(0),
+            OrderBook.MarketParams({
+                tickSize: 5,
+                lotSize: 10,
+                minQuantity: 20,
+                minNotional: 5_000,
+                priceExponent: -6,
+                priceBandBps: 1_000,
+                priceBandPolicy: 1
+            })
+        );
+        (uint64 tickSize, uint64 lotSize, uint64 minQuantity, uint128 minNotional, int8 priceExponent) =
+            market.marketParams();
+        assertEq(tickSize, 5);
+        assertEq(lotSize, 10);
+        assertEq(minQuantity, 20);
+        assertEq(minNotional, 5_000);
+        assertEq(priceExponent, -6);
+        (uint16 bandBps, uint8 policy) = market.priceBand();
+        assertEq(bandBps, 1_000);
+        assertEq(policy, 1);
+    }
+
+    function test_RevertWhen_InvalidPriceExponent() public {
+        vm.expectRevert(OrderBook.InvalidPriceExponent.selector);
+        new OrderBook(
+            verifier,
+            boundlessMarket,
+            imageId,
+            IERC20(address(assetA)),
+            IERC20(address(assetB)),
+            1,
+            0,
+            0,
+            address(0),
+            0,
+            0,
+            address(0),
+            OrderBook.MarketParams({
+                tickSize: 0,
+                lotSize: 0,
+                minQuantity: 0,
+                minNotional: 0,
+                priceExponent: 19,
+                priceBandBps: 0,
+                priceBandPolicy: 0
+            })
+        );
+    }
+
+    function test_RevertWhen_InvalidPriceBand() public {
+        vm.expectRevert(OrderBook.InvalidPriceBand.selector);
+        new OrderBook(
+            verifier,
+            boundlessMarket,
+            imageId,
+            IERC20(address(assetA)),
+            IERC20(address(assetB)),
+            1,
+            0,
+            0,
+            address(0),
+            0,
+            0,
+            address(0),
+            OrderBook.MarketParams({
+                tickSize: 0,
+                lotSize: 0,
+                minQuantity: 0,
+                minNotional: 0,
+                priceExponent: 0,
+                priceBandBps: 1_000,
+                priceBandPolicy: 2
+            })
+        );
+    }
+
+    function test_RevertWhen_InvalidSelfTradePrevention() public {
+        vm.expectRevert(OrderBook.InvalidSelfTradePrevention.selector);
+        new OrderBook(verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)), 0, 0, 0, address(0), 0, 0, address(0), noMarketParams);
+    }
+
+    function test_RevertWhen_InvalidMatchingEngine() public {
+        vm.expectRevert(OrderBook.InvalidMatchingEngine.selector);
+        new OrderBook(verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)), 1, 3, 0, address(0), 0, 0, address(0), noMarketParams);
+    }
+
+    function test_RevertWhen_InvalidPostOnlyPolicy() public {
+        vm.expectRevert(OrderBook.InvalidPostOnlyPolicy.selector);
+        new OrderBook(verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)), 1, 0, 2, address(0), 0, 0, address(0), noMarketParams);
+    }
+
+    function test_RevertWhen_FeeAboveMax() public {
+        vm.expectRevert(OrderBook.InvalidFee.selector);
+        new OrderBook(
+            verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)), 1, 0, 0, address(0), 10_001, 0, address(1), noMarketParams
+        );
+    }
+
+    function test_RevertWhen_FeeWithoutRecipient() public {
+        vm.expectRevert(OrderBook.InvalidFee.selector);
+        new OrderBook(
+            verifier, boundlessMarket, imageId, IERC20(address(assetA)), IERC20(address(assetB)), 1, 0, 0, address(0), 0, 5, address(0), noMarketParams
+        );
+    }
+
+    function test_SettleNetTransfersCollectsPayersFirst() public {
+        OrderBookHarness market = _deployHarness(0, 0, address(0));
+        address alice = makeAddr("alice");
+        address bob = makeAddr("bob");
+        _fund(market, bob, 10, 0);
+        _fund(market, alice, 0, 1_000);
+
+        // ALICE receives first in the list, but the contract holds nothing until BOB pays
+        OrderBook.NetTransferData[] memory transfers = new OrderBook.NetTransferData[](2);
+        transfers[0] = OrderBook.NetTransferData({account: alice, assetA: 10, assetB: -1_000});
+        transfers[1] = OrderBook.NetTransferData({account: bob, assetA: -10, assetB: 1_000});
+        market.settleNetTransfers(transfers, 0);
+
+        assertEq(assetA.balanceOf(alice), 10);
+        assertEq(assetB.balanceOf(bob), 1_000);
+        assertEq(assetA.balanceOf(address(market)), 0);
+        assertEq(assetB.balanceOf(address(market)), 0);
+    }
+
+    function test_SettleNetTransfersSkipsZeroNetAccounts() public {
+        OrderBookHarness market = _deployHarness(0, 0, address(0));
+        address alice = makeAddr("alice");
+        address bob = makeAddr("bob");
+        address carol = makeAddr("carol");
+        _fund(market, bob, 10, 0);
+        _fund(market, alice, 0, 1_000);
+
+        // CAROL bought and sold the same amount and has no allowance, so nothing moves for her
+        OrderBook.NetTransferData[] memory transfers = new OrderBook.NetTransferData[](3);
+        transfers[0] = OrderBook.NetTransferData({account: carol, assetA: 0, assetB: 0});
+        transfers[1] = OrderBook.NetTransferData({account: alice, assetA: 10, assetB: -1_000});
+        transfers[2] = OrderBook.NetTransferData({account: bob, assetA: -10, assetB: 1_000});
+        market.settleNetTransfers(transfers, 0);
+
+        assertEq(assetA.balanceOf(carol), 0);
+        assertEq(assetB.balanceOf(carol), 0);
+        assertEq(assetA.balanceOf(alice), 10);
+        assertEq(assetB.balanceOf(bob), 1_000);
+    }
+
+    function test_RevertWhen_NetTransfersUnbalanced() public {
+        OrderBookHarness market = _deployHarness(0, 0, address(0));
+        address alice = makeAddr("alice");
+        address bob = makeAddr("bob");
+        _fund(market, bob, 10, 0);
+        _fund(market, alice, 0, 1_000);
+        // Tokens the contract holds must not cover a short list
+        assetA.mint(address(market), 1);
+
+        OrderBook.NetTransferData[] memory transfers = new OrderBook.NetTransferData[](2);
+        transfers[0] = OrderBook.NetTransferData({account: alice, assetA: 10, assetB: -1_000});
+        transfers[1] = OrderBook.NetTransferData({account: bob, assetA: -9, assetB: 1_000});
+        vm.expectRevert("OrderBook: unbalanced net transfers");
+        market.settleNetTransfers(transfers, 0);
+    }
+
+    function test_RevertWhen_SettledBatchUnbalanced() public {
+        OrderBookHarness market = _deployHarness(0, 0, address(0));
+        address alice = makeAddr("alice");
+        _fund(market, alice, 0, 1_000);
+
+        // AssetB paid with no one receiving it
+        OrderBook.BatchData memory batch;
+        batch.netTransfers = new OrderBook.NetTransferData[](1);
+        batch.netTransfers[0] = OrderBook.NetTransferData({account: alice, assetA: 0, assetB: -1_000});
+        vm.expectRevert("OrderBook: unbalanced net transfers");
+        market.settleBatch(batch);
+        assertEq(market.currentBatchIndex(), 0);
+    }
+
+    function test_SettleBatchPaysFeesToRecipient() public {
+        address feeRecipient = makeAddr("feeRecipient");
+        OrderBookHarness market = _deployHarness(10, 20, feeRecipient);
+        address alice = makeAddr("alice");
+        address bob = makeAddr("bob");
+        uint256 notional = 10_000;
+        uint256 makerFee = notional * market.makerFeeBps() / market.MAX_FEE_BPS();
+        uint256 takerFee = notional * market.takerFeeBps() / market.MAX_FEE_BPS();
+        _fund(market, alice, 100, 0);
+        _fund(market, bob, 0, notional + takerFee);
+
+        // ALICE's resting sell is taken by BOB's buy
+        OrderBook.BatchData memory batch;
+        batch.fills = new OrderBook.FillData[](1);
+        batch.fills[0] = OrderBook.FillData({
+            makerUtxoId: bytes32(uint256(1)),
+            takerUtxoId: bytes32(uint256(2)),
+            price: 100,
+            priceExponent: 0,
+            quantity: 100,
+            notional: notional,
+            maker: alice,
+            taker: bob,
+            makerIsSeller: true,
+            makerFee: makerFee,
+            takerFee: takerFee
+        });
+        // The seller's fee is held back from the notional and the buyer's is paid on top of it
+        batch.netTransfers = new OrderBook.NetTransferData[](2);
+        batch.netTransfers[0] =
+            OrderBook.NetTransferData({account: alice, assetA: -100, assetB: int256(notional - makerFee)});
+        batch.netTransfers[1] =
+            OrderBook.NetTransferData({account: bob, assetA: 100, assetB: -int256(notional + takerFee)});
+
+        // Net transfers plus fees conserve both assets
+        int256 netA;
+        int256 netB = int256(makerFee + takerFee);
+        for (uint256 i = 0; i < batch.netTransfers.length; i++) {
+            netA += batch.netTransfers[i].assetA;
+            netB += batch.netTransfers[i].assetB;
+        }
+        assertEq(netA, 0);
+        assertEq(netB, 0);
+
+        market.settleBatch(batch);
+
+        assertEq(makerFee, 10);
+        assertEq(takerFee, 20);
+        assertEq(assetB.balanceOf(feeRecipient), makerFee + takerFee);
+        assertEq(assetA.balanceOf(alice), 0);
+        assertEq(assetB.balanceOf(alice), notional - makerFee);
+        assertEq(assetA.balanceOf(bob), 100);
+        assertEq(assetB.balanceOf(bob), 0);
+        assertEq(assetA.balanceOf(address(market)), 0);
+        assertEq(assetB.balanceOf(address(market)), 0);
+        assertEq(market.currentBatchIndex(), 1);
+    }
+
+    function test_HandleProofSettlesOnlyThisMarketsBatch() public {
+        bytes32 newRoot = keccak256("new root");
+        OrderBook.BatchData[] memory markets = new OrderBook.BatchData[](2);
+        // Another market's batch, which this market could not settle
+        markets[0].market = makeAddr("otherMarket");
+        markets[0].batchIndex = 7;
+        markets[0].newUtxoMerkleRoot = keccak256("other root");
+        markets[0].newUtxoLeafCount = 9;
+        markets[1].market = address(orderBook);
+        markets[1].newUtxoMerkleRoot = newRoot;
+        markets[1].newUtxoLeafCount = 3;
+        markets[1].lastTradePrice = 100;
+
+        // Delivered by anyone but the Boundless Market, so the seal is verified
+        bytes memory journalData = _journal(markets);
+        orderBook.handleProof(imageId, journalData, verifier.mockProve(imageId, sha256(journalData)).seal);
+
+        assertEq(orderBook.utxoMerkleRoot(), newRoot);
+        assertEq(orderBook.utxoLeafCount(), 3);
+        assertEq(orderBook.currentBatchIndex(), 1);
+        assertEq(orderBook.lastTradePrice(), 100);
+    }
+
+    function test_RevertWhen_JournalHasNoBatchForMarket() public {
+        OrderBook.BatchData[] memory markets = new OrderBook.BatchData[](1);
+        markets[0].market = makeAddr("otherMarket");
+
+        bytes memory journalData = _journal(markets);
+        bytes memory seal = verifier.mockProve(imageId, sha256(journalData)).seal;
+        vm.expectRevert("OrderBook: no batch for this market");
+        orderBook.handleProof(imageId, journalData, seal);
+        assertEq(orderBook.currentBatchIndex(), 0);
+    }
+
+    /// @notice Encode a journal of these batches, committed to the previous block
+    function _journal(OrderBook.BatchData[] memory markets) internal returns (bytes memory) {
+        vm.roll(block.number + 1);
+        uint256 blockNumber = block.number - 1;
+        OrderBook.Journal memory journal = OrderBook.Journal({
+            steelCommitment: Steel.Commitment({id: blockNumber, digest: blockhash(blockNumber), configID: bytes32(0)}),
+            markets: markets
+        });
+        return abi.encode(journal);
+    }
+
+    /// @notice Deploy a harness market without market parameters
+    function _deployHarness(uint16 makerFeeBps, uint16 takerFeeBps, address feeRecipient)
+        internal
+        returns (OrderBookHarness)
+    {
+        return new OrderBookHarness(
+            verifier,
+            boundlessMarket,
+            imageId,
+            IERC20(address(assetA)),
+            IERC20(address(assetB)),
+            makerFeeBps,
+            takerFeeBps,
+            feeRecipient,
+            noMarketParams
+        );
+    }
+
+    /// @notice Mint both assets to an account and approve the market to collect them
+    function _fund(OrderBook market, address account, uint256 amountA, uint256 amountB) internal {
+        assetA.mint(account, amountA);
+        assetB.mint(account, amountB);
+        vm.startPrank(account);
+        assetA.approve(address(market), amountA);
+        assetB.approve(address(market), amountB);
+        vm.stopPrank();
     }
 }
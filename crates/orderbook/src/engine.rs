@@ -0,0 +1,2008 @@
+//! Matching engines that clear a batch of orders
+
+use alloy_primitives::{
+    map::{AddressMap, B256Map, B256Set},
+    Address, FixedBytes,
+};
+use core::ops::{Bound, Index, IndexMut};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::{
+    net_transfers, notional, AmendedOrder, BatchInput, BatchOutput, CancelReason, CancelledOrder,
+    EngineKind, FeeSchedule, Fill, IntentKind, IntentRejection, MarketParams, MatchError,
+    OracleRound, Order, OrderType, PostOnlyOutcome, PostOnlyPolicy, PostOnlyResult,
+    PriceBandPolicy, RejectedIntent, SelfTradePrevention, Side, TimeInForce, Utxo, UtxoTree,
+};
+
+/// Algorithm that clears a batch of orders
+pub trait MatchingEngine {
+    /// Verify the batch input and match its orders into fills and a new UTXO set
+    fn match_batch(&self, input: BatchInput) -> Result<BatchOutput, MatchError>;
+}
+
+/// Price-time priority: best price first, oldest first within a price.
+/// Every fill executes at the maker's price.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceTimeEngine;
+
+/// Pro-rata allocation: the best crossing price levels are matched as a whole, and the
+/// larger level is split across its orders by resting size. Rounding leftovers go to the
+/// oldest orders. Market orders are matched first, the same way, against the best limit
+/// levels within their bounds. Every fill executes at the maker's price.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProRataEngine;
+
+/// Uniform-price batch auction: every crossing order, market orders included, fills at
+/// a single clearing price
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchAuctionEngine;
+
+impl MatchingEngine for PriceTimeEngine {
+    fn match_batch(&self, input: BatchInput) -> Result<BatchOutput, MatchError> {
+        let book = Book::load(input)?;
+        Ok(book.clear(|book| {
+            book.sweep_market_orders();
+            book.match_price_time(None);
+        }))
+    }
+}
+
+impl MatchingEngine for ProRataEngine {
+    fn match_batch(&self, input: BatchInput) -> Result<BatchOutput, MatchError> {
+        let book = Book::load(input)?;
+        Ok(book.clear(Book::match_pro_rata))
+    }
+}
+
+impl MatchingEngine for BatchAuctionEngine {
+    fn match_batch(&self, input: BatchInput) -> Result<BatchOutput, MatchError> {
+        let book = Book::load(input)?;
+        Ok(book.clear(|book| {
+            // Without a clearing price nothing crosses
+            if let Some(price) = uniform_clearing_price(&book.buy_orders, &book.sell_orders) {
+                book.match_price_time(Some(price));
+            }
+        }))
+    }
+}
+
+impl EngineKind {
+    /// The engine implementing this kind
+    pub fn engine(self) -> &'static dyn MatchingEngine {
+        match self {
+            EngineKind::PriceTime => &PriceTimeEngine,
+            EngineKind::BatchAuction => &BatchAuctionEngine,
+            EngineKind::ProRata => &ProRataEngine,
+        }
+    }
+}
+
+/// Place of an order on one side of the book: its price level and its position in
+/// that level's queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Slot {
+    price: u64,
+    pos: usize,
+}
+
+/// One side of the book as a ladder of price levels, each queueing its orders by
+/// nonce (time priority), except that an iceberg's refreshed slice queues at the
+/// back of its level while matching. Walks go best price first: highest for buys,
+/// lowest for sells.
+#[derive(Clone)]
+struct Ladder {
+    side: Side,
+    levels: BTreeMap<u64, VecDeque<Utxo>>,
+}
+
+impl Ladder {
+    fn new(side: Side) -> Self {
+        Ladder {
+            side,
+            levels: BTreeMap::new(),
+        }
+    }
+
+    /// Queue an order at its price, behind every order with the same or a lower nonce
+    fn insert(&mut self, utxo: Utxo) {
+        let queue = self.levels.entry(utxo.order.price).or_default();
+        let pos = queue.partition_point(|u| u.order.nonce <= utxo.order.nonce);
+        queue.insert(pos, utxo);
+    }
+
+    /// Queue an order at the back of its level, whatever its nonce
+    fn push_back(&mut self, utxo: Utxo) -> Slot {
+        let price = utxo.order.price;
+        let queue = self.levels.entry(price).or_default();
+        queue.push_back(utxo);
+        Slot {
+            price,
+            pos: queue.len() - 1,
+        }
+    }
+
+    /// Take an order out of its level, dropping the level once it is empty
+    fn remove(&mut self, slot: Slot) -> Option<Utxo> {
+        let queue = self.levels.get_mut(&slot.price)?;
+        let utxo = queue.remove(slot.pos);
+        if queue.is_empty() {
+            self.levels.remove(&slot.price);
+        }
+        utxo
+    }
+
+    /// Slot of the order with this UTXO ID at `price`
+    fn find(&self, price: u64, id: FixedBytes<32>) -> Option<Slot> {
+        let pos = self.levels.get(&price)?.iter().position(|u| u.id == id)?;
+        Some(Slot { price, pos })
+    }
+
+    /// The slot after `slot` in priority order, or the best slot for `None`
+    fn next(&self, slot: Option<Slot>) -> Option<Slot> {
+        if let Some(slot) = slot {
+            if self
+                .levels
+                .get(&slot.price)
+                .is_some_and(|queue| slot.pos + 1 < queue.len())
+            {
+                return Some(Slot {
+                    pos: slot.pos + 1,
+                    ..slot
+                });
+            }
+        }
+        let price = match (self.side, slot) {
+            (Side::Buy, None) => self.levels.keys().next_back(),
+            (Side::Buy, Some(slot)) => self.levels.range(..slot.price).next_back().map(|(p, _)| p),
+            (Side::Sell, None) => self.levels.keys().next(),
+            (Side::Sell, Some(slot)) => self
+                .levels
+                .range((Bound::Excluded(slot.price), Bound::Unbounded))
+                .next()
+                .map(|(p, _)| p),
+        };
+        price.map(|&price| Slot { price, pos: 0 })
+    }
+
+    /// Price levels, best first
+    fn levels(&self) -> Box<dyn Iterator<Item = (u64, &VecDeque<Utxo>)> + '_> {
+        let levels = self.levels.iter().map(|(&price, queue)| (price, queue));
+        match self.side {
+            Side::Buy => Box::new(levels.rev()),
+            Side::Sell => Box::new(levels),
+        }
+    }
+
+    /// Every order with its slot, in priority order
+    fn iter(&self) -> impl Iterator<Item = (Slot, &Utxo)> {
+        self.levels().flat_map(|(price, queue)| {
+            queue
+                .iter()
+                .enumerate()
+                .map(move |(pos, utxo)| (Slot { price, pos }, utxo))
+        })
+    }
+
+    /// Take every order out of the ladder, in priority order
+    fn take(&mut self) -> Vec<Utxo> {
+        let levels = core::mem::take(&mut self.levels).into_values();
+        match self.side {
+            Side::Buy => levels.rev().flatten().collect(),
+            Side::Sell => levels.flatten().collect(),
+        }
+    }
+}
+
+impl Index<Slot> for Ladder {
+    type Output = Utxo;
+
+    fn index(&self, slot: Slot) -> &Utxo {
+        &self.levels[&slot.price][slot.pos]
+    }
+}
+
+impl IndexMut<Slot> for Ladder {
+    fn index_mut(&mut self, slot: Slot) -> &mut Utxo {
+        let queue = self
+            .levels
+            .get_mut(&slot.price)
+            .expect("slot outside the ladder");
+        &mut queue[slot.pos]
+    }
+}
+
+/// Working state of a batch: the verified book plus everything produced so far.
+///
+/// Filled and cancelled orders stay in place with zero quantity, so slots in the
+/// ladders remain stable while matching.
+struct Book {
+    market: Address,
+    batch_index: u64,
+    matching_engine: EngineKind,
+    self_trade_prevention: SelfTradePrevention,
+    post_only_policy: PostOnlyPolicy,
+    oracle_round: Option<OracleRound>,
+    fee_schedule: FeeSchedule,
+    /// Decimal exponent of every price in the market
+    price_exponent: i8,
+    /// Sizes new orders must conform to, including repriced post-only orders and
+    /// what a quote buy's budget buys
+    market_params: MarketParams,
+    /// Price of the last fill so far (0 before the first trade)
+    last_trade_price: u64,
+    /// Lowest and highest fill price allowed around the previous batch's last trade
+    price_band: Option<(u64, u64)>,
+    price_band_policy: PriceBandPolicy,
+    /// Whether matching was halted for a fill outside the price band
+    halted: bool,
+    /// Price levels walked highest first, nonce ASC within a level (price-time priority)
+    buy_orders: Ladder,
+    /// Price levels walked lowest first, nonce ASC within a level (price-time priority)
+    sell_orders: Ladder,
+    /// Orders kept out of matching: stop orders waiting for their trigger price and
+    /// pegged orders without an oracle price
+    dormant: Vec<Utxo>,
+    /// Existing UTXO IDs (these must be consumed when filled, even partially)
+    existing_utxo_ids: B256Set,
+    /// UTXO tree of the input, knowing the paths of the existing UTXOs and of the free
+    /// leaf new UTXOs are appended at
+    utxo_tree: UtxoTree,
+    /// Leaf index of every existing UTXO
+    leaf_indices: B256Map<u64>,
+    /// Consumed UTXO IDs in the order they were consumed
+    consumed_utxo_ids: Vec<FixedBytes<32>>,
+    /// Existing UTXOs consumed so far, for membership checks
+    consumed: B256Set,
+    fills: Vec<Fill>,
+    cancelled_orders: Vec<CancelledOrder>,
+    post_only_results: Vec<PostOnlyResult>,
+    amended_orders: Vec<AmendedOrder>,
+    /// Owner intents dropped while loading the book
+    rejected_intents: Vec<RejectedIntent>,
+    /// Nonce for the next order to lose time priority, behind every order in the batch
+    next_nonce: u64,
+    /// UTXO IDs of icebergs that showed their next slice while matching
+    refreshed: B256Set,
+    /// Every change to the ladders since the last checkpoint, oldest first
+    undo_log: Vec<Undo>,
+}
+
+/// A change to a ladder while matching, with what [`Book::rollback`] needs to undo it
+enum Undo {
+    /// An order changed from this previous state
+    Changed(Side, Slot, Box<Order>),
+    /// An iceberg's next slice was queued at this slot
+    Queued(Side, Slot),
+}
+
+/// Where a matching attempt started, so a fill-or-kill retry can roll it back
+/// without copying the book
+struct Checkpoint {
+    fills: usize,
+    cancelled_orders: usize,
+    consumed_utxo_ids: usize,
+    last_trade_price: u64,
+}
+
+impl Book {
+    /// Verify existing UTXOs, remove owner-cancelled and expired orders, replace
+    /// amended ones, reject new orders that break the market parameters or the price
+    /// band, set aside dormant stop orders, price pegged orders, apply the post-only
+    /// policy and queue both sides by priority
+    fn load(input: BatchInput) -> Result<Self, MatchError> {
+        let current_batch = input.batch_index;
+        let price_band = input.price_band.bounds(input.last_trade_price);
+        let rejects_out_of_band = |order: &Order| {
+            input.price_band.policy == PriceBandPolicy::RejectOrders
+                && order.order_type == OrderType::Limit
+                && !order.pegged
+                && price_band.is_some_and(|(low, high)| order.price < low || order.price > high)
+        };
+
+        let mut buy_orders = Ladder::new(Side::Buy);
+        let mut sell_orders = Ladder::new(Side::Sell);
+        let mut consumed_utxo_ids: Vec<FixedBytes<32>> = Vec::new();
+        let mut existing_utxo_ids = B256Set::default();
+        let mut leaf_indices = B256Map::default();
+        let mut cancelled_orders: Vec<CancelledOrder> = Vec::new();
+        // Outcome of each cancellation, which is not open until it finds its UTXO
+        let mut cancellation_results: Vec<Result<(), IntentRejection>> =
+            vec![Err(IntentRejection::NotOpen); input.cancellations.len()];
+        // Index of the first cancellation and amendment of each UTXO ID
+        let mut cancellation_index = B256Map::default();
+        for (index, cancellation) in input.cancellations.iter().enumerate() {
+            cancellation_index
+                .entry(cancellation.utxo_id)
+                .or_insert(index);
+        }
+        let mut amendment_index = B256Map::default();
+        for (index, amendment) in input.amendments.iter().enumerate() {
+            amendment_index.entry(amendment.utxo_id).or_insert(index);
+        }
+        // Amended UTXOs and their replacement orders by amendment index, and whether
+        // they keep their priority, or why the amendment was dropped
+        let mut amended: Vec<Result<(Utxo, Order, bool), IntentRejection>> =
+            vec![Err(IntentRejection::NotOpen); input.amendments.len()];
+
+        // New UTXOs are appended from the first free leaf on, so its path is needed too
+        let mut utxo_tree = UtxoTree::from_root(input.utxo_merkle_root, input.utxo_leaf_count);
+        if !utxo_tree.insert_proof(input.utxo_leaf_count, FixedBytes::ZERO, &input.append_proof) {
+            return Err(MatchError::InvalidAppendProof {
+                leaf_index: input.utxo_leaf_count,
+            });
+        }
+
+        // Process existing UTXOs with proof verification (skip expired ones)
+        for utxo_with_proof in input.existing_utxos_with_proofs {
+            // The leaf is the UTXO ID, so the order data must hash to it to be covered by the proof
+            if utxo_with_proof.utxo.id != utxo_with_proof.utxo.compute_id() {
+                return Err(MatchError::MalformedUtxo {
+                    leaf_index: utxo_with_proof.leaf_index,
+                    utxo_id: utxo_with_proof.utxo.id,
+                    reason: "order data does not hash to UTXO ID",
+                });
+            }
+
+            // Verify UTXO against on-chain Merkle root
+            let leaf_index = utxo_with_proof.leaf_index as u64;
+            if !utxo_tree.insert_proof(
+                leaf_index,
+                utxo_with_proof.utxo.id,
+                &utxo_with_proof.proof_hashes,
+            ) {
+                return Err(MatchError::InvalidProof {
+                    leaf_index: utxo_with_proof.leaf_index,
+                    utxo_id: utxo_with_proof.utxo.id,
+                });
+            }
+            leaf_indices.insert(utxo_with_proof.utxo.id, leaf_index);
+
+            let utxo = utxo_with_proof.utxo;
+            if utxo.order.market != input.market {
+                return Err(MatchError::MalformedUtxo {
+                    leaf_index: utxo_with_proof.leaf_index,
+                    utxo_id: utxo.id,
+                    reason: "UTXO belongs to another market",
+                });
+            }
+
+            // Only the order owner can cancel it, anyone else's cancellation is dropped
+            if let Some(&index) = cancellation_index.get(&utxo.id) {
+                cancellation_results[index] = input.cancellations[index]
+                    .verify(utxo.order.owner)
+                    .map_err(|_| IntentRejection::BadSignature);
+                if cancellation_results[index].is_ok() {
+                    consumed_utxo_ids.push(utxo.id);
+                    cancelled_orders.push(CancelledOrder {
+                        utxo_id: utxo.id,
+                        owner: utxo.order.owner,
+                        quantity: utxo
+                            .order
+                            .quantity
+                            .saturating_add(utxo.order.hidden_quantity),
+                        reason: CancelReason::OwnerRequest,
+                    });
+                    continue;
+                }
+            }
+
+            if utxo.is_expired(current_batch, input.block_timestamp) {
+                consumed_utxo_ids.push(utxo.id);
+                continue;
+            }
+
+            // A dropped amendment leaves its UTXO resting as it was
+            if let Some(&index) = amendment_index.get(&utxo.id) {
+                let amendment = &input.amendments[index];
+                let replacement = amendment
+                    .verify(utxo.order.owner)
+                    .map_err(|_| IntentRejection::BadSignature)
+                    .and_then(|()| {
+                        amendment
+                            .apply(&utxo.order)
+                            .map_err(|_| IntentRejection::InvalidAmendment)
+                    })
+                    .and_then(|(order, priority_kept)| {
+                        if input.market_params.check(&order).is_err() {
+                            Err(IntentRejection::NonConforming)
+                        } else if rejects_out_of_band(&order) {
+                            Err(IntentRejection::PriceBand)
+                        } else {
+                            Ok((order, priority_kept))
+                        }
+                    });
+                match replacement {
+                    Ok((order, priority_kept)) => {
+                        consumed_utxo_ids.push(utxo.id);
+                        amended[index] = Ok((utxo, order, priority_kept));
+                        continue;
+                    }
+                    Err(reason) => amended[index] = Err(reason),
+                }
+            }
+
+            existing_utxo_ids.insert(utxo.id);
+
+            match utxo.order.side {
+                Side::Buy => buy_orders.insert(utxo),
+                Side::Sell => sell_orders.insert(utxo),
+            }
+        }
+
+        // A duplicate intent is never applied, as its UTXO is matched by the first,
+        // and amendments also miss cancelled and expired UTXOs
+        let cancellations =
+            input
+                .cancellations
+                .iter()
+                .zip(cancellation_results)
+                .map(|(cancellation, result)| {
+                    (cancellation.utxo_id, IntentKind::Cancellation, result.err())
+                });
+        let amendments = input
+            .amendments
+            .iter()
+            .zip(&amended)
+            .map(|(amendment, result)| {
+                (
+                    amendment.utxo_id,
+                    IntentKind::Amendment,
+                    result.as_ref().err().copied(),
+                )
+            });
+        let rejected_intents: Vec<RejectedIntent> = cancellations
+            .chain(amendments)
+            .filter_map(|(utxo_id, kind, reason)| {
+                reason.map(|reason| RejectedIntent {
+                    utxo_id,
+                    kind,
+                    reason,
+                })
+            })
+            .collect();
+
+        // Process new orders (create UTXOs)
+        for (index, mut order) in input.new_orders.into_iter().enumerate() {
+            if order.market != input.market {
+                return Err(MatchError::MalformedOrder {
+                    index,
+                    reason: "order belongs to another market",
+                });
+            }
+            // A quote buy is sized by what its budget buys at its limit price
+            order.quantity = order.quantity_at(order.price, input.market_params.lot_size);
+            let utxo = Utxo::new(order);
+            if utxo.is_expired(current_batch, input.block_timestamp) {
+                continue;
+            }
+
+            // Non-conforming orders never reach the book
+            let rejection = input.market_params.check(&utxo.order).and_then(|()| {
+                if rejects_out_of_band(&utxo.order) {
+                    Err(CancelReason::PriceBand)
+                } else {
+                    Ok(())
+                }
+            });
+            if let Err(reason) = rejection {
+                cancelled_orders.push(CancelledOrder {
+                    utxo_id: utxo.id,
+                    owner: utxo.order.owner,
+                    quantity: utxo
+                        .order
+                        .quantity
+                        .saturating_add(utxo.order.hidden_quantity),
+                    reason,
+                });
+                continue;
+            }
+
+            match utxo.order.side {
+                Side::Buy => buy_orders.insert(utxo),
+                Side::Sell => sell_orders.insert(utxo),
+            }
+        }
+
+        // Amended orders that lose priority go behind every order in this batch
+        let mut next_nonce = buy_orders
+            .iter()
+            .chain(sell_orders.iter())
+            .map(|(_, utxo)| utxo.order.nonce)
+            .chain(amended.iter().flatten().map(|(_, order, _)| order.nonce))
+            .max()
+            .map_or(0, |nonce| nonce.saturating_add(1));
+        let mut amended_orders: Vec<AmendedOrder> = Vec::new();
+        for (old, mut order, priority_kept) in amended.into_iter().flatten() {
+            if !priority_kept {
+                order.nonce = next_nonce;
+                next_nonce = next_nonce.saturating_add(1);
+            }
+            // The replacement follows the amended UTXO, sized from what had filled so far
+            let original_quantity = old.filled_quantity.saturating_add(order.total_quantity());
+            let utxo = Utxo::with_lineage(
+                order,
+                old.id,
+                original_quantity,
+                old.original_quote_quantity,
+                old.filled_quantity,
+            );
+            amended_orders.push(AmendedOrder {
+                old_utxo_id: old.id,
+                new_utxo_id: utxo.id,
+                owner: utxo.order.owner,
+                priority_kept,
+            });
+
+            match utxo.order.side {
+                Side::Buy => buy_orders.insert(utxo),
+                Side::Sell => sell_orders.insert(utxo),
+            }
+        }
+
+        let mut book = Book {
+            market: input.market,
+            batch_index: current_batch,
+            matching_engine: input.matching_engine,
+            self_trade_prevention: input.self_trade_prevention,
+            post_only_policy: input.post_only_policy,
+            oracle_round: input.oracle_round,
+            fee_schedule: input.fee_schedule,
+            price_exponent: input.market_params.price_exponent,
+            market_params: input.market_params,
+            last_trade_price: input.last_trade_price,
+            price_band,
+            price_band_policy: input.price_band.policy,
+            halted: false,
+            buy_orders,
+            sell_orders,
+            dormant: Vec::new(),
+            existing_utxo_ids,
+            utxo_tree,
+            leaf_indices,
+            consumed_utxo_ids,
+            consumed: B256Set::default(),
+            fills: Vec::new(),
+            cancelled_orders,
+            post_only_results: Vec::new(),
+            amended_orders,
+            rejected_intents,
+            next_nonce,
+            refreshed: B256Set::default(),
+            undo_log: Vec::new(),
+        };
+        book.park_stop_orders();
+        book.bound_market_buys();
+        book.reprice_pegged_orders();
+        book.sideline_out_of_band_orders();
+        book.apply_post_only();
+
+        Ok(book)
+    }
+
+    /// Activate stop orders triggered by the last trade of earlier batches and move
+    /// the ones still waiting out of the book. An activated UTXO changes ID, so an
+    /// existing one is consumed.
+    fn park_stop_orders(&mut self) {
+        let orders = self
+            .buy_orders
+            .take()
+            .into_iter()
+            .chain(self.sell_orders.take());
+        for mut utxo in orders {
+            if utxo.order.is_dormant() {
+                if !utxo.order.is_triggered_by(self.last_trade_price) {
+                    self.dormant.push(utxo);
+                    continue;
+                }
+                utxo.order.triggered = true;
+                self.consume(utxo.id);
+            }
+            match utxo.order.side {
+                Side::Buy => self.buy_orders.insert(utxo),
+                Side::Sell => self.sell_orders.insert(utxo),
+            }
+        }
+    }
+
+    /// Give active market buys without a bound the highest price, so every engine
+    /// walks them before any limit buy. Market orders never rest, so the changed
+    /// price never reaches a UTXO.
+    fn bound_market_buys(&mut self) {
+        let unbounded =
+            |utxo: &Utxo| utxo.order.order_type == OrderType::Market && utxo.order.price == 0;
+        if !self.buy_orders.iter().any(|(_, utxo)| unbounded(utxo)) {
+            return;
+        }
+        for mut utxo in self.buy_orders.take() {
+            if unbounded(&utxo) {
+                utxo.order.price = u64::MAX;
+            }
+            self.buy_orders.insert(utxo);
+        }
+    }
+
+    /// Price active pegged orders at the oracle price plus their offset. Without an
+    /// oracle round, or when that price is out of range, they sit out the batch at
+    /// their last price. A repriced UTXO changes ID, so an existing one is consumed.
+    fn reprice_pegged_orders(&mut self) {
+        let oracle_price = self.oracle_round.map(|round| round.price);
+        let tick_size = self.market_params.tick_size;
+        for side in [Side::Buy, Side::Sell] {
+            let orders = match side {
+                Side::Buy => self.buy_orders.take(),
+                Side::Sell => self.sell_orders.take(),
+            };
+            for mut utxo in orders {
+                if utxo.order.pegged {
+                    match oracle_price.and_then(|price| utxo.order.pegged_price(price, tick_size)) {
+                        Some(price) => {
+                            if price != utxo.order.price {
+                                utxo.order.price = price;
+                                self.consume(utxo.id);
+                            }
+                        }
+                        None => {
+                            self.dormant.push(utxo);
+                            continue;
+                        }
+                    }
+                }
+                match side {
+                    Side::Buy => self.buy_orders.insert(utxo),
+                    Side::Sell => self.sell_orders.insert(utxo),
+                }
+            }
+        }
+    }
+
+    /// Set aside resting limit orders, and pegged orders priced by the oracle, that
+    /// are outside the price band, so no fill is outside it. They sit out the batch
+    /// like dormant orders and are matched again once the band moves back over them.
+    fn sideline_out_of_band_orders(&mut self) {
+        let Some((low, high)) = self.price_band else {
+            return;
+        };
+        if self.price_band_policy != PriceBandPolicy::RejectOrders {
+            return;
+        }
+        let orders = self
+            .buy_orders
+            .take()
+            .into_iter()
+            .chain(self.sell_orders.take());
+        for utxo in orders {
+            let order = &utxo.order;
+            if order.order_type == OrderType::Limit && (order.price < low || order.price > high) {
+                self.dormant.push(utxo);
+                continue;
+            }
+            match utxo.order.side {
+                Side::Buy => self.buy_orders.insert(utxo),
+                Side::Sell => self.sell_orders.insert(utxo),
+            }
+        }
+    }
+
+    /// Check new post-only orders, and resting pegged ones the oracle moved, oldest
+    /// first, against the orders they would be the taker against, and reject or
+    /// reprice every one that would take liquidity
+    fn apply_post_only(&mut self) {
+        // A repriced pegged order has been consumed, one the oracle left in place has not
+        let moved = |utxo: &Utxo| {
+            !self.existing_utxo_ids.contains(&utxo.id)
+                || (utxo.order.pegged && self.consumed.contains(&utxo.id))
+        };
+        let mut post_only: Vec<(Side, FixedBytes<32>, u64, u64)> = self
+            .positions(|utxo| utxo.order.post_only && moved(utxo))
+            .into_iter()
+            .map(|(side, slot, _)| {
+                let utxo = &self.orders(side)[slot];
+                (side, utxo.id, utxo.order.price, utxo.order.nonce)
+            })
+            .collect();
+        post_only.sort_by_key(|&(_, _, _, nonce)| nonce);
+
+        for (side, id, price, nonce) in post_only {
+            // Repricing moves orders between levels, so find the order again
+            let Some(slot) = self.orders(side).find(price, id) else {
+                continue;
+            };
+            let utxo = &self.orders(side)[slot];
+            let quantity = utxo.order.quantity;
+            let order = utxo.order.clone();
+
+            // Market orders never make, otherwise the older order is the maker
+            // and a sell wins a nonce tie (see `buy_is_taker`)
+            let opposite = match side {
+                Side::Buy => &self.sell_orders,
+                Side::Sell => &self.buy_orders,
+            };
+            let is_maker = |u: &Utxo| {
+                u.order.quantity > 0
+                    && u.order.order_type == OrderType::Limit
+                    && (u.order.nonce < nonce || (u.order.nonce == nonce && side == Side::Buy))
+                    && u.order.can_trade_with(&order)
+            };
+            let crosses = |level: u64| match side {
+                Side::Buy => price >= level,
+                Side::Sell => price <= level,
+            };
+            // Best opposite price this order would cross, and the price one tick behind it
+            let tick = self.market_params.tick_size.max(1);
+            let crossed = opposite
+                .levels()
+                .take_while(|&(level, _)| crosses(level))
+                .find(|(_, queue)| queue.iter().any(is_maker))
+                .map(|(level, _)| match side {
+                    Side::Buy => level.checked_sub(tick).filter(|&price| price > 0),
+                    Side::Sell => level.checked_add(tick),
+                });
+
+            // The repriced order must still meet the market parameters, as a quote buy
+            // is resized and a lower buy price lowers the notional
+            let repriced = match (crossed, self.post_only_policy) {
+                (None, _) => None,
+                (Some(Some(repriced)), PostOnlyPolicy::Reprice) => {
+                    let mut order = order.clone();
+                    order.price = repriced;
+                    order.quantity = order.quantity_at(repriced, self.market_params.lot_size);
+                    Some(self.market_params.check(&order).map(|()| order))
+                }
+                // Rejected by policy, or no price one tick behind exists
+                (Some(_), _) => Some(Err(CancelReason::PostOnly)),
+            };
+            let (outcome, new_utxo_id, price) = match repriced {
+                None => (PostOnlyOutcome::Posted, id, price),
+                Some(Ok(order)) => {
+                    let orders = match side {
+                        Side::Buy => &mut self.buy_orders,
+                        Side::Sell => &mut self.sell_orders,
+                    };
+                    // A new order matches under the ID of its new price, a resting
+                    // pegged one under its UTXO, which its successor replaces
+                    let mut utxo = orders.remove(slot).expect("post-only order in the book");
+                    if self.existing_utxo_ids.contains(&id) {
+                        utxo.order = order;
+                    } else {
+                        utxo = Utxo::new(order);
+                    }
+                    let (new_utxo_id, price) = (utxo.id, utxo.order.price);
+                    orders.insert(utxo);
+                    (PostOnlyOutcome::Repriced, new_utxo_id, price)
+                }
+                Some(Err(reason)) => {
+                    self.cancel(side, slot, quantity, reason);
+                    (PostOnlyOutcome::Rejected, FixedBytes::ZERO, price)
+                }
+            };
+            self.post_only_results.push(PostOnlyResult {
+                utxo_id: id,
+                new_utxo_id,
+                owner: order.owner,
+                outcome,
+                price,
+            });
+        }
+    }
+
+    /// Match the book with `match_book`, cancel what market orders have left,
+    /// enforce time in force and collect the output.
+    ///
+    /// A fill-or-kill order that does not fill completely is cancelled whole and the
+    /// batch is matched again without it, so none of its fills reach the output. This
+    /// repeats until every remaining fill-or-kill order fills. Each retry rolls back
+    /// only the orders the failed attempt changed. Under the halting price band
+    /// policy, a batch with any fill outside the band is not matched at all: market
+    /// and fill-or-kill orders are cancelled and everything else rests.
+    /// Immediate-or-cancel remainders are then cancelled instead of resting.
+    fn clear(mut self, match_book: impl Fn(&mut Book)) -> BatchOutput {
+        let checkpoint = loop {
+            let fill_or_kill: Vec<(Side, Slot, u64)> = self.positions(|utxo| {
+                utxo.order.time_in_force == TimeInForce::FillOrKill && utxo.order.quantity > 0
+            });
+            let checkpoint = self.checkpoint();
+            match_book(&mut self);
+            let unfilled = self.positions(|utxo| {
+                utxo.order.order_type == OrderType::Market && utxo.order.quantity > 0
+            });
+            for (side, idx, quantity) in unfilled {
+                self.cancel(side, idx, quantity, CancelReason::MarketRemainder);
+            }
+
+            let filled = self.filled_quantities();
+            let killed: Vec<(Side, Slot, u64)> = fill_or_kill
+                .into_iter()
+                .filter(|&(side, idx, quantity)| {
+                    let id = self.orders(side)[idx].id;
+                    filled.get(&id).copied().unwrap_or(0) < quantity
+                })
+                .collect();
+            if killed.is_empty() {
+                break checkpoint;
+            }
+            self.rollback(checkpoint);
+            for (side, idx, quantity) in killed {
+                self.cancel(side, idx, quantity, CancelReason::FillOrKill);
+            }
+        };
+
+        if self.price_band_policy == PriceBandPolicy::HaltMatching {
+            if let Some((low, high)) = self.price_band {
+                if self
+                    .fills
+                    .iter()
+                    .any(|fill| fill.price < low || fill.price > high)
+                {
+                    self.rollback(checkpoint);
+                    self.halted = true;
+                    let unfillable = self.positions(|utxo| {
+                        utxo.order.quantity > 0
+                            && (utxo.order.order_type == OrderType::Market
+                                || utxo.order.time_in_force == TimeInForce::FillOrKill)
+                    });
+                    for (side, idx, quantity) in unfillable {
+                        let reason = match self.orders(side)[idx].order.order_type {
+                            OrderType::Market => CancelReason::MarketRemainder,
+                            OrderType::Limit => CancelReason::FillOrKill,
+                        };
+                        self.cancel(side, idx, quantity, reason);
+                    }
+                }
+            }
+        }
+
+        let remainders = self.positions(|utxo| {
+            utxo.order.time_in_force == TimeInForce::ImmediateOrCancel && utxo.order.quantity > 0
+        });
+        for (side, idx, quantity) in remainders {
+            self.cancel(side, idx, quantity, CancelReason::ImmediateOrCancel);
+        }
+
+        self.into_output()
+    }
+
+    /// Start recording changes to roll back to this point
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.undo_log.clear();
+        Checkpoint {
+            fills: self.fills.len(),
+            cancelled_orders: self.cancelled_orders.len(),
+            consumed_utxo_ids: self.consumed_utxo_ids.len(),
+            last_trade_price: self.last_trade_price,
+        }
+    }
+
+    /// Undo every fill, cancellation, consumption and refreshed iceberg slice since
+    /// `checkpoint`
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        // Newest first, so an order changed several times ends in its oldest state and
+        // a queued slice is still last in its level when it is removed
+        for undo in core::mem::take(&mut self.undo_log).into_iter().rev() {
+            match undo {
+                Undo::Changed(side, idx, order) => self.ladder_mut(side)[idx].order = *order,
+                Undo::Queued(side, idx) => {
+                    if let Some(utxo) = self.ladder_mut(side).remove(idx) {
+                        self.refreshed.remove(&utxo.id);
+                    }
+                }
+            }
+        }
+        self.fills.truncate(checkpoint.fills);
+        self.cancelled_orders.truncate(checkpoint.cancelled_orders);
+        for id in self.consumed_utxo_ids.drain(checkpoint.consumed_utxo_ids..) {
+            self.consumed.remove(&id);
+        }
+        self.last_trade_price = checkpoint.last_trade_price;
+    }
+
+    /// An order to change while matching, recording its state for [`Book::rollback`]
+    fn order_mut(&mut self, side: Side, idx: Slot) -> &mut Order {
+        let utxo = match side {
+            Side::Buy => &mut self.buy_orders[idx],
+            Side::Sell => &mut self.sell_orders[idx],
+        };
+        self.undo_log
+            .push(Undo::Changed(side, idx, Box::new(utxo.order.clone())));
+        &mut utxo.order
+    }
+
+    /// Show an iceberg's next slice from its reserve once its displayed quantity has
+    /// filled. The slice keeps the UTXO but loses time priority: it queues at the back
+    /// of its level, while the filled slot stays in place with nothing left. It keeps
+    /// its nonce until the batch ends, so it is still the maker against the orders it
+    /// rested before, and then rests behind every order in the batch.
+    fn refresh_iceberg(&mut self, side: Side, idx: Slot) {
+        let utxo = &self.orders(side)[idx];
+        if utxo.order.quantity > 0 || utxo.order.hidden_quantity == 0 {
+            return;
+        }
+        let mut refreshed = utxo.clone();
+        let order = &mut refreshed.order;
+        let slice = match order.display_quantity {
+            0 => order.hidden_quantity,
+            display => display.min(order.hidden_quantity),
+        };
+        order.quantity = slice;
+        order.hidden_quantity -= slice;
+        self.order_mut(side, idx).hidden_quantity = 0;
+
+        self.refreshed.insert(refreshed.id);
+        let slot = self.ladder_mut(side).push_back(refreshed);
+        self.undo_log.push(Undo::Queued(side, slot));
+    }
+
+    /// Sweep each market order, oldest first, through the resting limit orders on the
+    /// opposite side in priority order until it is filled or reaches its worst price.
+    /// Market orders are always the taker and their remainder is cancelled, so they
+    /// are gone before price-time priority matches the limit orders.
+    fn sweep_market_orders(&mut self) {
+        let mut market = self.positions(|utxo| utxo.order.order_type == OrderType::Market);
+        market.sort_by_key(|&(side, idx, _)| self.orders(side)[idx].order.nonce);
+
+        for (side, idx, _) in market {
+            let worst_price = self.orders(side)[idx].order.price;
+            let opposite_side = match side {
+                Side::Buy => Side::Sell,
+                Side::Sell => Side::Buy,
+            };
+
+            let mut next = self.orders(opposite_side).next(None);
+            while let Some(opposite_idx) = next {
+                next = self.orders(opposite_side).next(Some(opposite_idx));
+                let (buy_idx, sell_idx) = match side {
+                    Side::Buy => (idx, opposite_idx),
+                    Side::Sell => (opposite_idx, idx),
+                };
+                let buy = &self.buy_orders[buy_idx].order;
+                let sell = &self.sell_orders[sell_idx].order;
+                let (taker, resting) = match side {
+                    Side::Buy => (buy, sell),
+                    Side::Sell => (sell, buy),
+                };
+
+                if taker.quantity == 0 {
+                    break;
+                }
+                if resting.quantity == 0 || resting.order_type == OrderType::Market {
+                    continue;
+                }
+                let within_bound = match side {
+                    Side::Buy => resting.price <= worst_price,
+                    Side::Sell => resting.price >= worst_price,
+                };
+                // The opposite side is walked best first, so nothing further is in bound
+                if !within_bound {
+                    break;
+                }
+                // A directed order is skipped by everyone it does not name
+                if !buy.can_trade_with(sell) {
+                    continue;
+                }
+
+                // Self-trade prevention leaves either this resting order or the market
+                // order with nothing to match
+                if buy.owner == sell.owner {
+                    self.prevent_self_trade(buy_idx, sell_idx);
+                    continue;
+                }
+
+                let fill_qty = self.fill_capacity(buy, sell, None);
+                if !self.fill_allowed(buy, sell, fill_qty, None) {
+                    continue;
+                }
+                self.execute(buy_idx, sell_idx, fill_qty, None);
+                // A refreshed iceberg queues its next slice behind its own level
+                next = self.orders(opposite_side).next(Some(opposite_idx));
+            }
+
+            let remaining = self.orders(side)[idx].order.quantity;
+            if remaining > 0 {
+                self.cancel(side, idx, remaining, CancelReason::MarketRemainder);
+            }
+        }
+    }
+
+    /// Orders on one side of the book
+    fn orders(&self, side: Side) -> &Ladder {
+        match side {
+            Side::Buy => &self.buy_orders,
+            Side::Sell => &self.sell_orders,
+        }
+    }
+
+    /// Orders on one side of the book, to change without recording it
+    fn ladder_mut(&mut self, side: Side) -> &mut Ladder {
+        match side {
+            Side::Buy => &mut self.buy_orders,
+            Side::Sell => &mut self.sell_orders,
+        }
+    }
+
+    /// Side, slot and remaining quantity of every order matching `predicate`
+    fn positions(&self, predicate: impl Fn(&Utxo) -> bool) -> Vec<(Side, Slot, u64)> {
+        let buys = self.buy_orders.iter().map(|(i, u)| (Side::Buy, i, u));
+        let sells = self.sell_orders.iter().map(|(i, u)| (Side::Sell, i, u));
+        buys.chain(sells)
+            .filter(|(_, _, utxo)| predicate(utxo))
+            .map(|(side, idx, utxo)| (side, idx, utxo.order.quantity))
+            .collect()
+    }
+
+    /// Total quantity filled so far by each UTXO ID
+    fn filled_quantities(&self) -> B256Map<u64> {
+        let mut filled = B256Map::default();
+        for fill in &self.fills {
+            *filled.entry(fill.maker_utxo_id).or_default() += fill.quantity;
+            *filled.entry(fill.taker_utxo_id).or_default() += fill.quantity;
+        }
+        filled
+    }
+
+    /// Walk both sides in priority order, filling each buy against the sells it
+    /// crosses. A pair whose fill would fall below either order's minimum or be worth
+    /// nothing (see [`Book::fill_allowed`]), or where either order does not allow the
+    /// other's owner, is skipped, so both orders keep their place for later
+    /// counterparties.
+    /// With a clearing price every fill executes at it, and only orders that
+    /// accept it participate.
+    fn match_price_time(&mut self, clearing_price: Option<u64>) {
+        // Sells before this slot have nothing left to match
+        let mut first_sell = self.sell_orders.next(None);
+
+        let mut next_buy = self.buy_orders.next(None);
+        while let Some(buy_idx) = next_buy {
+            let buy_price = self.buy_orders[buy_idx].order.price;
+            if clearing_price.is_some_and(|price| buy_price < price) {
+                break;
+            }
+            while let Some(sell_idx) = first_sell {
+                if self.sell_orders[sell_idx].order.quantity > 0 {
+                    break;
+                }
+                first_sell = self.sell_orders.next(Some(sell_idx));
+            }
+
+            let mut next_sell = first_sell;
+            while let Some(sell_idx) = next_sell {
+                next_sell = self.sell_orders.next(Some(sell_idx));
+                let buy = &self.buy_orders[buy_idx].order;
+                let sell = &self.sell_orders[sell_idx].order;
+
+                // Filled or cancelled orders have nothing left to match
+                if buy.quantity == 0 {
+                    break;
+                }
+                if sell.quantity == 0 {
+                    continue;
+                }
+
+                if buy.price < sell.price {
+                    break;
+                }
+                if clearing_price.is_some_and(|price| sell.price > price) {
+                    break;
+                }
+                // A sell that does not allow this buy, or the other way round, keeps
+                // its place for the buys behind it
+                if !buy.can_trade_with(sell) {
+                    continue;
+                }
+
+                // Prevent self-trading (same owner on both sides)
+                if buy.owner == sell.owner {
+                    self.prevent_self_trade(buy_idx, sell_idx);
+                    continue;
+                }
+
+                let fill_qty = self.fill_capacity(buy, sell, clearing_price);
+                if !self.fill_allowed(buy, sell, fill_qty, clearing_price) {
+                    continue;
+                }
+                self.execute(buy_idx, sell_idx, fill_qty, clearing_price);
+                // A refreshed iceberg queues its next slice behind its own level
+                next_sell = self.sell_orders.next(Some(sell_idx));
+            }
+
+            // Walked after matching, so the buy's next slice is reached in its level
+            next_buy = self.buy_orders.next(Some(buy_idx));
+        }
+    }
+
+    /// Match the best crossing price levels against each other, splitting the
+    /// larger level across its orders in proportion to their size.
+    ///
+    /// Market orders go first. Each side's market orders are matched as one level
+    /// against the best opposite limit levels within their bounds, so a level they
+    /// reach is shared among its orders by size rather than by time. The limit
+    /// orders are then matched without them.
+    ///
+    /// An order whose share would fall below its minimum fill, or that cannot be
+    /// paired without breaking a minimum or with a counterparty it allows, sits out
+    /// the rest of its phase and keeps its remaining quantity.
+    fn match_pro_rata(&mut self) {
+        for side in [Side::Buy, Side::Sell] {
+            let opposite_side = match side {
+                Side::Buy => Side::Sell,
+                Side::Sell => Side::Buy,
+            };
+            let mut takers_out = BTreeSet::new();
+            let mut makers_out = BTreeSet::new();
+            loop {
+                let level = best_level(self.orders(opposite_side), &makers_out);
+                let Some(level_price) = level.first().map(|slot| slot.price) else {
+                    break;
+                };
+                let takers: Vec<Slot> = self
+                    .orders(side)
+                    .iter()
+                    .filter(|(slot, utxo)| {
+                        let order = &utxo.order;
+                        order.order_type == OrderType::Market
+                            && order.quantity > 0
+                            && !takers_out.contains(slot)
+                            && match side {
+                                Side::Buy => level_price <= order.price,
+                                Side::Sell => level_price >= order.price,
+                            }
+                    })
+                    .map(|(slot, _)| slot)
+                    .collect();
+                if takers.is_empty() {
+                    break;
+                }
+                match side {
+                    Side::Buy => {
+                        self.fill_levels_pro_rata(&takers, &level, &mut takers_out, &mut makers_out)
+                    }
+                    Side::Sell => {
+                        self.fill_levels_pro_rata(&level, &takers, &mut makers_out, &mut takers_out)
+                    }
+                }
+            }
+        }
+
+        let mut buys_out = BTreeSet::new();
+        let mut sells_out = BTreeSet::new();
+        loop {
+            let buy_level = best_level(&self.buy_orders, &buys_out);
+            let sell_level = best_level(&self.sell_orders, &sells_out);
+            let (Some(&best_buy), Some(&best_sell)) = (buy_level.first(), sell_level.first())
+            else {
+                break;
+            };
+            if self.buy_orders[best_buy].order.price < self.sell_orders[best_sell].order.price {
+                break;
+            }
+            self.fill_levels_pro_rata(&buy_level, &sell_level, &mut buys_out, &mut sells_out);
+        }
+    }
+
+    /// Match a level of buys against a level of sells: split the traded quantity
+    /// across each level in proportion to order size and pair the shares in priority
+    /// order, each fill at the maker's price. If the levels hold a self-trade, only
+    /// that is resolved. Orders whose share is below their minimum fill, or is left
+    /// over because it could not be paired, are added to `buys_out` or `sells_out`.
+    fn fill_levels_pro_rata(
+        &mut self,
+        buy_level: &[Slot],
+        sell_level: &[Slot],
+        buys_out: &mut BTreeSet<Slot>,
+        sells_out: &mut BTreeSet<Slot>,
+    ) {
+        // Resolve self-trades first, so any buy in the level can pair with any sell
+        let self_trade = buy_level.iter().find_map(|&buy_idx| {
+            let buy = &self.buy_orders[buy_idx].order;
+            sell_level
+                .iter()
+                .find(|&&sell_idx| {
+                    let sell = &self.sell_orders[sell_idx].order;
+                    sell.owner == buy.owner && sell.can_trade_with(buy)
+                })
+                .map(|&sell_idx| (buy_idx, sell_idx))
+        });
+        if let Some((buy_idx, sell_idx)) = self_trade {
+            self.prevent_self_trade(buy_idx, sell_idx);
+            return;
+        }
+
+        let buy_sizes: Vec<u64> = buy_level
+            .iter()
+            .map(|&i| self.buy_orders[i].order.quantity)
+            .collect();
+        let sell_sizes: Vec<u64> = sell_level
+            .iter()
+            .map(|&i| self.sell_orders[i].order.quantity)
+            .collect();
+        let traded = buy_sizes
+            .iter()
+            .map(|&q| u128::from(q))
+            .sum::<u128>()
+            .min(sell_sizes.iter().map(|&q| u128::from(q)).sum());
+
+        let mut buy_alloc = pro_rata_allocation(&buy_sizes, traded);
+        let mut sell_alloc = pro_rata_allocation(&sell_sizes, traded);
+
+        // Split the levels again without any order whose share is below its minimum
+        let mut short = false;
+        for (b, &i) in buy_level.iter().enumerate() {
+            if buy_alloc[b] > 0 && buy_alloc[b] < self.buy_orders[i].order.min_fill() {
+                buys_out.insert(i);
+                short = true;
+            }
+        }
+        for (s, &i) in sell_level.iter().enumerate() {
+            if sell_alloc[s] > 0 && sell_alloc[s] < self.sell_orders[i].order.min_fill() {
+                sells_out.insert(i);
+                short = true;
+            }
+        }
+        if short {
+            return;
+        }
+
+        // Both allocations sum to the traded quantity, so without minimums or
+        // directed orders pairing them in priority order uses up both
+        for (b, &buy_idx) in buy_level.iter().enumerate() {
+            for (s, &sell_idx) in sell_level.iter().enumerate() {
+                let fill_qty = buy_alloc[b].min(sell_alloc[s]);
+                let buy = &self.buy_orders[buy_idx].order;
+                let sell = &self.sell_orders[sell_idx].order;
+                if fill_qty == 0
+                    || !buy.can_trade_with(sell)
+                    || !self.fill_allowed(buy, sell, fill_qty, None)
+                {
+                    continue;
+                }
+                self.execute(buy_idx, sell_idx, fill_qty, None);
+                buy_alloc[b] -= fill_qty;
+                sell_alloc[s] -= fill_qty;
+            }
+        }
+
+        // Orders left with part of their share could not be paired within the
+        // minimums or their counterparties
+        for (b, &i) in buy_level.iter().enumerate() {
+            if buy_alloc[b] > 0 {
+                buys_out.insert(i);
+            }
+        }
+        for (s, &i) in sell_level.iter().enumerate() {
+            if sell_alloc[s] > 0 {
+                sells_out.insert(i);
+            }
+        }
+    }
+
+    /// Most a buy and a sell can fill against each other. A quote buy's budget is
+    /// converted at the fill price, so it buys more below its limit price.
+    fn fill_capacity(&self, buy: &Order, sell: &Order, clearing_price: Option<u64>) -> u64 {
+        if buy.quote_quantity == 0 {
+            return buy.quantity.min(sell.quantity);
+        }
+        let price = fill_price(buy, sell, clearing_price);
+        buy.quantity_at(price, self.market_params.lot_size)
+            .min(sell.quantity)
+    }
+
+    /// Whether a buy and a sell may fill `quantity` against each other: it meets both
+    /// minimum fills and is worth at least one base unit of AssetB at the fill price,
+    /// so no fill hands over AssetA for a notional rounded down to nothing
+    fn fill_allowed(
+        &self,
+        buy: &Order,
+        sell: &Order,
+        quantity: u64,
+        clearing_price: Option<u64>,
+    ) -> bool {
+        let price = fill_price(buy, sell, clearing_price);
+        meets_min_fill(buy, sell, quantity)
+            && !notional(price, quantity, self.price_exponent).is_zero()
+    }
+
+    /// Fill `quantity` between a buy and a sell order. The maker (see `buy_is_taker`)
+    /// sets the price unless a clearing price is given. A quote buy pays the notional
+    /// out of its budget and keeps what the rest buys at its limit price, and an
+    /// iceberg whose displayed quantity fills shows its next slice.
+    fn execute(
+        &mut self,
+        buy_idx: Slot,
+        sell_idx: Slot,
+        quantity: u64,
+        clearing_price: Option<u64>,
+    ) {
+        let buy = &self.buy_orders[buy_idx];
+        let sell = &self.sell_orders[sell_idx];
+
+        let (maker, taker, maker_is_seller) = if buy_is_taker(&buy.order, &sell.order) {
+            (sell, buy, true)
+        } else {
+            (buy, sell, false)
+        };
+
+        let price = clearing_price.unwrap_or(maker.order.price);
+        let notional = notional(price, quantity, self.price_exponent);
+        self.fills.push(Fill {
+            maker_utxo_id: maker.id,
+            taker_utxo_id: taker.id,
+            price,
+            price_exponent: self.price_exponent,
+            quantity,
+            notional,
+            maker: maker.order.owner,
+            taker: taker.order.owner,
+            maker_is_seller,
+            maker_fee: self.fee_schedule.maker_fee(notional),
+            taker_fee: self.fee_schedule.taker_fee(notional),
+        });
+        self.last_trade_price = price;
+
+        // Consume existing UTXOs on any fill (partial or full)
+        let (buy_id, sell_id) = (buy.id, sell.id);
+        self.consume(buy_id);
+        self.consume(sell_id);
+
+        let lot_size = self.market_params.lot_size;
+        let buy = self.order_mut(Side::Buy, buy_idx);
+        if buy.quote_quantity > 0 {
+            buy.quote_quantity -= notional.saturating_to::<u128>();
+            buy.quantity = buy.quantity_at(buy.price, lot_size);
+        } else {
+            buy.quantity -= quantity;
+        }
+        self.order_mut(Side::Sell, sell_idx).quantity -= quantity;
+        self.refresh_iceberg(Side::Buy, buy_idx);
+        self.refresh_iceberg(Side::Sell, sell_idx);
+    }
+
+    /// Apply self-trade prevention to a crossing buy and sell from the same owner.
+    /// The taker counts as the newer order, and its mode applies.
+    fn prevent_self_trade(&mut self, buy_idx: Slot, sell_idx: Slot) {
+        let buy = &self.buy_orders[buy_idx].order;
+        let sell = &self.sell_orders[sell_idx].order;
+
+        let buy_is_newer = buy_is_taker(buy, sell);
+        let taker = if buy_is_newer { buy } else { sell };
+        let mode = taker
+            .self_trade_prevention
+            .unwrap_or(self.self_trade_prevention);
+
+        // Quantity to cancel from each side (None leaves the order untouched)
+        let (buy_cancel, sell_cancel) = match mode {
+            SelfTradePrevention::CancelNewest if buy_is_newer => (Some(buy.quantity), None),
+            SelfTradePrevention::CancelNewest => (None, Some(sell.quantity)),
+            SelfTradePrevention::CancelOldest if buy_is_newer => (None, Some(sell.quantity)),
+            SelfTradePrevention::CancelOldest => (Some(buy.quantity), None),
+            SelfTradePrevention::CancelBoth => (Some(buy.quantity), Some(sell.quantity)),
+            SelfTradePrevention::DecrementAndCancel => {
+                let qty = buy.quantity.min(sell.quantity);
+                (Some(qty), Some(qty))
+            }
+        };
+
+        if let Some(qty) = buy_cancel {
+            self.cancel(Side::Buy, buy_idx, qty, CancelReason::SelfTrade);
+        }
+        if let Some(qty) = sell_cancel {
+            self.cancel(Side::Sell, sell_idx, qty, CancelReason::SelfTrade);
+        }
+    }
+
+    /// Cancel part of an order's remaining quantity and report it. An order whose
+    /// displayed quantity is cancelled to zero loses its hidden reserve as well, and a
+    /// quote buy loses the notional of the cancelled quantity at its limit price.
+    fn cancel(&mut self, side: Side, idx: Slot, quantity: u64, reason: CancelReason) {
+        let id = self.orders(side)[idx].id;
+        let order = self.order_mut(side, idx);
+        order.quantity -= quantity;
+        let mut quantity = quantity;
+        if order.quantity == 0 {
+            quantity += core::mem::take(&mut order.hidden_quantity);
+            order.quote_quantity = 0;
+        } else if order.quote_quantity > 0 {
+            let cancelled = notional(order.price, quantity, order.price_exponent);
+            order.quote_quantity = order
+                .quote_quantity
+                .saturating_sub(cancelled.saturating_to::<u128>());
+        }
+
+        let owner = order.owner;
+        self.cancelled_orders.push(CancelledOrder {
+            utxo_id: id,
+            owner,
+            quantity,
+            reason,
+        });
+        self.consume(id);
+    }
+
+    /// Mark an existing UTXO as consumed (new orders have nothing to consume)
+    fn consume(&mut self, id: FixedBytes<32>) {
+        if self.existing_utxo_ids.contains(&id) && self.consumed.insert(id) {
+            self.consumed_utxo_ids.push(id);
+        }
+    }
+
+    /// Collect orders with quantity left and update the Merkle root. Existing orders
+    /// nothing touched keep their UTXO at its leaf, every consumed UTXO leaves a
+    /// tombstone and every other order gets a new UTXO appended after the last leaf.
+    /// A changed order's new UTXO succeeds its old one and adds the quantity it filled
+    /// in this batch.
+    ///
+    /// An iceberg that showed a new slice rests with the last one shown and loses time
+    /// priority: it gets a nonce behind every order in this batch. Stop orders crossed
+    /// by this batch's last trade are activated for the next batch.
+    fn into_output(mut self) -> BatchOutput {
+        let mut dormant = core::mem::take(&mut self.dormant);
+        for utxo in &mut dormant {
+            if utxo.order.is_triggered_by(self.last_trade_price) {
+                utxo.order.triggered = true;
+                self.consume(utxo.id);
+            }
+        }
+
+        let filled = self.filled_quantities();
+        let resting: Vec<Utxo> = self
+            .buy_orders
+            .take()
+            .into_iter()
+            .chain(self.sell_orders.take())
+            .chain(dormant)
+            .map(|mut utxo| {
+                if self.refreshed.contains(&utxo.id) && utxo.order.quantity > 0 {
+                    utxo.order.nonce = self.next_nonce;
+                    self.next_nonce = self.next_nonce.saturating_add(1);
+                }
+                utxo
+            })
+            .filter(|utxo| utxo.order.quantity > 0)
+            .collect();
+
+        let (unchanged, changed): (Vec<Utxo>, Vec<Utxo>) = resting.into_iter().partition(|utxo| {
+            self.existing_utxo_ids.contains(&utxo.id) && !self.consumed.contains(&utxo.id)
+        });
+        let unchanged: B256Set = unchanged.iter().map(|utxo| utxo.id).collect();
+        // A new order nothing changed rests as submitted, any other order as the
+        // successor of its UTXO
+        let new_utxos: Vec<Utxo> = changed
+            .into_iter()
+            .map(|utxo| {
+                if utxo.compute_id() == utxo.id {
+                    utxo
+                } else {
+                    utxo.successor(filled.get(&utxo.id).copied().unwrap_or(0))
+                }
+            })
+            .collect();
+
+        // Update the root in place: every existing UTXO the batch touched leaves a
+        // tombstone, and the new UTXOs are appended
+        for (id, &leaf_index) in &self.leaf_indices {
+            if !unchanged.contains(id) {
+                self.utxo_tree.remove(leaf_index);
+            }
+        }
+        for utxo in &new_utxos {
+            self.utxo_tree.push(utxo.id);
+        }
+        let transfers = net_transfers(&self.fills);
+
+        BatchOutput {
+            market: self.market,
+            batch_index: self.batch_index,
+            matching_engine: self.matching_engine,
+            fills: self.fills,
+            new_utxos,
+            consumed_utxo_ids: self.consumed_utxo_ids,
+            cancelled_orders: self.cancelled_orders,
+            post_only_results: self.post_only_results,
+            amended_orders: self.amended_orders,
+            new_utxo_merkle_root: self.utxo_tree.root(),
+            new_utxo_leaf_count: self.utxo_tree.leaf_count(),
+            last_trade_price: self.last_trade_price,
+            oracle_round: self.oracle_round,
+            halted: self.halted,
+            net_transfers: transfers,
+            rejected_intents: self.rejected_intents,
+        }
+    }
+}
+
+/// Whether the buy takes liquidity from the sell. A market order is always the
+/// taker, otherwise the newer order (higher nonce) is, and the buy on a nonce tie.
+fn buy_is_taker(buy: &Order, sell: &Order) -> bool {
+    match (buy.order_type, sell.order_type) {
+        (OrderType::Market, _) => true,
+        (_, OrderType::Market) => false,
+        _ => buy.nonce >= sell.nonce,
+    }
+}
+
+/// Price a buy and a sell fill at: the clearing price if there is one, otherwise the
+/// maker's price
+fn fill_price(buy: &Order, sell: &Order, clearing_price: Option<u64>) -> u64 {
+    clearing_price.unwrap_or(if buy_is_taker(buy, sell) {
+        sell.price
+    } else {
+        buy.price
+    })
+}
+
+/// Whether a fill of `quantity` meets the minimum fill of both orders
+fn meets_min_fill(buy: &Order, sell: &Order, quantity: u64) -> bool {
+    quantity >= buy.min_fill() && quantity >= sell.min_fill()
+}
+
+/// Slots of the limit orders with quantity left at the best price of one side,
+/// leaving out orders in `out`
+fn best_level(orders: &Ladder, out: &BTreeSet<Slot>) -> Vec<Slot> {
+    orders
+        .levels()
+        .map(|(price, queue)| {
+            queue
+                .iter()
+                .enumerate()
+                .map(|(pos, utxo)| (Slot { price, pos }, utxo))
+                .filter(|(slot, utxo)| {
+                    utxo.order.order_type == OrderType::Limit
+                        && utxo.order.quantity > 0
+                        && !out.contains(slot)
+                })
+                .map(|(slot, _)| slot)
+                .collect::<Vec<Slot>>()
+        })
+        .find(|level| !level.is_empty())
+        .unwrap_or_default()
+}
+
+/// Split `traded` across orders in proportion to their sizes (rounded down), then
+/// hand out the rounding leftover one unit at a time in priority order.
+/// `traded` must not exceed the total size.
+fn pro_rata_allocation(sizes: &[u64], traded: u128) -> Vec<u64> {
+    let total: u128 = sizes.iter().map(|&q| u128::from(q)).sum();
+    if total == 0 {
+        return vec![0; sizes.len()];
+    }
+
+    // Each share is at most the order size, so it fits in a u64
+    let mut alloc: Vec<u64> = sizes
+        .iter()
+        .map(|&q| (traded * u128::from(q) / total) as u64)
+        .collect();
+
+    // Each share loses less than one unit to rounding, so the leftover is smaller than the
+    // number of orders, and a rounded-down share is always below its order size
+    let mut leftover = traded - alloc.iter().map(|&a| u128::from(a)).sum::<u128>();
+    for (share, &size) in alloc.iter_mut().zip(sizes) {
+        if leftover == 0 {
+            break;
+        }
+        if *share < size {
+            *share += 1;
+            leftover -= 1;
+        }
+    }
+    alloc
+}
+
+/// Compute the single price that clears a batch auction.
+///
+/// Candidates are the prices of all limit orders. Market orders count in the
+/// demand or supply at every candidate within their bound, an unbounded market buy
+/// being priced above every candidate, but never make a candidate themselves. The
+/// winner is chosen by:
+/// 1. maximum executable volume
+/// 2. minimum imbalance between demand and supply at that price
+/// 3. midpoint (rounded down) of the lowest and highest remaining candidates
+///
+/// Any price between two maximum-volume candidates also executes the maximum
+/// volume, so the midpoint never reduces the matched quantity.
+/// Returns `None` if no orders cross.
+///
+/// An order that no crossing order on the other side may trade with is left out,
+/// so a directed order cannot move the price without its counterparties. One that
+/// has an allowed counterparty counts in full, even if that counterparty is too
+/// small to fill it.
+fn uniform_clearing_price(buy_orders: &Ladder, sell_orders: &Ladder) -> Option<u64> {
+    let (buy_index, sell_index) = (
+        Counterparties::new(buy_orders),
+        Counterparties::new(sell_orders),
+    );
+    // Price and total quantity of every level with quantity left, lowest price first,
+    // and whether a limit order makes the price a candidate
+    let level_sizes = |orders: &Ladder, opposite: &Counterparties| -> Vec<(u64, u128, bool)> {
+        orders
+            .levels
+            .iter()
+            .map(|(&price, queue)| {
+                let counted = queue
+                    .iter()
+                    .filter(|u| u.order.quantity > 0 && opposite.can_fill(&u.order));
+                let (size, candidate) = counted.fold((0, false), |(size, candidate), u| {
+                    (
+                        size + u128::from(u.order.quantity),
+                        candidate || u.order.order_type == OrderType::Limit,
+                    )
+                });
+                (price, size, candidate)
+            })
+            .filter(|&(_, size, _)| size > 0)
+            .collect()
+    };
+    let (buys, sells) = (
+        level_sizes(buy_orders, &sell_index),
+        level_sizes(sell_orders, &buy_index),
+    );
+
+    let mut candidates: Vec<u64> = buys
+        .iter()
+        .chain(&sells)
+        .filter(|&&(_, _, candidate)| candidate)
+        .map(|&(price, _, _)| price)
+        .collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    // Demand is every buy at or above a candidate and supply every sell at or below
+    // it, updated level by level as the candidates rise
+    let mut demand: u128 = buys.iter().map(|&(_, size, _)| size).sum();
+    let mut supply: u128 = 0;
+    let (mut next_buy, mut next_sell) = (0, 0);
+
+    // (volume, imbalance, lowest price, highest price) of the best candidates so far
+    let mut best: Option<(u128, u128, u64, u64)> = None;
+    for price in candidates {
+        while next_buy < buys.len() && buys[next_buy].0 < price {
+            demand -= buys[next_buy].1;
+            next_buy += 1;
+        }
+        while next_sell < sells.len() && sells[next_sell].0 <= price {
+            supply += sells[next_sell].1;
+            next_sell += 1;
+        }
+        let volume = demand.min(supply);
+        if volume == 0 {
+            continue;
+        }
+        let imbalance = demand.abs_diff(supply);
+
+        best = match best {
+            Some((v, i, low, _)) if volume == v && imbalance == i => Some((v, i, low, price)),
+            Some((v, i, _, _)) if volume < v || (volume == v && imbalance > i) => best,
+            _ => Some((volume, imbalance, price, price)),
+        };
+    }
+
+    best.map(|(_, _, low, high)| low + (high - low) / 2)
+}
+
+/// One side of the book indexed by who its orders may trade with
+struct Counterparties<'a> {
+    side: Side,
+    /// Best price of the orders open to anyone
+    open: Option<u64>,
+    /// Best price of the directed orders allowing each owner
+    allowed: AddressMap<u64>,
+    /// Orders of each owner
+    by_owner: AddressMap<Vec<&'a Order>>,
+}
+
+impl<'a> Counterparties<'a> {
+    fn new(orders: &'a Ladder) -> Self {
+        let side = orders.side;
+        let better = |best: u64, price: u64| match side {
+            Side::Buy => best.max(price),
+            Side::Sell => best.min(price),
+        };
+        let mut index = Counterparties {
+            side,
+            open: None,
+            allowed: AddressMap::default(),
+            by_owner: AddressMap::default(),
+        };
+        for (_, utxo) in orders.iter() {
+            let order = &utxo.order;
+            if order.quantity == 0 {
+                continue;
+            }
+            if order.counterparties.is_empty() {
+                index.open = Some(
+                    index
+                        .open
+                        .map_or(order.price, |best| better(best, order.price)),
+                );
+            }
+            for &owner in &order.counterparties {
+                index
+                    .allowed
+                    .entry(owner)
+                    .and_modify(|best| *best = better(*best, order.price))
+                    .or_insert(order.price);
+            }
+            index.by_owner.entry(order.owner).or_default().push(order);
+        }
+        index
+    }
+
+    /// Whether `price` on this side crosses `limit` on the other
+    fn crosses(&self, price: u64, limit: u64) -> bool {
+        match self.side {
+            Side::Buy => price >= limit,
+            Side::Sell => price <= limit,
+        }
+    }
+
+    /// Whether some order on this side crosses `order` and may trade with it
+    fn can_fill(&self, order: &Order) -> bool {
+        if order.counterparties.is_empty() {
+            let crosses =
+                |best: Option<u64>| best.is_some_and(|best| self.crosses(best, order.price));
+            crosses(self.open) || crosses(self.allowed.get(&order.owner).copied())
+        } else {
+            order
+                .counterparties
+                .iter()
+                .filter_map(|owner| self.by_owner.get(owner))
+                .flatten()
+                .any(|other| self.crosses(other.price, order.price) && other.can_trade_with(order))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{new_orders_batch, order, ALICE, BOB};
+
+    const CAROL: alloy_primitives::Address = alloy_primitives::Address::repeat_byte(0xc0);
+
+    #[test]
+    fn test_uniform_clearing_price_prefers_volume_then_imbalance() {
+        let buys = ladder(
+            Side::Buy,
+            vec![
+                order(Side::Buy, 110, 10, ALICE, 1),
+                order(Side::Buy, 100, 30, ALICE, 2),
+            ],
+        );
+        let sells = ladder(
+            Side::Sell,
+            vec![
+                order(Side::Sell, 90, 20, BOB, 3),
+                order(Side::Sell, 100, 15, BOB, 4),
+            ],
+        );
+
+        // 90: 40 vs 20, 100: 40 vs 35, 110: 10 vs 35 -> 100 maximizes volume
+        assert_eq!(uniform_clearing_price(&buys, &sells), Some(100));
+        // Without the outer levels, 100 is the only candidate
+        let inner_buys = ladder(Side::Buy, vec![order(Side::Buy, 100, 30, ALICE, 2)]);
+        let inner_sells = ladder(Side::Sell, vec![order(Side::Sell, 100, 15, BOB, 4)]);
+        assert_eq!(uniform_clearing_price(&inner_buys, &inner_sells), Some(100));
+        assert_eq!(
+            uniform_clearing_price(&buys, &Ladder::new(Side::Sell)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_uniform_clearing_price_ignores_orders_without_allowed_counterparty() {
+        // ALICE's 110 buy only trades with CAROL, who has no sell in the book
+        let directed = Order {
+            counterparties: vec![CAROL],
+            ..order(Side::Buy, 110, 10, ALICE, 1)
+        };
+        let orders = vec![
+            directed.clone(),
+            order(Side::Buy, 100, 10, ALICE, 2),
+            order(Side::Sell, 90, 10, BOB, 3),
+        ];
+
+        // Counting the directed buy would clear at 110, above the open buy's limit,
+        // so nothing would trade. Without it, 90 and 100 both clear 10.
+        let buys = ladder(Side::Buy, orders[..2].to_vec());
+        let sells = ladder(Side::Sell, orders[2..].to_vec());
+        assert_eq!(uniform_clearing_price(&buys, &sells), Some(95));
+        let output = match_orders_with(BatchAuctionEngine, new_orders_batch(orders.clone()));
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!((output.fills[0].price, output.fills[0].quantity), (95, 10));
+
+        // Once CAROL sells, the directed buy counts again: every candidate from 90 to
+        // 110 clears 10 with an imbalance of 10
+        let carol = order(Side::Sell, 105, 10, CAROL, 4);
+        let sells = ladder(Side::Sell, vec![orders[2].clone(), carol]);
+        assert_eq!(uniform_clearing_price(&buys, &sells), Some(100));
+
+        // A sell that refuses every buyer is left out as well
+        let refusing = Order {
+            counterparties: vec![CAROL],
+            ..order(Side::Sell, 80, 50, BOB, 5)
+        };
+        let sells = ladder(Side::Sell, vec![orders[2].clone(), refusing]);
+        assert_eq!(uniform_clearing_price(&buys, &sells), Some(95));
+    }
+
+    #[test]
+    fn test_ladder_walks_best_price_then_nonce() {
+        let buys = ladder(
+            Side::Buy,
+            vec![
+                order(Side::Buy, 100, 10, ALICE, 3),
+                order(Side::Buy, 101, 10, ALICE, 4),
+                order(Side::Buy, 100, 10, BOB, 1),
+            ],
+        );
+
+        let walk: Vec<(u64, u64)> = buys
+            .iter()
+            .map(|(_, utxo)| (utxo.order.price, utxo.order.nonce))
+            .collect();
+        assert_eq!(walk, vec![(101, 4), (100, 1), (100, 3)]);
+
+        // `next` visits the same slots as `iter`
+        let mut slots = Vec::new();
+        let mut next = buys.next(None);
+        while let Some(slot) = next {
+            slots.push(slot);
+            next = buys.next(Some(slot));
+        }
+        let expected: Vec<Slot> = buys.iter().map(|(slot, _)| slot).collect();
+        assert_eq!(slots, expected);
+    }
+
+    #[test]
+    fn test_pro_rata_allocation_rounding() {
+        // 5 split over 3:3:1 -> 2.14, 2.14, 0.71 -> 2, 2, 0 plus one leftover to the oldest
+        assert_eq!(pro_rata_allocation(&[3, 3, 1], 5), vec![3, 2, 0]);
+        assert_eq!(pro_rata_allocation(&[3, 3, 1], 7), vec![3, 3, 1]);
+        assert_eq!(pro_rata_allocation(&[0, 5], 0), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_pro_rata_engine_splits_level_by_size() {
+        let input = BatchInput {
+            matching_engine: EngineKind::ProRata,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 30, ALICE, 1),
+                order(Side::Sell, 100, 10, BOB, 2),
+                order(Side::Buy, 100, 20, CAROL, 3),
+            ])
+        };
+
+        let output = match_orders_with(ProRataEngine, input);
+
+        let filled = |owner| -> u64 {
+            output
+                .fills
+                .iter()
+                .filter(|f| f.maker == owner)
+                .map(|f| f.quantity)
+                .sum()
+        };
+        assert_eq!(filled(ALICE), 15);
+        assert_eq!(filled(BOB), 5);
+        assert_eq!(output.new_utxos.len(), 2);
+    }
+
+    #[test]
+    fn test_pro_rata_engine_shares_level_with_market_orders() {
+        let market_buy = Order {
+            order_type: OrderType::Market,
+            ..order(Side::Buy, 0, 20, CAROL, 3)
+        };
+        let input = BatchInput {
+            matching_engine: EngineKind::ProRata,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 30, ALICE, 1),
+                order(Side::Sell, 100, 10, BOB, 2),
+                order(Side::Sell, 110, 10, BOB, 4),
+                market_buy,
+            ])
+        };
+
+        let output = match_orders_with(ProRataEngine, input);
+
+        // Price-time would give all 20 to ALICE; the level is split 30:10 instead
+        let filled = |owner| -> u64 {
+            output
+                .fills
+                .iter()
+                .filter(|f| f.maker == owner)
+                .map(|f| f.quantity)
+                .sum()
+        };
+        assert_eq!((filled(ALICE), filled(BOB)), (15, 5));
+        assert!(output
+            .fills
+            .iter()
+            .all(|f| f.price == 100 && f.taker == CAROL));
+        assert!(output.cancelled_orders.is_empty());
+    }
+
+    #[test]
+    fn test_batch_auction_fills_market_orders_at_clearing_price() {
+        let market_buy = Order {
+            order_type: OrderType::Market,
+            ..order(Side::Buy, 0, 20, CAROL, 3)
+        };
+        let input = BatchInput {
+            matching_engine: EngineKind::BatchAuction,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 10, ALICE, 1),
+                order(Side::Sell, 110, 10, BOB, 2),
+                market_buy,
+            ])
+        };
+
+        // Supply reaches the market buy's 20 at 110, so both sells fill there
+        let output = match_orders_with(BatchAuctionEngine, input);
+        assert_eq!(output.fills.len(), 2);
+        assert!(output.fills.iter().all(|f| f.price == 110));
+        assert_eq!(output.fills.iter().map(|f| f.quantity).sum::<u64>(), 20);
+        assert_eq!(output.last_trade_price, 110);
+
+        // A bounded market sell counts in the supply but is not a candidate price
+        let market_sell = Order {
+            order_type: OrderType::Market,
+            ..order(Side::Sell, 90, 5, ALICE, 2)
+        };
+        let output = match_orders_with(
+            BatchAuctionEngine,
+            BatchInput {
+                matching_engine: EngineKind::BatchAuction,
+                ..new_orders_batch(vec![order(Side::Buy, 100, 10, BOB, 1), market_sell])
+            },
+        );
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!((output.fills[0].price, output.fills[0].quantity), (100, 5));
+        assert_eq!(output.fills[0].taker, ALICE);
+    }
+
+    #[test]
+    fn test_pro_rata_engine_drops_share_below_min_fill() {
+        let input = BatchInput {
+            matching_engine: EngineKind::ProRata,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 30, ALICE, 1),
+                Order {
+                    min_fill_quantity: 8,
+                    ..order(Side::Sell, 100, 10, BOB, 2)
+                },
+                order(Side::Buy, 100, 20, CAROL, 3),
+            ])
+        };
+
+        let output = match_orders_with(ProRataEngine, input);
+
+        // BOB's share of 5 is below 8, so ALICE takes the whole level
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].maker, ALICE);
+        assert_eq!(output.fills[0].quantity, 20);
+    }
+
+    #[test]
+    fn test_price_time_engine_fills_oldest_first() {
+        let input = new_orders_batch(vec![
+            order(Side::Sell, 100, 30, ALICE, 1),
+            order(Side::Sell, 100, 10, BOB, 2),
+            order(Side::Buy, 100, 20, CAROL, 3),
+        ]);
+
+        let output = match_orders_with(PriceTimeEngine, input);
+
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].maker, ALICE);
+        assert_eq!(output.fills[0].quantity, 20);
+    }
+
+    fn ladder(side: Side, orders: Vec<Order>) -> Ladder {
+        let mut ladder = Ladder::new(side);
+        for order in orders {
+            ladder.insert(Utxo::new(order));
+        }
+        ladder
+    }
+
+    fn match_orders_with(engine: impl MatchingEngine, input: BatchInput) -> BatchOutput {
+        engine.match_batch(input).unwrap()
+    }
+}
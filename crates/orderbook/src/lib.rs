@@ -1,13 +1,21 @@
-use alloy_primitives::{Address, FixedBytes};
-use alloy_sol_types::sol;
-use core::cmp::Ordering;
+use alloy_primitives::{
+    aliases::U80,
+    map::{B256Set, HashMap},
+    Address, FixedBytes, Signature, I256, U256,
+};
+use alloy_sol_types::{sol, SolValue};
+use core::fmt;
 pub use risc0_steel::Commitment;
-use rs_merkle::{algorithms::Sha256 as MerkleSha256, MerkleProof, MerkleTree};
 use sha2::{Digest, Sha256};
 
+mod engine;
+
+pub use engine::{BatchAuctionEngine, MatchingEngine, PriceTimeEngine, ProRataEngine};
+
 /// Order side: Buy or Sell
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Side {
+    #[default]
     Buy,
     Sell,
 }
@@ -21,67 +29,814 @@ impl From<Side> for u8 {
     }
 }
 
-impl From<u8> for Side {
-    fn from(value: u8) -> Self {
-        if value == 0 {
-            Side::Buy
-        } else {
-            Self::Sell
+impl TryFrom<u8> for Side {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Side::Buy),
+            1 => Ok(Side::Sell),
+            _ => Err("invalid side"),
         }
     }
 }
 
-/// A limit order
-#[derive(Debug, Clone)]
+/// What to do when two orders from the same owner would trade with each other
+///
+/// The mode of the newer (taker) order applies, falling back to the market default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    /// Cancel the remaining quantity of the newer order
+    #[default]
+    CancelNewest,
+    /// Cancel the remaining quantity of the older order
+    CancelOldest,
+    /// Cancel the remaining quantity of both orders
+    CancelBoth,
+    /// Reduce both orders by the smaller remaining quantity, cancelling whichever reaches zero
+    DecrementAndCancel,
+}
+
+impl From<SelfTradePrevention> for u8 {
+    fn from(value: SelfTradePrevention) -> Self {
+        match value {
+            SelfTradePrevention::CancelNewest => 1,
+            SelfTradePrevention::CancelOldest => 2,
+            SelfTradePrevention::CancelBoth => 3,
+            SelfTradePrevention::DecrementAndCancel => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for SelfTradePrevention {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(SelfTradePrevention::CancelNewest),
+            2 => Ok(SelfTradePrevention::CancelOldest),
+            3 => Ok(SelfTradePrevention::CancelBoth),
+            4 => Ok(SelfTradePrevention::DecrementAndCancel),
+            _ => Err("invalid self-trade prevention mode"),
+        }
+    }
+}
+
+/// Encode an optional per-order mode (0 means "use the market default")
+fn encode_stp(mode: Option<SelfTradePrevention>) -> u8 {
+    mode.map_or(0, u8::from)
+}
+
+/// Decode an optional per-order mode (0 means "use the market default")
+fn decode_stp(value: u8) -> Result<Option<SelfTradePrevention>, &'static str> {
+    match value {
+        0 => Ok(None),
+        value => value.try_into().map(Some),
+    }
+}
+
+/// Matching engine a market clears its batches with (see [`MatchingEngine`])
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EngineKind {
+    /// Continuous price-time priority, each fill executes at the maker's price
+    #[default]
+    PriceTime,
+    /// Uniform-price batch auction, every fill executes at a single clearing price
+    BatchAuction,
+    /// Pro-rata allocation within each price level, fills execute at the maker's price
+    ProRata,
+}
+
+impl From<EngineKind> for u8 {
+    fn from(value: EngineKind) -> Self {
+        match value {
+            EngineKind::PriceTime => 0,
+            EngineKind::BatchAuction => 1,
+            EngineKind::ProRata => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for EngineKind {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(EngineKind::PriceTime),
+            1 => Ok(EngineKind::BatchAuction),
+            2 => Ok(EngineKind::ProRata),
+            _ => Err("invalid matching engine"),
+        }
+    }
+}
+
+/// How an order's price is applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderType {
+    /// Trade at the order's price or better, resting any remainder
+    #[default]
+    Limit,
+    /// Sweep resting liquidity up to a worst price (the order's price, 0 for no bound)
+    /// and never rest
+    Market,
+}
+
+impl From<OrderType> for u8 {
+    fn from(value: OrderType) -> Self {
+        match value {
+            OrderType::Limit => 0,
+            OrderType::Market => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for OrderType {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OrderType::Limit),
+            1 => Ok(OrderType::Market),
+            _ => Err("invalid order type"),
+        }
+    }
+}
+
+/// What to do with a post-only order that would take liquidity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PostOnlyPolicy {
+    /// Cancel the whole order
+    #[default]
+    Reject,
+    /// Move the order one tick behind the best older opposite price
+    Reprice,
+}
+
+impl From<PostOnlyPolicy> for u8 {
+    fn from(value: PostOnlyPolicy) -> Self {
+        match value {
+            PostOnlyPolicy::Reject => 0,
+            PostOnlyPolicy::Reprice => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for PostOnlyPolicy {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PostOnlyPolicy::Reject),
+            1 => Ok(PostOnlyPolicy::Reprice),
+            _ => Err("invalid post-only policy"),
+        }
+    }
+}
+
+/// What to do about prices outside the market's price band
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriceBandPolicy {
+    /// Cancel new limit orders priced outside the band, and stop market orders at its edge
+    #[default]
+    RejectOrders,
+    /// Match nothing in a batch that would fill outside the band
+    HaltMatching,
+}
+
+impl From<PriceBandPolicy> for u8 {
+    fn from(value: PriceBandPolicy) -> Self {
+        match value {
+            PriceBandPolicy::RejectOrders => 0,
+            PriceBandPolicy::HaltMatching => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for PriceBandPolicy {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PriceBandPolicy::RejectOrders),
+            1 => Ok(PriceBandPolicy::HaltMatching),
+            _ => Err("invalid price band policy"),
+        }
+    }
+}
+
+/// Band around the last trade price that prices must stay in (verified via Steel)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PriceBand {
+    /// Half-width of the band in basis points of the reference price (0 for no band)
+    pub band_bps: u16,
+    /// What to do about prices outside the band
+    pub policy: PriceBandPolicy,
+}
+
+impl PriceBand {
+    /// Lowest and highest price in the band around `reference_price`, rounded inwards,
+    /// or `None` without a band or before the first trade
+    pub fn bounds(&self, reference_price: u64) -> Option<(u64, u64)> {
+        if self.band_bps == 0 || reference_price == 0 {
+            return None;
+        }
+        let reference = u128::from(reference_price);
+        let bps = u128::from(self.band_bps);
+        let low = (reference * 10_000u128.saturating_sub(bps)).div_ceil(10_000);
+        let high = reference * (10_000 + bps) / 10_000;
+        Some((
+            u64::try_from(low).unwrap_or(u64::MAX),
+            u64::try_from(high).unwrap_or(u64::MAX),
+        ))
+    }
+}
+
+/// How long an order stays on the book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeInForce {
+    /// Rest on the book until filled or expired
+    #[default]
+    GoodTillCancel,
+    /// Fill what crosses in the submitting batch, cancel the remainder
+    ImmediateOrCancel,
+    /// Fill completely in the submitting batch or cancel the whole order
+    FillOrKill,
+}
+
+impl From<TimeInForce> for u8 {
+    fn from(value: TimeInForce) -> Self {
+        match value {
+            TimeInForce::GoodTillCancel => 0,
+            TimeInForce::ImmediateOrCancel => 1,
+            TimeInForce::FillOrKill => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for TimeInForce {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TimeInForce::GoodTillCancel),
+            1 => Ok(TimeInForce::ImmediateOrCancel),
+            2 => Ok(TimeInForce::FillOrKill),
+            _ => Err("invalid time in force"),
+        }
+    }
+}
+
+/// An order
+#[derive(Debug, Clone, Default)]
 pub struct Order {
+    /// Market the order trades in: the address of its pair's order book contract
+    pub market: Address,
     /// Buy or Sell
     pub side: Side,
-    /// Price in AssetB per AssetA (e.g., 100 means 100 AssetB for 1 AssetA)
+    /// Price mantissa in AssetB per AssetA: the price is `price * 10^price_exponent`
+    /// base units of AssetB per base unit of AssetA (e.g., 321 with exponent -6 is 0.000321).
+    /// For market orders this is the worst acceptable price, 0 for no bound.
+    /// For pegged orders this is the price of the latest oracle round.
     pub price: u64,
-    /// Quantity of AssetA to trade
+    /// Decimal exponent of `price`, which must be the market's
+    pub price_exponent: i8,
+    /// Quantity of AssetA to trade (the maximum quantity for market orders).
+    /// For iceberg orders this is the displayed slice. For quote buys the engine sets
+    /// it to what the budget buys at `price`, see [`Order::quantity_at`].
     pub quantity: u64,
+    /// Budget in base units of AssetB for a buy that spends an amount of AssetB
+    /// rather than buying a quantity of AssetA (0 for a quantity in AssetA)
+    pub quote_quantity: u128,
     /// Owner's Ethereum address
     pub owner: Address,
+    /// Owners the order may trade with, at most [`MAX_COUNTERPARTIES`] (empty for anyone)
+    pub counterparties: Vec<Address>,
     /// Unique nonce for this order (used for ordering and UTXO ID generation)
     pub nonce: u64,
     /// Batch number after which this order expires
     pub expiry_batch: u64,
+    /// Block timestamp in seconds after which this order expires (0 for good-till-batch only)
+    pub expiry_timestamp: u64,
+    /// Self-trade prevention mode, or `None` to use the market default
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    /// How long the order stays on the book
+    pub time_in_force: TimeInForce,
+    /// Only add liquidity: the order must never trade as the taker
+    pub post_only: bool,
+    /// Limit or market order
+    pub order_type: OrderType,
+    /// Smallest quantity of AssetA a single fill may have (0 for no minimum)
+    pub min_fill_quantity: u64,
+    /// Only fill the whole remaining quantity at once
+    pub all_or_none: bool,
+    /// Reserve of AssetA hidden behind the displayed quantity (iceberg orders)
+    pub hidden_quantity: u64,
+    /// Size of each slice displayed from the reserve (0 displays the whole reserve)
+    pub display_quantity: u64,
+    /// Last trade price that activates a stop order (0 for an order that is always active)
+    pub trigger_price: u64,
+    /// Whether a stop order's trigger price has been crossed
+    pub triggered: bool,
+    /// Price the order at the oracle price plus `peg_offset` every batch
+    pub pegged: bool,
+    /// Offset from the oracle price for pegged orders, in price units
+    pub peg_offset: i64,
+    /// Random blinding salt covered by the UTXO ID, so the ID of a published order
+    /// cannot be matched against guesses at its hidden reserve. Never published.
+    pub salt: FixedBytes<32>,
 }
 
 impl Order {
-    /// Compute the UTXO ID for this order (hash of all fields)
+    /// Compute the ID of this order's first UTXO (hash of all fields and a fresh
+    /// lineage, see [`Utxo::new`])
     pub fn compute_utxo_id(&self) -> FixedBytes<32> {
-        let mut hasher = Sha256::new();
-        hasher.update([self.side.into()]);
-        hasher.update(self.price.to_le_bytes());
-        hasher.update(self.quantity.to_le_bytes());
-        hasher.update(self.owner.as_slice());
-        hasher.update(self.nonce.to_le_bytes());
-        hasher.update(self.expiry_batch.to_le_bytes());
-        FixedBytes::from_slice(&hasher.finalize())
+        let (original_quantity, original_quote_quantity) = self.original_size();
+        hash_utxo(
+            self,
+            FixedBytes::ZERO,
+            original_quantity,
+            original_quote_quantity,
+            0,
+        )
+    }
+
+    /// Original quantity and quote budget of the order's first UTXO. A quote buy is
+    /// sized by its budget alone, as the quantity it buys depends on the fill prices.
+    fn original_size(&self) -> (u64, u128) {
+        if self.quote_quantity > 0 {
+            (0, self.quote_quantity)
+        } else {
+            (self.total_quantity(), 0)
+        }
+    }
+
+    /// Quantity left in the order, displayed and hidden
+    pub fn total_quantity(&self) -> u64 {
+        self.quantity.saturating_add(self.hidden_quantity)
+    }
+
+    /// Quantity of AssetA the order can trade at `price`. For a quote buy this is the
+    /// largest multiple of `lot_size` whose [`notional`] at `price` fits the budget,
+    /// and nothing without a price. Any other order has its quantity.
+    pub fn quantity_at(&self, price: u64, lot_size: u64) -> u64 {
+        if self.quote_quantity == 0 {
+            return self.quantity;
+        }
+        if price == 0 {
+            return 0;
+        }
+        let budget = U256::from(self.quote_quantity);
+        let price = U256::from(price);
+        let scale = U256::from(10u8).saturating_pow(U256::from(self.price_exponent.unsigned_abs()));
+        // The notional is rounded down, so with a negative exponent it fits the budget
+        // as long as the exact value is below one more unit
+        let quantity = if self.price_exponent >= 0 {
+            budget / price.saturating_mul(scale)
+        } else {
+            ((budget + U256::from(1u8)).saturating_mul(scale) - U256::from(1u8)) / price
+        };
+        let quantity: u64 = quantity.saturating_to();
+        if lot_size == 0 {
+            quantity
+        } else {
+            quantity - quantity % lot_size
+        }
+    }
+
+    /// The order as shown on the public book: the displayed slice without the reserve
+    /// or the salt
+    pub fn displayed(&self) -> Order {
+        Order {
+            hidden_quantity: 0,
+            display_quantity: 0,
+            salt: FixedBytes::ZERO,
+            ..self.clone()
+        }
+    }
+
+    /// Whether this order and `other` each allow the other's owner as a counterparty
+    pub fn can_trade_with(&self, other: &Order) -> bool {
+        let allows = |order: &Order, owner: &Address| {
+            order.counterparties.is_empty() || order.counterparties.contains(owner)
+        };
+        allows(self, &other.owner) && allows(other, &self.owner)
+    }
+
+    /// Whether this is a stop order still waiting for its trigger price
+    pub fn is_dormant(&self) -> bool {
+        self.trigger_price > 0 && !self.triggered
+    }
+
+    /// Whether a last trade at `last_trade_price` (0 before the first trade) crosses this
+    /// order's trigger price: at or above it for a buy, at or below it for a sell
+    pub fn is_triggered_by(&self, last_trade_price: u64) -> bool {
+        if self.trigger_price == 0 || last_trade_price == 0 {
+            return false;
+        }
+        match self.side {
+            Side::Buy => last_trade_price >= self.trigger_price,
+            Side::Sell => last_trade_price <= self.trigger_price,
+        }
+    }
+
+    /// Price of a pegged order at the given oracle price, rounded to the tick on the
+    /// passive side (down for a buy, up for a sell), or `None` if it is not a
+    /// positive `u64`
+    pub fn pegged_price(&self, oracle_price: u64, tick_size: u64) -> Option<u64> {
+        let price = i128::from(oracle_price) + i128::from(self.peg_offset);
+        let price = u64::try_from(price).ok()?;
+        let tick = tick_size.max(1);
+        let price = match self.side {
+            Side::Buy => price - price % tick,
+            Side::Sell => price.checked_next_multiple_of(tick)?,
+        };
+        Some(price).filter(|&price| price > 0)
+    }
+
+    /// Smallest quantity a single fill of this order may have
+    pub fn min_fill(&self) -> u64 {
+        if self.all_or_none {
+            self.quantity
+        } else {
+            self.min_fill_quantity
+        }
+    }
+}
+
+/// Oldest an oracle round may be, in seconds before the block, to price pegged orders
+pub const MAX_ORACLE_AGE: u64 = 3_600;
+
+/// Latest round of the market's Chainlink price feed, read via Steel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OracleRound {
+    /// Chainlink round ID (never 0)
+    pub round_id: U80,
+    /// Feed answer as a price mantissa at the market's price exponent
+    pub price: u64,
+}
+
+impl OracleRound {
+    /// Take the round from a `latestRoundData` answer with the feed's `decimals`,
+    /// scaled to the market's `price_exponent` and rounded down. The round is unusable
+    /// if its ID is zero, it was last updated more than [`MAX_ORACLE_AGE`] seconds
+    /// before `block_timestamp`, or the scaled answer is not a positive `u64`.
+    pub fn from_answer(
+        round_id: U80,
+        answer: I256,
+        updated_at: U256,
+        decimals: u8,
+        price_exponent: i8,
+        block_timestamp: u64,
+    ) -> Result<Self, &'static str> {
+        if round_id.is_zero() {
+            return Err("oracle round ID is zero");
+        }
+        if updated_at.is_zero()
+            || U256::from(block_timestamp).saturating_sub(updated_at) > U256::from(MAX_ORACLE_AGE)
+        {
+            return Err("oracle round is stale");
+        }
+        if answer <= I256::ZERO {
+            return Err("oracle answer is not positive");
+        }
+        // answer * 10^-decimals = price * 10^price_exponent
+        let answer = answer.into_raw();
+        let shift = -i32::from(decimals) - i32::from(price_exponent);
+        let scale = U256::from(10u8).checked_pow(U256::from(shift.unsigned_abs()));
+        let price = match (shift >= 0, scale) {
+            (true, Some(scale)) => answer.checked_mul(scale),
+            (true, None) => None,
+            (false, Some(scale)) => Some(answer / scale),
+            (false, None) => Some(U256::ZERO),
+        };
+        let price = price
+            .and_then(|price| u64::try_from(price).ok())
+            .ok_or("oracle answer out of range")?;
+        if price == 0 {
+            return Err("oracle answer rounds to zero");
+        }
+        Ok(OracleRound { round_id, price })
+    }
+}
+
+/// Largest fee rate a market can charge: the whole notional
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+/// Maker and taker fee rates of a market, in basis points of the notional (verified via Steel)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeSchedule {
+    /// Fee charged to the maker of a fill
+    pub maker_fee_bps: u16,
+    /// Fee charged to the taker of a fill
+    pub taker_fee_bps: u16,
+}
+
+impl FeeSchedule {
+    /// Maker fee in AssetB on a fill worth `notional`
+    pub fn maker_fee(&self, notional: U256) -> U256 {
+        fee_on(notional, self.maker_fee_bps)
+    }
+
+    /// Taker fee in AssetB on a fill worth `notional`
+    pub fn taker_fee(&self, notional: U256) -> U256 {
+        fee_on(notional, self.taker_fee_bps)
+    }
+}
+
+/// `bps` of `notional`, rounded down to a whole unit of AssetB.
+/// Split around 10 000 so that it cannot overflow for any notional.
+fn fee_on(notional: U256, bps: u16) -> U256 {
+    let (bps, basis) = (U256::from(bps), U256::from(10_000u16));
+    let (whole, rest) = (notional / basis, notional % basis);
+    whole * bps + rest * bps / basis
+}
+
+/// Largest magnitude of a market's price exponent
+pub const MAX_PRICE_EXPONENT: i8 = 18;
+
+/// Most counterparties a directed order can name
+pub const MAX_COUNTERPARTIES: usize = 16;
+
+/// Value in base units of AssetB of `quantity` base units of AssetA at
+/// `price * 10^price_exponent`, rounded down. The contract settles fills with exactly
+/// this amount.
+pub fn notional(price: u64, quantity: u64, price_exponent: i8) -> U256 {
+    let value = U256::from(price) * U256::from(quantity);
+    let scale = U256::from(10u8).saturating_pow(U256::from(price_exponent.unsigned_abs()));
+    if price_exponent >= 0 {
+        value.saturating_mul(scale)
+    } else {
+        value / scale
+    }
+}
+
+/// Sizes new orders must conform to (verified via Steel). A zero field places no constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MarketParams {
+    /// Prices must be a multiple of this
+    pub tick_size: u64,
+    /// Quantities must be a multiple of this
+    pub lot_size: u64,
+    /// Smallest total quantity (displayed plus hidden) of an order
+    pub min_quantity: u64,
+    /// Smallest value of an order in AssetB (its [`notional`] at its total quantity)
+    pub min_notional: u128,
+    /// Decimal exponent of every price in the market, at most [`MAX_PRICE_EXPONENT`] in magnitude
+    pub price_exponent: i8,
+}
+
+impl MarketParams {
+    /// Check a new order, returning the reason it is rejected if it does not conform.
+    ///
+    /// The tick applies to the limit price (or worst-price bound), the trigger price and
+    /// the peg offset. Orders without a price (unbounded market orders) are exempt from
+    /// the minimum notional.
+    pub fn check(&self, order: &Order) -> Result<(), CancelReason> {
+        if order.price_exponent != self.price_exponent {
+            return Err(CancelReason::PriceExponent);
+        }
+
+        let on_tick = |value: u64| self.tick_size == 0 || value.is_multiple_of(self.tick_size);
+        if !on_tick(order.price)
+            || !on_tick(order.trigger_price)
+            || !on_tick(order.peg_offset.unsigned_abs())
+        {
+            return Err(CancelReason::OffTick);
+        }
+
+        // The slices an iceberg shows and the smallest fill must be whole lots as well
+        let on_lot = |value: u64| self.lot_size == 0 || value.is_multiple_of(self.lot_size);
+        if !on_lot(order.quantity)
+            || !on_lot(order.hidden_quantity)
+            || !on_lot(order.display_quantity)
+            || !on_lot(order.min_fill_quantity)
+        {
+            return Err(CancelReason::OffLot);
+        }
+
+        let total = order.quantity.saturating_add(order.hidden_quantity);
+        if order.quantity == 0 || total < self.min_quantity {
+            return Err(CancelReason::BelowMinQuantity);
+        }
+
+        if order.price > 0
+            && notional(order.price, total, order.price_exponent) < U256::from(self.min_notional)
+        {
+            return Err(CancelReason::BelowMinNotional);
+        }
+        Ok(())
+    }
+}
+
+/// Encode an optional oracle round (round ID 0 means "no oracle")
+fn encode_oracle_round(round: Option<OracleRound>) -> SolOracleRound {
+    let round = round.unwrap_or(OracleRound {
+        round_id: U80::ZERO,
+        price: 0,
+    });
+    SolOracleRound {
+        roundId: round.round_id,
+        price: round.price,
+    }
+}
+
+/// Decode an optional oracle round (round ID 0 means "no oracle")
+fn decode_oracle_round(sol: &SolOracleRound) -> Option<OracleRound> {
+    (!sol.roundId.is_zero()).then_some(OracleRound {
+        round_id: sol.roundId,
+        price: sol.price,
+    })
+}
+
+/// SHA-256 of the order data followed by the UTXO lineage
+fn hash_utxo(
+    order: &Order,
+    parent_utxo_id: FixedBytes<32>,
+    original_quantity: u64,
+    original_quote_quantity: u128,
+    filled_quantity: u64,
+) -> FixedBytes<32> {
+    let mut hasher = Sha256::new();
+    hasher.update(order.market.as_slice());
+    hasher.update([order.side.into()]);
+    hasher.update(order.price.to_le_bytes());
+    hasher.update(order.price_exponent.to_le_bytes());
+    hasher.update(order.quantity.to_le_bytes());
+    hasher.update(order.quote_quantity.to_le_bytes());
+    hasher.update(order.owner.as_slice());
+    hasher.update((order.counterparties.len() as u64).to_le_bytes());
+    for counterparty in &order.counterparties {
+        hasher.update(counterparty.as_slice());
     }
+    hasher.update(order.nonce.to_le_bytes());
+    hasher.update(order.expiry_batch.to_le_bytes());
+    hasher.update(order.expiry_timestamp.to_le_bytes());
+    hasher.update([encode_stp(order.self_trade_prevention)]);
+    hasher.update([order.time_in_force.into()]);
+    hasher.update([u8::from(order.post_only)]);
+    hasher.update([order.order_type.into()]);
+    hasher.update(order.min_fill_quantity.to_le_bytes());
+    hasher.update([u8::from(order.all_or_none)]);
+    hasher.update(order.hidden_quantity.to_le_bytes());
+    hasher.update(order.display_quantity.to_le_bytes());
+    hasher.update(order.trigger_price.to_le_bytes());
+    hasher.update([u8::from(order.triggered)]);
+    hasher.update([u8::from(order.pegged)]);
+    hasher.update(order.peg_offset.to_le_bytes());
+    hasher.update(order.salt.as_slice());
+    hasher.update(parent_utxo_id.as_slice());
+    hasher.update(original_quantity.to_le_bytes());
+    hasher.update(original_quote_quantity.to_le_bytes());
+    hasher.update(filled_quantity.to_le_bytes());
+    FixedBytes::from_slice(&hasher.finalize())
 }
 
-/// A UTXO representing an unfilled or partially filled order
+/// A UTXO representing an unfilled or partially filled order.
+///
+/// Every UTXO after an order's first links to the one it replaces, so an order can be
+/// followed from batch to batch through the journal alone.
 #[derive(Debug, Clone)]
 pub struct Utxo {
-    /// Unique identifier (hash of original order data)
+    /// Unique identifier (hash of the order data and the lineage below)
     pub id: FixedBytes<32>,
     /// The order data
     pub order: Order,
+    /// ID of the UTXO this one replaces (zero for an order's first UTXO)
+    pub parent_utxo_id: FixedBytes<32>,
+    /// Total quantity the order was placed with, or last amended to plus what had
+    /// filled by then. 0 for a quote buy, whose size is its budget.
+    pub original_quantity: u64,
+    /// Budget in AssetB a quote buy was placed with (0 for any other order). What is
+    /// left of it is the order's `quote_quantity`.
+    pub original_quote_quantity: u128,
+    /// Quantity of the order filled across all batches so far, in AssetA. A quote buy
+    /// that fills below its limit price buys more than its budget buys at that price.
+    pub filled_quantity: u64,
 }
 
 impl Utxo {
-    /// Create a new UTXO from an order
+    /// Create the first UTXO of a new order
     pub fn new(order: Order) -> Self {
-        let id = order.compute_utxo_id();
-        Self { id, order }
+        let (original_quantity, original_quote_quantity) = order.original_size();
+        Self::with_lineage(
+            order,
+            FixedBytes::ZERO,
+            original_quantity,
+            original_quote_quantity,
+            0,
+        )
+    }
+
+    /// Create a UTXO with the given lineage
+    pub fn with_lineage(
+        order: Order,
+        parent_utxo_id: FixedBytes<32>,
+        original_quantity: u64,
+        original_quote_quantity: u128,
+        filled_quantity: u64,
+    ) -> Self {
+        let id = hash_utxo(
+            &order,
+            parent_utxo_id,
+            original_quantity,
+            original_quote_quantity,
+            filled_quantity,
+        );
+        Self {
+            id,
+            order,
+            parent_utxo_id,
+            original_quantity,
+            original_quote_quantity,
+            filled_quantity,
+        }
+    }
+
+    /// Hash of the order data and lineage, which a valid UTXO has as its ID
+    pub fn compute_id(&self) -> FixedBytes<32> {
+        hash_utxo(
+            &self.order,
+            self.parent_utxo_id,
+            self.original_quantity,
+            self.original_quote_quantity,
+            self.filled_quantity,
+        )
+    }
+
+    /// The UTXO replacing this one once its order data has changed, with `filled`
+    /// more of the order filled since this UTXO was created
+    pub fn successor(&self, filled: u64) -> Utxo {
+        Utxo::with_lineage(
+            self.order.clone(),
+            self.id,
+            self.original_quantity,
+            self.original_quote_quantity,
+            self.filled_quantity.saturating_add(filled),
+        )
     }
 
-    /// Check if this UTXO is expired at the given batch
-    pub fn is_expired(&self, current_batch: u64) -> bool {
+    /// Check if this UTXO is expired at the given batch, cleared against a block with
+    /// the given timestamp
+    pub fn is_expired(&self, current_batch: u64, block_timestamp: u64) -> bool {
         self.order.expiry_batch < current_batch
+            || (self.order.expiry_timestamp != 0 && self.order.expiry_timestamp < block_timestamp)
+    }
+
+    /// The UTXO as published on the public book. The ID still commits to any
+    /// hidden reserve and to the salt, but the order data shows only the displayed
+    /// slice, and the original quantity is withheld while a reserve is left.
+    pub fn to_public_sol(&self) -> SolUtxo {
+        SolUtxo {
+            id: self.id,
+            order: SolOrder::from(&self.order.displayed()),
+            parentUtxoId: self.parent_utxo_id,
+            originalQuantity: if self.order.hidden_quantity > 0 {
+                0
+            } else {
+                self.original_quantity
+            },
+            originalQuoteQuantity: self.original_quote_quantity,
+            filledQuantity: self.filled_quantity,
+        }
+    }
+}
+
+/// Displayed quantity resting at one price on one side of the book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookLevel {
+    /// Buy or Sell
+    pub side: Side,
+    /// Price of the level
+    pub price: u64,
+    /// Total displayed quantity of AssetA at this price
+    pub quantity: u64,
+}
+
+/// Public depth of a UTXO set: displayed quantity per price level, bids then asks,
+/// best price first. Hidden iceberg reserves are never included.
+pub fn book_snapshot(utxos: &[Utxo]) -> Vec<BookLevel> {
+    let mut orders: Vec<&Order> = utxos.iter().map(|u| &u.order).collect();
+    orders.sort_by(|a, b| match (a.side, b.side) {
+        (Side::Buy, Side::Sell) => core::cmp::Ordering::Less,
+        (Side::Sell, Side::Buy) => core::cmp::Ordering::Greater,
+        (Side::Buy, Side::Buy) => b.price.cmp(&a.price),
+        (Side::Sell, Side::Sell) => a.price.cmp(&b.price),
+    });
+
+    let mut levels: Vec<BookLevel> = Vec::new();
+    for order in orders {
+        match levels.last_mut() {
+            Some(level) if level.side == order.side && level.price == order.price => {
+                level.quantity = level.quantity.saturating_add(order.quantity);
+            }
+            _ => levels.push(BookLevel {
+                side: order.side,
+                price: order.price,
+                quantity: order.quantity,
+            }),
+        }
     }
+    levels
 }
 
 /// A UTXO with its Merkle proof for on-chain verification
@@ -89,7 +844,7 @@ impl Utxo {
 pub struct UtxoWithProof {
     /// The UTXO data
     pub utxo: Utxo,
-    /// Merkle proof (hashes in the proof path)
+    /// Merkle proof (hashes in the proof path), see [`UtxoTree::proof`]
     pub proof_hashes: Vec<[u8; 32]>,
     /// Index of this UTXO in the Merkle tree
     pub leaf_index: usize,
@@ -97,53 +852,167 @@ pub struct UtxoWithProof {
 
 impl UtxoWithProof {
     /// Verify this UTXO against a Merkle root
-    pub fn verify(&self, root: &FixedBytes<32>, total_leaves: usize) -> bool {
-        let proof = MerkleProof::<MerkleSha256>::new(self.proof_hashes.clone());
+    pub fn verify(&self, root: &FixedBytes<32>) -> bool {
         // Use UTXO ID directly as leaf (it's already a hash)
-        let leaf: [u8; 32] = self.utxo.id.0;
-        proof.verify(
-            root.as_slice().try_into().unwrap_or([0u8; 32]),
-            &[self.leaf_index],
-            &[leaf],
-            total_leaves,
+        UtxoTree::from_root(*root, 0).insert_proof(
+            self.leaf_index as u64,
+            self.utxo.id,
+            &self.proof_hashes,
         )
     }
 }
 
-/// Compute Merkle root from a list of UTXO IDs
-pub fn compute_utxo_merkle_root(utxo_ids: &[FixedBytes<32>]) -> FixedBytes<32> {
-    if utxo_ids.is_empty() {
-        return FixedBytes::ZERO;
-    }
+/// Depth of the UTXO Merkle tree, which has room for 2^32 leaves
+pub const UTXO_TREE_DEPTH: usize = 32;
 
-    // Use UTXO IDs directly as leaves (they're already hashes)
-    let leaves: Vec<[u8; 32]> = utxo_ids.iter().map(|id| id.0).collect();
+/// Hash two sibling nodes. A node over no UTXOs is zero, so the tree of an empty book
+/// has the zero root a market starts with.
+fn hash_utxo_nodes(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    if left == &[0u8; 32] && right == &[0u8; 32] {
+        return [0u8; 32];
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
 
-    let tree = MerkleTree::<MerkleSha256>::from_leaves(&leaves);
-    let root = tree.root().unwrap_or([0u8; 32]);
-    FixedBytes::from_slice(&root)
+/// Sparse Merkle tree over the UTXO IDs of a market. A UTXO keeps the leaf it was
+/// created at: consuming it zeroes the leaf (a tombstone), and new UTXOs are appended
+/// after the last leaf. A batch therefore updates the root along the paths of the
+/// leaves it touches, and every other UTXO keeps its index.
+///
+/// A tree started from a root only knows the nodes of the proofs inserted into it,
+/// which is all that changing those leaves and appending after the last one needs.
+#[derive(Debug, Clone, Default)]
+pub struct UtxoTree {
+    /// Known nodes by level (0 for leaves) and index within the level. A node that is
+    /// not stored is zero.
+    nodes: HashMap<(usize, u64), [u8; 32]>,
+    /// Number of leaves appended so far, tombstones included
+    leaf_count: u64,
 }
 
-/// Build a Merkle tree from UTXOs and return the tree for proof generation
-pub fn build_utxo_merkle_tree(utxos: &[Utxo]) -> (MerkleTree<MerkleSha256>, FixedBytes<32>) {
-    if utxos.is_empty() {
-        return (MerkleTree::<MerkleSha256>::new(), FixedBytes::ZERO);
+impl UtxoTree {
+    /// Build the full tree over the given leaves, zero for a tombstone
+    pub fn from_leaves(leaves: &[FixedBytes<32>]) -> Self {
+        let mut tree = Self::default();
+        for leaf in leaves {
+            tree.push(*leaf);
+        }
+        tree
+    }
+
+    /// Start a tree from its root and leaf count, whose leaves are then learnt from
+    /// their proofs with [`UtxoTree::insert_proof`]
+    pub fn from_root(root: FixedBytes<32>, leaf_count: u64) -> Self {
+        let mut tree = Self {
+            leaf_count,
+            ..Default::default()
+        };
+        tree.set_node(UTXO_TREE_DEPTH, 0, root.0);
+        tree
     }
 
-    // Use UTXO IDs directly as leaves (they're already hashes)
-    let leaves: Vec<[u8; 32]> = utxos.iter().map(|utxo| utxo.id.0).collect();
+    /// Merkle root of the tree
+    pub fn root(&self) -> FixedBytes<32> {
+        FixedBytes(self.node(UTXO_TREE_DEPTH, 0))
+    }
 
-    let tree = MerkleTree::<MerkleSha256>::from_leaves(&leaves);
-    let root = tree.root().unwrap_or([0u8; 32]);
-    (tree, FixedBytes::from_slice(&root))
-}
+    /// Number of leaves, tombstones included. The next UTXO is appended at this index.
+    pub fn leaf_count(&self) -> u64 {
+        self.leaf_count
+    }
+
+    /// Merkle proof of the leaf at `leaf_index`: the sibling of each node on its path,
+    /// from the leaf up, leaving out the zero siblings above the last non-zero one
+    pub fn proof(&self, leaf_index: u64) -> Vec<[u8; 32]> {
+        let mut proof: Vec<[u8; 32]> = (0..UTXO_TREE_DEPTH)
+            .map(|level| self.node(level, (leaf_index >> level) ^ 1))
+            .collect();
+        while proof.last() == Some(&[0u8; 32]) {
+            proof.pop();
+        }
+        proof
+    }
+
+    /// Check that `leaf` is at `leaf_index` against the root, and learn the nodes on its
+    /// path. A proof that does not verify teaches nothing.
+    pub fn insert_proof(
+        &mut self,
+        leaf_index: u64,
+        leaf: FixedBytes<32>,
+        proof: &[[u8; 32]],
+    ) -> bool {
+        if leaf_index >> UTXO_TREE_DEPTH != 0 || proof.len() > UTXO_TREE_DEPTH {
+            return false;
+        }
+        let sibling = |level: usize| proof.get(level).copied().unwrap_or([0u8; 32]);
+        let mut path = Vec::with_capacity(UTXO_TREE_DEPTH);
+        let mut node = leaf.0;
+        for level in 0..UTXO_TREE_DEPTH {
+            path.push(node);
+            node = if (leaf_index >> level) & 1 == 0 {
+                hash_utxo_nodes(&node, &sibling(level))
+            } else {
+                hash_utxo_nodes(&sibling(level), &node)
+            };
+        }
+        if node != self.node(UTXO_TREE_DEPTH, 0) {
+            return false;
+        }
+        for (level, node) in path.into_iter().enumerate() {
+            let index = leaf_index >> level;
+            self.set_node(level, index, node);
+            self.set_node(level, index ^ 1, sibling(level));
+        }
+        true
+    }
+
+    /// Consume the UTXO at `leaf_index`, leaving a tombstone
+    pub fn remove(&mut self, leaf_index: u64) {
+        self.set_leaf(leaf_index, FixedBytes::ZERO);
+    }
+
+    /// Append a leaf after the last one and return its index
+    pub fn push(&mut self, leaf: FixedBytes<32>) -> u64 {
+        let leaf_index = self.leaf_count;
+        assert!(leaf_index >> UTXO_TREE_DEPTH == 0, "UTXO tree is full");
+        self.set_leaf(leaf_index, leaf);
+        self.leaf_count += 1;
+        leaf_index
+    }
+
+    /// Replace a leaf and rehash its path up to the root
+    fn set_leaf(&mut self, leaf_index: u64, leaf: FixedBytes<32>) {
+        let mut node = leaf.0;
+        for level in 0..UTXO_TREE_DEPTH {
+            let index = leaf_index >> level;
+            self.set_node(level, index, node);
+            let sibling = self.node(level, index ^ 1);
+            node = if index & 1 == 0 {
+                hash_utxo_nodes(&node, &sibling)
+            } else {
+                hash_utxo_nodes(&sibling, &node)
+            };
+        }
+        self.set_node(UTXO_TREE_DEPTH, 0, node);
+    }
 
-/// Generate a Merkle proof for a UTXO at a given index
-pub fn generate_utxo_proof(
-    tree: &MerkleTree<MerkleSha256>,
-    leaf_index: usize,
-) -> Option<Vec<[u8; 32]>> {
-    tree.proof(&[leaf_index]).proof_hashes().to_vec().into()
+    fn node(&self, level: usize, index: u64) -> [u8; 32] {
+        self.nodes
+            .get(&(level, index))
+            .copied()
+            .unwrap_or([0u8; 32])
+    }
+
+    fn set_node(&mut self, level: usize, index: u64, node: [u8; 32]) {
+        if node == [0u8; 32] {
+            self.nodes.remove(&(level, index));
+        } else {
+            self.nodes.insert((level, index), node);
+        }
+    }
 }
 
 /// A fill representing a matched trade
@@ -153,67 +1022,650 @@ pub struct Fill {
     pub maker_utxo_id: FixedBytes<32>,
     /// UTXO ID of the taker (newer order)
     pub taker_utxo_id: FixedBytes<32>,
-    /// Execution price (maker's price)
+    /// Execution price mantissa (maker's price)
     pub price: u64,
+    /// Decimal exponent of `price` (the market's)
+    pub price_exponent: i8,
     /// Quantity of AssetA traded
     pub quantity: u64,
+    /// AssetB paid for the fill, see [`notional`]
+    pub notional: U256,
     /// Maker's address
     pub maker: Address,
     /// Taker's address
     pub taker: Address,
     /// Whether maker is selling (true) or buying (false)
     pub maker_is_seller: bool,
+    /// Fee the maker pays in AssetB
+    pub maker_fee: U256,
+    /// Fee the taker pays in AssetB
+    pub taker_fee: U256,
 }
 
-/// Input to the batch matching process
-#[derive(Debug, Clone)]
-pub struct BatchInput {
-    /// Current batch index (must match on-chain for replay protection)
-    pub batch_index: u64,
-    /// Expected on-chain UTXO Merkle root (verified via Steel)
-    pub utxo_merkle_root: FixedBytes<32>,
-    /// Existing UTXOs with their Merkle proofs
-    pub existing_utxos_with_proofs: Vec<UtxoWithProof>,
-    /// New orders from this batch
-    pub new_orders: Vec<Order>,
+/// Net change in one account's balances over all fills of a batch. The contract
+/// settles these instead of the fills, with one transfer per account and asset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetTransfer {
+    /// Trader's address
+    pub account: Address,
+    /// AssetA received (positive) or paid (negative)
+    pub asset_a: I256,
+    /// AssetB received (positive) or paid (negative), net of fees
+    pub asset_b: I256,
+}
+
+/// Net the fills of a batch into one balance change per account, in order of each
+/// account's first fill. Accounts whose balances end up unchanged are left out.
+///
+/// Each side pays its own fee, the maker fee or the taker fee of [`Fill`], in AssetB.
+/// The seller receives the notional less its fee and the buyer pays the notional
+/// plus its fee, so AssetA sums to zero and AssetB to minus the fees, which the
+/// contract pays to its fee recipient.
+pub fn net_transfers(fills: &[Fill]) -> Vec<NetTransfer> {
+    let signed = |value: U256| I256::try_from(value).unwrap_or(I256::MAX);
+    let mut transfers: Vec<NetTransfer> = Vec::new();
+    let mut apply = |account: Address, asset_a: I256, asset_b: I256| {
+        let index = match transfers.iter().position(|t| t.account == account) {
+            Some(index) => index,
+            None => {
+                transfers.push(NetTransfer {
+                    account,
+                    asset_a: I256::ZERO,
+                    asset_b: I256::ZERO,
+                });
+                transfers.len() - 1
+            }
+        };
+        let transfer = &mut transfers[index];
+        transfer.asset_a = transfer.asset_a.saturating_add(asset_a);
+        transfer.asset_b = transfer.asset_b.saturating_add(asset_b);
+    };
+
+    for fill in fills {
+        let (seller, buyer, seller_fee, buyer_fee) = if fill.maker_is_seller {
+            (fill.maker, fill.taker, fill.maker_fee, fill.taker_fee)
+        } else {
+            (fill.taker, fill.maker, fill.taker_fee, fill.maker_fee)
+        };
+        let quantity = I256::try_from(fill.quantity).unwrap_or(I256::MAX);
+        let notional = signed(fill.notional);
+        apply(
+            seller,
+            -quantity,
+            notional.saturating_sub(signed(seller_fee)),
+        );
+        apply(buyer, quantity, -notional.saturating_add(signed(buyer_fee)));
+    }
+
+    transfers.retain(|t| !t.asset_a.is_zero() || !t.asset_b.is_zero());
+    transfers
+}
+
+/// Reason an order left the book with quantity still unfilled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// Removed by self-trade prevention
+    SelfTrade,
+    /// Unfilled remainder of an immediate-or-cancel order
+    ImmediateOrCancel,
+    /// Fill-or-kill order that could not be filled completely
+    FillOrKill,
+    /// Post-only order that would have taken liquidity
+    PostOnly,
+    /// Unfilled remainder of a market order
+    MarketRemainder,
+    /// Cancelled by an owner-signed cancellation
+    OwnerRequest,
+    /// New order with a price (or trigger price, or peg offset) off the market's tick size
+    OffTick,
+    /// New order with a quantity off the market's lot size
+    OffLot,
+    /// New order smaller than the market's minimum quantity, or empty
+    BelowMinQuantity,
+    /// New order worth less than the market's minimum notional
+    BelowMinNotional,
+    /// New order with a price exponent other than the market's
+    PriceExponent,
+    /// New limit order priced outside the market's price band
+    PriceBand,
+}
+
+impl From<CancelReason> for u8 {
+    fn from(value: CancelReason) -> Self {
+        match value {
+            CancelReason::SelfTrade => 0,
+            CancelReason::ImmediateOrCancel => 1,
+            CancelReason::FillOrKill => 2,
+            CancelReason::PostOnly => 3,
+            CancelReason::MarketRemainder => 4,
+            CancelReason::OwnerRequest => 5,
+            CancelReason::OffTick => 6,
+            CancelReason::OffLot => 7,
+            CancelReason::BelowMinQuantity => 8,
+            CancelReason::BelowMinNotional => 9,
+            CancelReason::PriceExponent => 10,
+            CancelReason::PriceBand => 11,
+        }
+    }
+}
+
+impl TryFrom<u8> for CancelReason {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CancelReason::SelfTrade),
+            1 => Ok(CancelReason::ImmediateOrCancel),
+            2 => Ok(CancelReason::FillOrKill),
+            3 => Ok(CancelReason::PostOnly),
+            4 => Ok(CancelReason::MarketRemainder),
+            5 => Ok(CancelReason::OwnerRequest),
+            6 => Ok(CancelReason::OffTick),
+            7 => Ok(CancelReason::OffLot),
+            8 => Ok(CancelReason::BelowMinQuantity),
+            9 => Ok(CancelReason::BelowMinNotional),
+            10 => Ok(CancelReason::PriceExponent),
+            11 => Ok(CancelReason::PriceBand),
+            _ => Err("invalid cancel reason"),
+        }
+    }
+}
+
+/// Quantity removed from an order without being filled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelledOrder {
+    /// UTXO ID of the order (the original order ID for new orders)
+    pub utxo_id: FixedBytes<32>,
+    /// Owner of the order
+    pub owner: Address,
+    /// Quantity of AssetA removed from the order
+    pub quantity: u64,
+    /// Why the quantity was removed
+    pub reason: CancelReason,
+}
+
+/// Kind of owner intent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntentKind {
+    Cancellation,
+    Amendment,
+}
+
+impl From<IntentKind> for u8 {
+    fn from(value: IntentKind) -> Self {
+        match value {
+            IntentKind::Cancellation => 0,
+            IntentKind::Amendment => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for IntentKind {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(IntentKind::Cancellation),
+            1 => Ok(IntentKind::Amendment),
+            _ => Err("invalid intent kind"),
+        }
+    }
+}
+
+/// Reason an owner intent was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntentRejection {
+    /// Not signed by the owner of the named UTXO
+    BadSignature,
+    /// No open UTXO with the named ID: unknown, expired, or already cancelled or
+    /// amended by an earlier intent in the batch
+    NotOpen,
+    /// Amendment that changes nothing, sets a zero quantity, or amends a quote buy
+    InvalidAmendment,
+    /// Replacement does not meet the market parameters
+    NonConforming,
+    /// Replacement limit price is outside the price band
+    PriceBand,
+}
+
+impl From<IntentRejection> for u8 {
+    fn from(value: IntentRejection) -> Self {
+        match value {
+            IntentRejection::BadSignature => 0,
+            IntentRejection::NotOpen => 1,
+            IntentRejection::InvalidAmendment => 2,
+            IntentRejection::NonConforming => 3,
+            IntentRejection::PriceBand => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for IntentRejection {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(IntentRejection::BadSignature),
+            1 => Ok(IntentRejection::NotOpen),
+            2 => Ok(IntentRejection::InvalidAmendment),
+            3 => Ok(IntentRejection::NonConforming),
+            4 => Ok(IntentRejection::PriceBand),
+            _ => Err("invalid intent rejection"),
+        }
+    }
+}
+
+/// Owner intent that was dropped instead of applied, leaving its UTXO as it was
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedIntent {
+    /// UTXO the intent named
+    pub utxo_id: FixedBytes<32>,
+    /// Cancellation or amendment
+    pub kind: IntentKind,
+    /// Why it was dropped
+    pub reason: IntentRejection,
+}
+
+/// Owner-signed request to cancel a resting UTXO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cancellation {
+    /// ID of the UTXO to cancel
+    pub utxo_id: FixedBytes<32>,
+    /// EIP-191 signature of the UTXO ID by the order owner
+    pub signature: Signature,
+}
+
+impl Cancellation {
+    /// Check that the signature was made by `owner`
+    pub fn verify(&self, owner: Address) -> Result<(), &'static str> {
+        verify_signer(&self.signature, self.utxo_id, owner)
+    }
+}
+
+/// Owner-signed request to change the price or quantity of a resting UTXO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amendment {
+    /// ID of the UTXO to amend
+    pub utxo_id: FixedBytes<32>,
+    /// New price
+    pub price: u64,
+    /// New (displayed) quantity
+    pub quantity: u64,
+    /// EIP-191 signature of `abi.encodePacked(utxoId, price, quantity)` by the order owner
+    pub signature: Signature,
+}
+
+impl Amendment {
+    /// Message signed by the order owner
+    pub fn message(&self) -> Vec<u8> {
+        (self.utxo_id, self.price, self.quantity).abi_encode_packed()
+    }
+
+    /// Check that the signature was made by `owner`
+    pub fn verify(&self, owner: Address) -> Result<(), &'static str> {
+        verify_signer(&self.signature, self.message(), owner)
+    }
+
+    /// Return the replacement order, and whether it keeps the time priority of
+    /// `order`. Only a size reduction at the same price does; the caller gives any
+    /// other replacement a new nonce. The signature is checked by [`Self::verify`].
+    pub fn apply(&self, order: &Order) -> Result<(Order, bool), &'static str> {
+        if self.quantity == 0 {
+            return Err("zero quantity, cancel the order instead");
+        }
+        if order.quote_quantity > 0 {
+            return Err("quote buys cannot be amended, cancel the order instead");
+        }
+        if self.price == order.price && self.quantity == order.quantity {
+            return Err("amendment changes nothing");
+        }
+        let priority_kept = self.price == order.price && self.quantity < order.quantity;
+        Ok((
+            Order {
+                price: self.price,
+                quantity: self.quantity,
+                ..order.clone()
+            },
+            priority_kept,
+        ))
+    }
+}
+
+/// Check that `signature` is an EIP-191 signature of `message` by `owner`
+fn verify_signer(
+    signature: &Signature,
+    message: impl AsRef<[u8]>,
+    owner: Address,
+) -> Result<(), &'static str> {
+    let signer = signature
+        .recover_address_from_msg(message)
+        .map_err(|_| "invalid signature")?;
+    if signer != owner {
+        return Err("not signed by the order owner");
+    }
+    Ok(())
+}
+
+/// Lineage of an amended order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmendedOrder {
+    /// ID of the UTXO that was amended (consumed)
+    pub old_utxo_id: FixedBytes<32>,
+    /// ID of the replacement as amended, before any repricing or fills in the batch
+    pub new_utxo_id: FixedBytes<32>,
+    /// Owner of the order
+    pub owner: Address,
+    /// Whether the replacement kept the original nonce and time priority
+    pub priority_kept: bool,
+}
+
+/// What happened to a new post-only order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostOnlyOutcome {
+    /// Did not cross, accepted at its own price
+    Posted,
+    /// Would have crossed, moved one tick behind the best older opposite price
+    Repriced,
+    /// Would have crossed, cancelled in full
+    Rejected,
+}
+
+impl From<PostOnlyOutcome> for u8 {
+    fn from(value: PostOnlyOutcome) -> Self {
+        match value {
+            PostOnlyOutcome::Posted => 0,
+            PostOnlyOutcome::Repriced => 1,
+            PostOnlyOutcome::Rejected => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for PostOnlyOutcome {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PostOnlyOutcome::Posted),
+            1 => Ok(PostOnlyOutcome::Repriced),
+            2 => Ok(PostOnlyOutcome::Rejected),
+            _ => Err("invalid post-only outcome"),
+        }
+    }
+}
+
+/// Outcome of the post-only check for one new order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostOnlyResult {
+    /// Order ID as submitted
+    pub utxo_id: FixedBytes<32>,
+    /// ID the order goes on to match under: the submitted ID if posted, the ID at its
+    /// new price if repriced (the parent of any remainder it leaves), zero if rejected.
+    /// A resting pegged order keeps its UTXO ID, and its successor rests at the new price.
+    pub new_utxo_id: FixedBytes<32>,
+    /// Owner of the order
+    pub owner: Address,
+    /// What happened to the order
+    pub outcome: PostOnlyOutcome,
+    /// Price the order was accepted at (the submitted price if rejected)
+    pub price: u64,
+}
+
+/// Reasons a batch is rejected by the matching engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchError {
+    /// Merkle proof of an existing UTXO does not verify against the input root
+    InvalidProof {
+        leaf_index: usize,
+        utxo_id: FixedBytes<32>,
+    },
+    /// Merkle proof of the first free leaf, where new UTXOs are appended, does not
+    /// verify against the input root
+    InvalidAppendProof { leaf_index: u64 },
+    /// Input UTXO Merkle root does not match the on-chain root
+    RootMismatch {
+        expected: FixedBytes<32>,
+        actual: FixedBytes<32>,
+    },
+    /// Input UTXO leaf count does not match the on-chain leaf count
+    LeafCountMismatch { expected: u64, actual: u64 },
+    /// Input batch index does not match the on-chain batch index
+    BatchIndexMismatch { expected: u64, actual: u64 },
+    /// Input last trade price does not match the on-chain last trade price
+    LastTradePriceMismatch { expected: u64, actual: u64 },
+    /// Input block timestamp does not match the block committed by Steel
+    BlockTimestampMismatch { expected: u64, actual: u64 },
+    /// Input oracle round does not match the round read from the market's oracle
+    OracleRoundMismatch {
+        expected: Option<OracleRound>,
+        actual: Option<OracleRound>,
+    },
+    /// Input market parameter does not match the on-chain value
+    MarketParamMismatch { param: &'static str },
+    /// A new order (by position in the batch) could not be decoded
+    MalformedOrder { index: usize, reason: &'static str },
+    /// A cancellation (by position in the batch) could not be decoded. Well-formed
+    /// cancellations that cannot be applied are reported in
+    /// [`BatchOutput::rejected_intents`] instead.
+    InvalidCancellation { index: usize, reason: &'static str },
+    /// An amendment (by position in the batch) could not be decoded. Well-formed
+    /// amendments that cannot be applied are reported in
+    /// [`BatchOutput::rejected_intents`] instead.
+    InvalidAmendment { index: usize, reason: &'static str },
+    /// An existing UTXO could not be decoded or does not hash to its ID
+    MalformedUtxo {
+        leaf_index: usize,
+        utxo_id: FixedBytes<32>,
+        reason: &'static str,
+    },
+    /// More than one input for the same market in a multi-market batch
+    DuplicateMarket { market: Address },
+    /// The input for one market in a multi-market batch was rejected
+    InMarket {
+        market: Address,
+        error: Box<MatchError>,
+    },
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchError::InvalidProof {
+                leaf_index,
+                utxo_id,
+            } => write!(
+                f,
+                "invalid Merkle proof for UTXO {utxo_id} at leaf {leaf_index}"
+            ),
+            MatchError::InvalidAppendProof { leaf_index } => {
+                write!(f, "invalid Merkle proof for the free leaf {leaf_index}")
+            }
+            MatchError::RootMismatch { expected, actual } => write!(
+                f,
+                "UTXO Merkle root mismatch: expected {expected}, got {actual}"
+            ),
+            MatchError::LeafCountMismatch { expected, actual } => write!(
+                f,
+                "UTXO leaf count mismatch: expected {expected}, got {actual}"
+            ),
+            MatchError::BatchIndexMismatch { expected, actual } => {
+                write!(f, "batch index mismatch: expected {expected}, got {actual}")
+            }
+            MatchError::LastTradePriceMismatch { expected, actual } => write!(
+                f,
+                "last trade price mismatch: expected {expected}, got {actual}"
+            ),
+            MatchError::BlockTimestampMismatch { expected, actual } => write!(
+                f,
+                "block timestamp mismatch: expected {expected}, got {actual}"
+            ),
+            MatchError::OracleRoundMismatch { expected, actual } => write!(
+                f,
+                "oracle round mismatch: expected {expected:?}, got {actual:?}"
+            ),
+            MatchError::MarketParamMismatch { param } => {
+                write!(f, "market parameter mismatch: {param}")
+            }
+            MatchError::MalformedOrder { index, reason } => {
+                write!(f, "malformed order at index {index}: {reason}")
+            }
+            MatchError::InvalidCancellation { index, reason } => {
+                write!(f, "invalid cancellation at index {index}: {reason}")
+            }
+            MatchError::InvalidAmendment { index, reason } => {
+                write!(f, "invalid amendment at index {index}: {reason}")
+            }
+            MatchError::MalformedUtxo {
+                leaf_index,
+                utxo_id,
+                reason,
+            } => write!(f, "malformed UTXO {utxo_id} at leaf {leaf_index}: {reason}"),
+            MatchError::DuplicateMarket { market } => {
+                write!(f, "more than one input for market {market}")
+            }
+            MatchError::InMarket { market, error } => write!(f, "market {market}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+/// Input to the batch matching process
+#[derive(Debug, Clone, Default)]
+pub struct BatchInput {
+    /// Order book contract of the market, which every order must trade in
+    pub market: Address,
+    /// Current batch index (must match on-chain for replay protection)
+    pub batch_index: u64,
+    /// Timestamp of the block the batch is cleared against, which expires good-till-time
+    /// orders (must match the Steel commitment)
+    pub block_timestamp: u64,
+    /// Expected on-chain UTXO Merkle root (verified via Steel)
+    pub utxo_merkle_root: FixedBytes<32>,
+    /// Leaves of the on-chain UTXO tree, tombstones included (verified via Steel)
+    pub utxo_leaf_count: u64,
+    /// Merkle proof of the free leaf at `utxo_leaf_count`, after which new UTXOs are
+    /// appended
+    pub append_proof: Vec<[u8; 32]>,
+    /// Existing UTXOs with their Merkle proofs
+    pub existing_utxos_with_proofs: Vec<UtxoWithProof>,
+    /// New orders from this batch
+    pub new_orders: Vec<Order>,
+    /// Owner-signed cancellations of existing UTXOs, applied before matching
+    pub cancellations: Vec<Cancellation>,
+    /// Owner-signed amendments of existing UTXOs, applied before matching
+    pub amendments: Vec<Amendment>,
+    /// Market default self-trade prevention mode (must match on-chain)
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Market matching engine (must match on-chain)
+    pub matching_engine: EngineKind,
+    /// Market handling of crossing post-only orders (must match on-chain)
+    pub post_only_policy: PostOnlyPolicy,
+    /// Price of the last fill in earlier batches, 0 before the first trade (must match on-chain)
+    pub last_trade_price: u64,
+    /// Latest round of the market's oracle, `None` for a market without one (verified via Steel)
+    pub oracle_round: Option<OracleRound>,
+    /// Market fee rates (must match on-chain)
+    pub fee_schedule: FeeSchedule,
+    /// Sizes new orders must conform to (must match on-chain)
+    pub market_params: MarketParams,
+    /// Band around `last_trade_price` prices must stay in (must match on-chain)
+    pub price_band: PriceBand,
 }
 
 /// Output from the batch matching process (committed to journal)
 #[derive(Debug, Clone)]
 pub struct BatchOutput {
+    /// Order book contract of the market
+    pub market: Address,
     /// Batch index (for replay protection)
     pub batch_index: u64,
+    /// Matching engine that produced this output
+    pub matching_engine: EngineKind,
     /// Fills from matched orders
     pub fills: Vec<Fill>,
-    /// New UTXOs (unfilled and partially filled orders)
+    /// UTXOs created by this batch: new orders left resting and the remainders of
+    /// existing orders that changed. Orders nothing touched keep their UTXO and are
+    /// left out, see [`BatchOutput::next_utxo_set`].
     pub new_utxos: Vec<Utxo>,
-    /// IDs of consumed UTXOs (fully filled)
+    /// IDs of consumed UTXOs (filled, cancelled or expired). Owner cancellations are
+    /// also reported in `cancelled_orders` with [`CancelReason::OwnerRequest`].
     pub consumed_utxo_ids: Vec<FixedBytes<32>>,
-    /// Merkle root of the new UTXO set
+    /// Orders whose remaining quantity was cancelled instead of resting
+    pub cancelled_orders: Vec<CancelledOrder>,
+    /// Outcome of every new post-only order
+    pub post_only_results: Vec<PostOnlyResult>,
+    /// Amended UTXOs and their replacements
+    pub amended_orders: Vec<AmendedOrder>,
+    /// Merkle root of the new UTXO set, updated from the input root: consumed UTXOs
+    /// leave a tombstone and new ones are appended, see [`UtxoTree`]
     pub new_utxo_merkle_root: FixedBytes<32>,
+    /// Leaves of the new UTXO tree, tombstones included
+    pub new_utxo_leaf_count: u64,
+    /// Price of the last fill, or the input last trade price if nothing traded
+    pub last_trade_price: u64,
+    /// Oracle round pegged orders were priced from
+    pub oracle_round: Option<OracleRound>,
+    /// Whether matching was halted because a fill would have been outside the price band
+    pub halted: bool,
+    /// Fills netted into one balance change per account, which the contract settles
+    pub net_transfers: Vec<NetTransfer>,
+    /// Owner intents dropped without failing the batch
+    pub rejected_intents: Vec<RejectedIntent>,
 }
 
 // Solidity ABI types for encoding/decoding
 sol! {
     /// Order struct for Solidity
     struct SolOrder {
+        address market;
         uint8 side; // 0 = Buy, 1 = Sell
         uint64 price;
+        int8 priceExponent;
         uint64 quantity;
+        uint128 quoteQuantity; // 0 = quantity in AssetA
         address owner;
+        address[] counterparties; // empty = anyone
         uint64 nonce;
         uint64 expiryBatch;
+        uint64 expiryTimestamp; // 0 = good till batch only
+        uint8 selfTradePrevention; // 0 = market default
+        uint8 timeInForce; // 0 = GTC, 1 = IOC, 2 = FOK
+        bool postOnly;
+        uint8 orderType; // 0 = limit, 1 = market
+        uint64 minFillQuantity; // 0 = no minimum
+        bool allOrNone;
+        uint64 hiddenQuantity;
+        uint64 displayQuantity;
+        uint64 triggerPrice; // 0 = not a stop order
+        bool triggered;
+        bool pegged;
+        int64 pegOffset;
+        bytes32 salt; // 0 in the journal
+    }
+
+    /// Market parameters struct for Solidity
+    struct SolMarketParams {
+        uint64 tickSize;
+        uint64 lotSize;
+        uint64 minQuantity;
+        uint128 minNotional;
+        int8 priceExponent;
+    }
+
+    /// Oracle round struct for Solidity
+    struct SolOracleRound {
+        uint80 roundId; // 0 = no oracle
+        uint64 price;
     }
 
     /// UTXO struct for Solidity
     struct SolUtxo {
         bytes32 id;
-        uint8 side; // 0 = Buy, 1 = Sell
-        uint64 price;
-        uint64 quantity;
-        address owner;
-        uint64 nonce;
-        uint64 expiryBatch;
+        SolOrder order;
+        bytes32 parentUtxoId; // zero for an order's first UTXO
+        uint64 originalQuantity; // 0 for a quote buy
+        uint128 originalQuoteQuantity; // budget of a quote buy, 0 for any other order
+        uint64 filledQuantity; // across all batches, in AssetA
     }
 
     /// Fill struct for Solidity
@@ -221,77 +1673,221 @@ sol! {
         bytes32 makerUtxoId;
         bytes32 takerUtxoId;
         uint64 price;
+        int8 priceExponent;
         uint64 quantity;
+        uint256 notional;
         address maker;
         address taker;
         bool makerIsSeller;
+        uint256 makerFee;
+        uint256 takerFee;
     }
 
-    /// UTXO with Merkle proof for ABI encoding
-    struct SolUtxoWithProof {
-        bytes32 id;
-        uint8 side;
+    /// Net transfer struct for Solidity
+    struct SolNetTransfer {
+        address account;
+        int256 assetA;
+        int256 assetB;
+    }
+
+    /// Cancelled order struct for Solidity
+    struct SolCancelledOrder {
+        bytes32 utxoId;
+        address owner;
+        uint64 quantity;
+        uint8 reason;
+    }
+
+    /// Rejected intent struct for Solidity
+    struct SolRejectedIntent {
+        bytes32 utxoId;
+        uint8 kind;
+        uint8 reason;
+    }
+
+    /// Cancellation struct for Solidity
+    struct SolCancellation {
+        bytes32 utxoId;
+        bytes signature; // 65 bytes: r, s, v
+    }
+
+    /// Amendment struct for Solidity
+    struct SolAmendment {
+        bytes32 utxoId;
         uint64 price;
         uint64 quantity;
+        bytes signature; // 65 bytes: r, s, v
+    }
+
+    /// Amended order struct for Solidity
+    struct SolAmendedOrder {
+        bytes32 oldUtxoId;
+        bytes32 newUtxoId;
         address owner;
-        uint64 nonce;
-        uint64 expiryBatch;
+        bool priorityKept;
+    }
+
+    /// Post-only result struct for Solidity
+    struct SolPostOnlyResult {
+        bytes32 utxoId;
+        bytes32 newUtxoId;
+        address owner;
+        uint8 outcome;
+        uint64 price;
+    }
+
+    /// UTXO with Merkle proof for ABI encoding
+    struct SolUtxoWithProof {
+        SolUtxo utxo;
         bytes32[] proofHashes;
         uint256 leafIndex;
     }
 
     /// Batch input for ABI encoding
     struct SolBatchInput {
+        address market;
         uint64 batchIndex;
+        uint64 blockTimestamp;
         bytes32 utxoMerkleRoot;
+        uint64 utxoLeafCount;
+        bytes32[] appendProof;
         SolUtxoWithProof[] existingUtxosWithProofs;
         SolOrder[] newOrders;
+        SolCancellation[] cancellations;
+        SolAmendment[] amendments;
+        uint8 selfTradePrevention;
+        uint8 matchingEngine;
+        uint8 postOnlyPolicy;
+        uint64 lastTradePrice;
+        SolOracleRound oracleRound;
+        uint16 makerFeeBps;
+        uint16 takerFeeBps;
+        SolMarketParams marketParams;
+        uint16 priceBandBps;
+        uint8 priceBandPolicy;
+    }
+
+    /// Batch inputs for every market cleared in one guest execution
+    struct SolMultiBatchInput {
+        SolBatchInput[] markets;
     }
 
     /// Batch output for Solidity journal decoding
     struct SolBatchOutput {
+        address market;
         uint64 batchIndex;
+        uint8 matchingEngine;
         SolFill[] fills;
         SolUtxo[] newUtxos;
         bytes32[] consumedUtxoIds;
+        SolCancelledOrder[] cancelledOrders;
+        SolPostOnlyResult[] postOnlyResults;
         bytes32 newUtxoMerkleRoot;
+        uint64 newUtxoLeafCount;
+        uint64 lastTradePrice;
+        SolOracleRound oracleRound;
+        SolAmendedOrder[] amendedOrders;
+        bool halted;
+        SolNetTransfer[] netTransfers;
+        SolRejectedIntent[] rejectedIntents;
     }
 
-    /// Journal struct that includes Steel commitment and batch output
-    /// This is the actual structure committed to the journal and decoded by the contract
+    /// Journal struct that includes the Steel commitment and the batch output of each market
+    /// This is the actual structure committed to the journal and decoded by every market's contract
     struct SolJournal {
         Commitment steelCommitment;
-        uint64 batchIndex;
-        SolFill[] fills;
-        SolUtxo[] newUtxos;
-        bytes32[] consumedUtxoIds;
-        bytes32 newUtxoMerkleRoot;
+        SolBatchOutput[] markets;
     }
 }
 
 impl From<&Order> for SolOrder {
     fn from(order: &Order) -> Self {
         SolOrder {
+            market: order.market,
             side: order.side.into(),
             price: order.price,
+            priceExponent: order.price_exponent,
             quantity: order.quantity,
+            quoteQuantity: order.quote_quantity,
             owner: order.owner,
+            counterparties: order.counterparties.clone(),
             nonce: order.nonce,
             expiryBatch: order.expiry_batch,
+            expiryTimestamp: order.expiry_timestamp,
+            selfTradePrevention: encode_stp(order.self_trade_prevention),
+            timeInForce: order.time_in_force.into(),
+            postOnly: order.post_only,
+            orderType: order.order_type.into(),
+            minFillQuantity: order.min_fill_quantity,
+            allOrNone: order.all_or_none,
+            hiddenQuantity: order.hidden_quantity,
+            displayQuantity: order.display_quantity,
+            triggerPrice: order.trigger_price,
+            triggered: order.triggered,
+            pegged: order.pegged,
+            pegOffset: order.peg_offset,
+            salt: order.salt,
         }
     }
 }
 
-impl From<&SolOrder> for Order {
-    fn from(sol: &SolOrder) -> Self {
-        Order {
-            side: sol.side.into(),
+impl TryFrom<&SolOrder> for Order {
+    type Error = &'static str;
+
+    fn try_from(sol: &SolOrder) -> Result<Self, Self::Error> {
+        let order_type = sol.orderType.try_into()?;
+        if order_type == OrderType::Market && sol.postOnly {
+            return Err("market orders cannot be post-only");
+        }
+        if order_type == OrderType::Market && sol.hiddenQuantity > 0 {
+            return Err("market orders cannot have a hidden reserve");
+        }
+        if sol.triggerPrice > 0 && sol.postOnly {
+            return Err("stop orders cannot be post-only");
+        }
+        if order_type == OrderType::Market && sol.pegged {
+            return Err("market orders cannot be pegged");
+        }
+        if sol.counterparties.len() > MAX_COUNTERPARTIES {
+            return Err("too many counterparties");
+        }
+        if sol.quoteQuantity > 0 {
+            if sol.side != u8::from(Side::Buy) {
+                return Err("only buy orders can spend a quote quantity");
+            }
+            if sol.price == 0 {
+                return Err("quote buys need a price to convert their budget at");
+            }
+            if sol.hiddenQuantity > 0 || sol.pegged {
+                return Err("quote buys cannot have a hidden reserve or be pegged");
+            }
+        }
+        Ok(Order {
+            market: sol.market,
+            side: sol.side.try_into()?,
             price: sol.price,
+            price_exponent: sol.priceExponent,
             quantity: sol.quantity,
+            quote_quantity: sol.quoteQuantity,
             owner: sol.owner,
+            counterparties: sol.counterparties.clone(),
             nonce: sol.nonce,
             expiry_batch: sol.expiryBatch,
-        }
+            expiry_timestamp: sol.expiryTimestamp,
+            self_trade_prevention: decode_stp(sol.selfTradePrevention)?,
+            time_in_force: sol.timeInForce.try_into()?,
+            post_only: sol.postOnly,
+            order_type,
+            min_fill_quantity: sol.minFillQuantity,
+            all_or_none: sol.allOrNone,
+            hidden_quantity: sol.hiddenQuantity,
+            display_quantity: sol.displayQuantity,
+            trigger_price: sol.triggerPrice,
+            triggered: sol.triggered,
+            pegged: sol.pegged,
+            peg_offset: sol.pegOffset,
+            salt: sol.salt,
+        })
     }
 }
 
@@ -299,27 +1895,27 @@ impl From<&Utxo> for SolUtxo {
     fn from(utxo: &Utxo) -> Self {
         SolUtxo {
             id: utxo.id,
-            side: utxo.order.side.into(),
-            price: utxo.order.price,
-            quantity: utxo.order.quantity,
-            owner: utxo.order.owner,
-            nonce: utxo.order.nonce,
-            expiryBatch: utxo.order.expiry_batch,
+            order: SolOrder::from(&utxo.order),
+            parentUtxoId: utxo.parent_utxo_id,
+            originalQuantity: utxo.original_quantity,
+            originalQuoteQuantity: utxo.original_quote_quantity,
+            filledQuantity: utxo.filled_quantity,
         }
     }
 }
 
-impl From<&SolUtxo> for Utxo {
-    fn from(sol: &SolUtxo) -> Self {
-        let order = Order {
-            side: sol.side.into(),
-            price: sol.price,
-            quantity: sol.quantity,
-            owner: sol.owner,
-            nonce: sol.nonce,
-            expiry_batch: sol.expiryBatch,
-        };
-        Utxo { id: sol.id, order }
+impl TryFrom<&SolUtxo> for Utxo {
+    type Error = &'static str;
+
+    fn try_from(sol: &SolUtxo) -> Result<Self, Self::Error> {
+        Ok(Utxo {
+            id: sol.id,
+            order: Order::try_from(&sol.order)?,
+            parent_utxo_id: sol.parentUtxoId,
+            original_quantity: sol.originalQuantity,
+            original_quote_quantity: sol.originalQuoteQuantity,
+            filled_quantity: sol.filledQuantity,
+        })
     }
 }
 
@@ -329,10 +1925,138 @@ impl From<&Fill> for SolFill {
             makerUtxoId: fill.maker_utxo_id,
             takerUtxoId: fill.taker_utxo_id,
             price: fill.price,
+            priceExponent: fill.price_exponent,
             quantity: fill.quantity,
+            notional: fill.notional,
             maker: fill.maker,
             taker: fill.taker,
             makerIsSeller: fill.maker_is_seller,
+            makerFee: fill.maker_fee,
+            takerFee: fill.taker_fee,
+        }
+    }
+}
+
+impl From<&NetTransfer> for SolNetTransfer {
+    fn from(transfer: &NetTransfer) -> Self {
+        SolNetTransfer {
+            account: transfer.account,
+            assetA: transfer.asset_a,
+            assetB: transfer.asset_b,
+        }
+    }
+}
+
+impl From<&CancelledOrder> for SolCancelledOrder {
+    fn from(cancelled: &CancelledOrder) -> Self {
+        SolCancelledOrder {
+            utxoId: cancelled.utxo_id,
+            owner: cancelled.owner,
+            quantity: cancelled.quantity,
+            reason: cancelled.reason.into(),
+        }
+    }
+}
+
+impl From<&RejectedIntent> for SolRejectedIntent {
+    fn from(rejected: &RejectedIntent) -> Self {
+        SolRejectedIntent {
+            utxoId: rejected.utxo_id,
+            kind: rejected.kind.into(),
+            reason: rejected.reason.into(),
+        }
+    }
+}
+
+impl From<&Cancellation> for SolCancellation {
+    fn from(cancellation: &Cancellation) -> Self {
+        SolCancellation {
+            utxoId: cancellation.utxo_id,
+            signature: cancellation.signature.as_bytes().to_vec().into(),
+        }
+    }
+}
+
+impl TryFrom<&SolCancellation> for Cancellation {
+    type Error = &'static str;
+
+    fn try_from(sol: &SolCancellation) -> Result<Self, Self::Error> {
+        Ok(Cancellation {
+            utxo_id: sol.utxoId,
+            signature: Signature::try_from(sol.signature.as_ref())
+                .map_err(|_| "malformed signature")?,
+        })
+    }
+}
+
+impl From<&MarketParams> for SolMarketParams {
+    fn from(params: &MarketParams) -> Self {
+        SolMarketParams {
+            tickSize: params.tick_size,
+            lotSize: params.lot_size,
+            minQuantity: params.min_quantity,
+            minNotional: params.min_notional,
+            priceExponent: params.price_exponent,
+        }
+    }
+}
+
+impl From<&SolMarketParams> for MarketParams {
+    fn from(sol: &SolMarketParams) -> Self {
+        MarketParams {
+            tick_size: sol.tickSize,
+            lot_size: sol.lotSize,
+            min_quantity: sol.minQuantity,
+            min_notional: sol.minNotional,
+            price_exponent: sol.priceExponent,
+        }
+    }
+}
+
+impl From<&Amendment> for SolAmendment {
+    fn from(amendment: &Amendment) -> Self {
+        SolAmendment {
+            utxoId: amendment.utxo_id,
+            price: amendment.price,
+            quantity: amendment.quantity,
+            signature: amendment.signature.as_bytes().to_vec().into(),
+        }
+    }
+}
+
+impl TryFrom<&SolAmendment> for Amendment {
+    type Error = &'static str;
+
+    fn try_from(sol: &SolAmendment) -> Result<Self, Self::Error> {
+        Ok(Amendment {
+            utxo_id: sol.utxoId,
+            price: sol.price,
+            quantity: sol.quantity,
+            signature: Signature::try_from(sol.signature.as_ref())
+                .map_err(|_| "malformed signature")?,
+        })
+    }
+}
+
+impl From<&AmendedOrder> for SolAmendedOrder {
+    fn from(amended: &AmendedOrder) -> Self {
+        SolAmendedOrder {
+            oldUtxoId: amended.old_utxo_id,
+            newUtxoId: amended.new_utxo_id,
+            owner: amended.owner,
+            priorityKept: amended.priority_kept,
+        }
+    }
+}
+
+impl From<&PostOnlyResult> for SolPostOnlyResult {
+    fn from(result: &PostOnlyResult) -> Self {
+        SolPostOnlyResult {
+            utxoId: result.utxo_id,
+            newUtxoId: result.new_utxo_id,
+            owner: result.owner,
+            outcome: result.outcome.into(),
+            price: result.price,
         }
     }
 }
@@ -340,13 +2064,7 @@ impl From<&Fill> for SolFill {
 impl From<&UtxoWithProof> for SolUtxoWithProof {
     fn from(uwp: &UtxoWithProof) -> Self {
         SolUtxoWithProof {
-            id: uwp.utxo.id,
-            side: uwp.utxo.order.side.into(),
-            price: uwp.utxo.order.price,
-            quantity: uwp.utxo.order.quantity,
-            owner: uwp.utxo.order.owner,
-            nonce: uwp.utxo.order.nonce,
-            expiryBatch: uwp.utxo.order.expiry_batch,
+            utxo: SolUtxo::from(&uwp.utxo),
             proofHashes: uwp
                 .proof_hashes
                 .iter()
@@ -357,17 +2075,11 @@ impl From<&UtxoWithProof> for SolUtxoWithProof {
     }
 }
 
-impl From<&SolUtxoWithProof> for UtxoWithProof {
-    fn from(sol: &SolUtxoWithProof) -> Self {
-        let order = Order {
-            side: sol.side.into(),
-            price: sol.price,
-            quantity: sol.quantity,
-            owner: sol.owner,
-            nonce: sol.nonce,
-            expiry_batch: sol.expiryBatch,
-        };
-        let utxo = Utxo { id: sol.id, order };
+impl TryFrom<&SolUtxoWithProof> for UtxoWithProof {
+    type Error = &'static str;
+
+    fn try_from(sol: &SolUtxoWithProof) -> Result<Self, Self::Error> {
+        let utxo = Utxo::try_from(&sol.utxo)?;
         let proof_hashes: Vec<[u8; 32]> = sol
             .proofHashes
             .iter()
@@ -377,12 +2089,15 @@ impl From<&SolUtxoWithProof> for UtxoWithProof {
                 arr
             })
             .collect();
-        let leaf_index: usize = sol.leafIndex.try_into().unwrap_or(0);
-        UtxoWithProof {
+        let leaf_index: usize = sol
+            .leafIndex
+            .try_into()
+            .map_err(|_| "leaf index out of range")?;
+        Ok(UtxoWithProof {
             utxo,
             proof_hashes,
             leaf_index,
-        }
+        })
     }
 }
 
@@ -390,210 +2105,365 @@ impl BatchInput {
     /// Convert to Solidity-compatible format for ABI encoding
     pub fn to_sol(&self) -> SolBatchInput {
         SolBatchInput {
+            market: self.market,
             batchIndex: self.batch_index,
+            blockTimestamp: self.block_timestamp,
             utxoMerkleRoot: self.utxo_merkle_root,
+            utxoLeafCount: self.utxo_leaf_count,
+            appendProof: self
+                .append_proof
+                .iter()
+                .map(|h| FixedBytes::from_slice(h))
+                .collect(),
             existingUtxosWithProofs: self
                 .existing_utxos_with_proofs
                 .iter()
                 .map(SolUtxoWithProof::from)
                 .collect(),
             newOrders: self.new_orders.iter().map(SolOrder::from).collect(),
-        }
-    }
-
-    /// Create from Solidity-compatible format (ABI decoding)
-    pub fn from_sol(sol: &SolBatchInput) -> Self {
-        BatchInput {
-            batch_index: sol.batchIndex,
-            utxo_merkle_root: sol.utxoMerkleRoot,
-            existing_utxos_with_proofs: sol
-                .existingUtxosWithProofs
+            cancellations: self
+                .cancellations
                 .iter()
-                .map(UtxoWithProof::from)
+                .map(SolCancellation::from)
                 .collect(),
-            new_orders: sol.newOrders.iter().map(Order::from).collect(),
+            amendments: self.amendments.iter().map(SolAmendment::from).collect(),
+            selfTradePrevention: self.self_trade_prevention.into(),
+            matchingEngine: self.matching_engine.into(),
+            postOnlyPolicy: self.post_only_policy.into(),
+            lastTradePrice: self.last_trade_price,
+            oracleRound: encode_oracle_round(self.oracle_round),
+            makerFeeBps: self.fee_schedule.maker_fee_bps,
+            takerFeeBps: self.fee_schedule.taker_fee_bps,
+            marketParams: SolMarketParams::from(&self.market_params),
+            priceBandBps: self.price_band.band_bps,
+            priceBandPolicy: self.price_band.policy.into(),
         }
     }
-}
 
-impl BatchOutput {
-    /// Convert to Solidity-compatible format for ABI encoding
-    pub fn to_sol(&self) -> SolBatchOutput {
-        SolBatchOutput {
-            batchIndex: self.batch_index,
-            fills: self.fills.iter().map(SolFill::from).collect(),
-            newUtxos: self.new_utxos.iter().map(SolUtxo::from).collect(),
-            consumedUtxoIds: self.consumed_utxo_ids.clone(),
-            newUtxoMerkleRoot: self.new_utxo_merkle_root,
-        }
-    }
-
-    /// Convert to journal format with Steel commitment for on-chain verification
-    pub fn to_journal(&self, commitment: Commitment) -> SolJournal {
-        SolJournal {
-            steelCommitment: commitment,
-            batchIndex: self.batch_index,
-            fills: self.fills.iter().map(SolFill::from).collect(),
-            newUtxos: self.new_utxos.iter().map(SolUtxo::from).collect(),
-            consumedUtxoIds: self.consumed_utxo_ids.clone(),
-            newUtxoMerkleRoot: self.new_utxo_merkle_root,
-        }
-    }
-}
-
-/// Main order matching function - runs the limit order book matching algorithm
-pub fn match_orders(input: BatchInput) -> BatchOutput {
-    let current_batch = input.batch_index;
-
-    let mut buy_orders: Vec<Utxo> = Vec::new();
-    let mut sell_orders: Vec<Utxo> = Vec::new();
-    let mut consumed_utxo_ids: Vec<FixedBytes<32>> = Vec::new();
-
-    // Track existing UTXO IDs (these must be consumed when filled, even partially)
-    let mut existing_utxo_ids: Vec<FixedBytes<32>> = Vec::new();
-
-    // Total UTXO count for Merkle proof verification (derived from input)
-    let utxo_count = input.existing_utxos_with_proofs.len();
+    /// Create from Solidity-compatible format (ABI decoding)
+    pub fn from_sol(sol: &SolBatchInput) -> Result<Self, MatchError> {
+        let existing_utxos_with_proofs = sol
+            .existingUtxosWithProofs
+            .iter()
+            .enumerate()
+            .map(|(i, uwp)| {
+                UtxoWithProof::try_from(uwp).map_err(|reason| MatchError::MalformedUtxo {
+                    // The encoded leaf index may itself be the malformed field
+                    leaf_index: uwp.leafIndex.try_into().unwrap_or(i),
+                    utxo_id: uwp.utxo.id,
+                    reason,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let new_orders = sol
+            .newOrders
+            .iter()
+            .enumerate()
+            .map(|(index, order)| {
+                Order::try_from(order)
+                    .map_err(|reason| MatchError::MalformedOrder { index, reason })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let cancellations = sol
+            .cancellations
+            .iter()
+            .enumerate()
+            .map(|(index, cancellation)| {
+                Cancellation::try_from(cancellation)
+                    .map_err(|reason| MatchError::InvalidCancellation { index, reason })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let amendments = sol
+            .amendments
+            .iter()
+            .enumerate()
+            .map(|(index, amendment)| {
+                Amendment::try_from(amendment)
+                    .map_err(|reason| MatchError::InvalidAmendment { index, reason })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-    // Process existing UTXOs with proof verification (skip expired ones)
-    for utxo_with_proof in input.existing_utxos_with_proofs {
-        // Verify UTXO against on-chain Merkle root
-        assert!(
-            utxo_with_proof.verify(&input.utxo_merkle_root, utxo_count),
-            "Invalid Merkle proof for UTXO"
-        );
+        let self_trade_prevention =
+            sol.selfTradePrevention
+                .try_into()
+                .map_err(|_| MatchError::MarketParamMismatch {
+                    param: "self_trade_prevention",
+                })?;
+        let matching_engine =
+            sol.matchingEngine
+                .try_into()
+                .map_err(|_| MatchError::MarketParamMismatch {
+                    param: "matching_engine",
+                })?;
+        let post_only_policy =
+            sol.postOnlyPolicy
+                .try_into()
+                .map_err(|_| MatchError::MarketParamMismatch {
+                    param: "post_only_policy",
+                })?;
+        let price_band = PriceBand {
+            band_bps: sol.priceBandBps,
+            policy: sol.priceBandPolicy.try_into().map_err(|_| {
+                MatchError::MarketParamMismatch {
+                    param: "price_band_policy",
+                }
+            })?,
+        };
 
-        let utxo = utxo_with_proof.utxo;
+        Ok(BatchInput {
+            market: sol.market,
+            batch_index: sol.batchIndex,
+            block_timestamp: sol.blockTimestamp,
+            utxo_merkle_root: sol.utxoMerkleRoot,
+            utxo_leaf_count: sol.utxoLeafCount,
+            append_proof: sol.appendProof.iter().map(|h| h.0).collect(),
+            existing_utxos_with_proofs,
+            new_orders,
+            cancellations,
+            amendments,
+            self_trade_prevention,
+            matching_engine,
+            post_only_policy,
+            last_trade_price: sol.lastTradePrice,
+            oracle_round: decode_oracle_round(&sol.oracleRound),
+            fee_schedule: FeeSchedule {
+                maker_fee_bps: sol.makerFeeBps,
+                taker_fee_bps: sol.takerFeeBps,
+            },
+            market_params: MarketParams::from(&sol.marketParams),
+            price_band,
+        })
+    }
 
-        if utxo.is_expired(current_batch) {
-            consumed_utxo_ids.push(utxo.id);
-            continue;
+    /// Check that this input was built against the given on-chain state
+    pub fn verify_chain_state(
+        &self,
+        utxo_merkle_root: FixedBytes<32>,
+        batch_index: u64,
+        self_trade_prevention: u8,
+        matching_engine: u8,
+        post_only_policy: u8,
+        last_trade_price: u64,
+    ) -> Result<(), MatchError> {
+        if self.utxo_merkle_root != utxo_merkle_root {
+            return Err(MatchError::RootMismatch {
+                expected: utxo_merkle_root,
+                actual: self.utxo_merkle_root,
+            });
         }
-
-        existing_utxo_ids.push(utxo.id);
-
-        match utxo.order.side {
-            Side::Buy => buy_orders.push(utxo),
-            Side::Sell => sell_orders.push(utxo),
+        if self.batch_index != batch_index {
+            return Err(MatchError::BatchIndexMismatch {
+                expected: batch_index,
+                actual: self.batch_index,
+            });
         }
-    }
-
-    // Process new orders (create UTXOs)
-    for order in input.new_orders {
-        if order.expiry_batch < current_batch {
-            continue;
+        if u8::from(self.self_trade_prevention) != self_trade_prevention {
+            return Err(MatchError::MarketParamMismatch {
+                param: "self_trade_prevention",
+            });
         }
-
-        let utxo = Utxo::new(order);
-
-        match utxo.order.side {
-            Side::Buy => buy_orders.push(utxo),
-            Side::Sell => sell_orders.push(utxo),
+        if u8::from(self.matching_engine) != matching_engine {
+            return Err(MatchError::MarketParamMismatch {
+                param: "matching_engine",
+            });
         }
-    }
-
-    // Sort buy orders: price DESC, nonce ASC (price-time priority)
-    buy_orders.sort_by(|a, b| match b.order.price.cmp(&a.order.price) {
-        Ordering::Equal => a.order.nonce.cmp(&b.order.nonce),
-        other => other,
-    });
-
-    // Sort sell orders: price ASC, nonce ASC (price-time priority)
-    sell_orders.sort_by(|a, b| match a.order.price.cmp(&b.order.price) {
-        Ordering::Equal => a.order.nonce.cmp(&b.order.nonce),
-        other => other,
-    });
-
-    let mut fills: Vec<Fill> = Vec::new();
-    let mut buy_idx = 0;
-    let mut sell_idx = 0;
-
-    // Match orders while best buy price >= best sell price
-    while buy_idx < buy_orders.len() && sell_idx < sell_orders.len() {
-        let buy = &buy_orders[buy_idx];
-        let sell = &sell_orders[sell_idx];
-
-        if buy.order.price < sell.order.price {
-            break;
+        if u8::from(self.post_only_policy) != post_only_policy {
+            return Err(MatchError::MarketParamMismatch {
+                param: "post_only_policy",
+            });
+        }
+        if self.last_trade_price != last_trade_price {
+            return Err(MatchError::LastTradePriceMismatch {
+                expected: last_trade_price,
+                actual: self.last_trade_price,
+            });
         }
+        Ok(())
+    }
 
-        // Prevent self-trading (same owner on both sides)
-        if buy.order.owner == sell.order.owner {
-            // Skip this pair - advance the newer order (higher nonce)
-            if buy.order.nonce > sell.order.nonce {
-                buy_idx += 1;
-            } else {
-                sell_idx += 1;
-            }
-            continue;
+    /// Check that this input carries the oracle round read from the market's oracle
+    pub fn verify_oracle_round(&self, oracle_round: Option<OracleRound>) -> Result<(), MatchError> {
+        if self.oracle_round != oracle_round {
+            return Err(MatchError::OracleRoundMismatch {
+                expected: oracle_round,
+                actual: self.oracle_round,
+            });
         }
+        Ok(())
+    }
 
-        // Determine maker (older order by nonce) for price execution
-        let (maker, taker, maker_is_seller) = if buy.order.nonce < sell.order.nonce {
-            (buy, sell, false)
+    /// Check that this input carries the market's on-chain order size parameters
+    pub fn verify_market_params(&self, market_params: MarketParams) -> Result<(), MatchError> {
+        let input = &self.market_params;
+        let param = if input.tick_size != market_params.tick_size {
+            "tick_size"
+        } else if input.lot_size != market_params.lot_size {
+            "lot_size"
+        } else if input.min_quantity != market_params.min_quantity {
+            "min_quantity"
+        } else if input.min_notional != market_params.min_notional {
+            "min_notional"
+        } else if input.price_exponent != market_params.price_exponent {
+            "price_exponent"
         } else {
-            (sell, buy, true)
+            return Ok(());
         };
+        Err(MatchError::MarketParamMismatch { param })
+    }
 
-        let exec_price = maker.order.price;
-        let fill_qty = buy.order.quantity.min(sell.order.quantity);
-
-        let fill = Fill {
-            maker_utxo_id: maker.id,
-            taker_utxo_id: taker.id,
-            price: exec_price,
-            quantity: fill_qty,
-            maker: maker.order.owner,
-            taker: taker.order.owner,
-            maker_is_seller,
-        };
-        fills.push(fill);
+    /// Check that this input appends new UTXOs after the last leaf of the on-chain UTXO tree
+    pub fn verify_utxo_leaf_count(&self, utxo_leaf_count: u64) -> Result<(), MatchError> {
+        if self.utxo_leaf_count != utxo_leaf_count {
+            return Err(MatchError::LeafCountMismatch {
+                expected: utxo_leaf_count,
+                actual: self.utxo_leaf_count,
+            });
+        }
+        Ok(())
+    }
 
-        let buy_remaining = buy.order.quantity - fill_qty;
-        let sell_remaining = sell.order.quantity - fill_qty;
+    /// Check that this input is cleared against the timestamp of the block committed by Steel
+    pub fn verify_block_timestamp(&self, block_timestamp: u64) -> Result<(), MatchError> {
+        if self.block_timestamp != block_timestamp {
+            return Err(MatchError::BlockTimestampMismatch {
+                expected: block_timestamp,
+                actual: self.block_timestamp,
+            });
+        }
+        Ok(())
+    }
 
-        // Consume existing UTXOs on any fill (partial or full)
-        if existing_utxo_ids.contains(&buy.id) && !consumed_utxo_ids.contains(&buy.id) {
-            consumed_utxo_ids.push(buy.id);
+    /// Check that this input carries the market's on-chain price band
+    pub fn verify_price_band(&self, band_bps: u16, policy: u8) -> Result<(), MatchError> {
+        if self.price_band.band_bps != band_bps {
+            return Err(MatchError::MarketParamMismatch {
+                param: "price_band_bps",
+            });
         }
-        if existing_utxo_ids.contains(&sell.id) && !consumed_utxo_ids.contains(&sell.id) {
-            consumed_utxo_ids.push(sell.id);
+        if u8::from(self.price_band.policy) != policy {
+            return Err(MatchError::MarketParamMismatch {
+                param: "price_band_policy",
+            });
         }
+        Ok(())
+    }
 
-        if buy_remaining == 0 {
-            buy_idx += 1;
-        } else {
-            buy_orders[buy_idx].order.quantity = buy_remaining;
+    /// Check that this input charges the market's on-chain fee rates
+    pub fn verify_fee_schedule(
+        &self,
+        maker_fee_bps: u16,
+        taker_fee_bps: u16,
+    ) -> Result<(), MatchError> {
+        if self.fee_schedule.maker_fee_bps != maker_fee_bps {
+            return Err(MatchError::MarketParamMismatch {
+                param: "maker_fee_bps",
+            });
         }
-
-        if sell_remaining == 0 {
-            sell_idx += 1;
-        } else {
-            sell_orders[sell_idx].order.quantity = sell_remaining;
+        if self.fee_schedule.taker_fee_bps != taker_fee_bps {
+            return Err(MatchError::MarketParamMismatch {
+                param: "taker_fee_bps",
+            });
         }
+        Ok(())
     }
+}
 
-    // Collect remaining orders as new UTXOs
-    let mut new_utxos: Vec<Utxo> = Vec::new();
-
-    for utxo in buy_orders.into_iter().skip(buy_idx) {
-        new_utxos.push(Utxo::new(utxo.order));
+impl BatchOutput {
+    /// The UTXO set after this batch by leaf index, given the one before it: the UTXOs
+    /// this batch left untouched at their leaves, then the new ones at the leaves
+    /// appended after the previous last one. These are the live leaves of
+    /// `new_utxo_merkle_root`.
+    pub fn next_utxo_set(&self, previous: &[(u64, Utxo)]) -> Vec<(u64, Utxo)> {
+        let consumed: B256Set = self.consumed_utxo_ids.iter().copied().collect();
+        let first_new = self.new_utxo_leaf_count - self.new_utxos.len() as u64;
+        previous
+            .iter()
+            .filter(|(_, utxo)| !consumed.contains(&utxo.id))
+            .cloned()
+            .chain((first_new..).zip(self.new_utxos.iter().cloned()))
+            .collect()
     }
 
-    for utxo in sell_orders.into_iter().skip(sell_idx) {
-        new_utxos.push(Utxo::new(utxo.order));
+    /// Convert to Solidity-compatible format for ABI encoding.
+    /// The output is public, so new UTXOs show only their displayed quantity.
+    pub fn to_sol(&self) -> SolBatchOutput {
+        SolBatchOutput {
+            market: self.market,
+            batchIndex: self.batch_index,
+            matchingEngine: self.matching_engine.into(),
+            fills: self.fills.iter().map(SolFill::from).collect(),
+            newUtxos: self.new_utxos.iter().map(Utxo::to_public_sol).collect(),
+            consumedUtxoIds: self.consumed_utxo_ids.clone(),
+            cancelledOrders: self
+                .cancelled_orders
+                .iter()
+                .map(SolCancelledOrder::from)
+                .collect(),
+            postOnlyResults: self
+                .post_only_results
+                .iter()
+                .map(SolPostOnlyResult::from)
+                .collect(),
+            newUtxoMerkleRoot: self.new_utxo_merkle_root,
+            newUtxoLeafCount: self.new_utxo_leaf_count,
+            lastTradePrice: self.last_trade_price,
+            oracleRound: encode_oracle_round(self.oracle_round),
+            amendedOrders: self
+                .amended_orders
+                .iter()
+                .map(SolAmendedOrder::from)
+                .collect(),
+            halted: self.halted,
+            netTransfers: self
+                .net_transfers
+                .iter()
+                .map(SolNetTransfer::from)
+                .collect(),
+            rejectedIntents: self
+                .rejected_intents
+                .iter()
+                .map(SolRejectedIntent::from)
+                .collect(),
+        }
     }
+}
+
+/// Main order matching function - clears the batch with the market's matching engine
+///
+/// Fails without producing any output if an existing UTXO does not belong to
+/// the input Merkle root.
+pub fn match_orders(input: BatchInput) -> Result<BatchOutput, MatchError> {
+    input.matching_engine.engine().match_batch(input)
+}
 
-    // Compute new Merkle root from the resulting UTXOs
-    let new_utxo_ids: Vec<FixedBytes<32>> = new_utxos.iter().map(|u| u.id).collect();
-    let new_utxo_merkle_root = compute_utxo_merkle_root(&new_utxo_ids);
+/// Clear one batch for each of several markets, each with its own engine, UTXO set
+/// and on-chain state, so that a single proof covers all of them
+pub fn match_markets(inputs: Vec<BatchInput>) -> Result<Vec<BatchOutput>, MatchError> {
+    for (i, input) in inputs.iter().enumerate() {
+        if inputs[..i].iter().any(|other| other.market == input.market) {
+            return Err(MatchError::DuplicateMarket {
+                market: input.market,
+            });
+        }
+    }
+    inputs
+        .into_iter()
+        .map(|input| {
+            let market = input.market;
+            match_orders(input).map_err(|error| MatchError::InMarket {
+                market,
+                error: Box::new(error),
+            })
+        })
+        .collect()
+}
 
-    BatchOutput {
-        batch_index: current_batch,
-        fills,
-        new_utxos,
-        consumed_utxo_ids,
-        new_utxo_merkle_root,
+/// Build the journal committing the output of every market to the Steel commitment
+/// the on-chain state was read at. Each market's contract settles its own entry.
+pub fn to_journal(outputs: &[BatchOutput], commitment: Commitment) -> SolJournal {
+    SolJournal {
+        steelCommitment: commitment,
+        markets: outputs.iter().map(BatchOutput::to_sol).collect(),
     }
 }
 
@@ -601,6 +2471,61 @@ pub fn match_orders(input: BatchInput) -> BatchOutput {
 mod tests {
     use super::*;
 
+    pub(crate) const ALICE: Address = Address::repeat_byte(0xa1);
+    pub(crate) const BOB: Address = Address::repeat_byte(0xb0);
+
+    pub(crate) fn order(
+        side: Side,
+        price: u64,
+        quantity: u64,
+        owner: Address,
+        nonce: u64,
+    ) -> Order {
+        Order {
+            side,
+            price,
+            quantity,
+            owner,
+            nonce,
+            expiry_batch: 100,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn new_orders_batch(new_orders: Vec<Order>) -> BatchInput {
+        BatchInput {
+            new_orders,
+            ..Default::default()
+        }
+    }
+
+    /// `input` against a UTXO tree holding `resting`, each at the leaf of its position
+    pub(crate) fn resting_batch(resting: &[Utxo], input: BatchInput) -> BatchInput {
+        let ids: Vec<FixedBytes<32>> = resting.iter().map(|utxo| utxo.id).collect();
+        let tree = UtxoTree::from_leaves(&ids);
+        BatchInput {
+            utxo_merkle_root: tree.root(),
+            utxo_leaf_count: tree.leaf_count(),
+            append_proof: tree.proof(tree.leaf_count()),
+            existing_utxos_with_proofs: resting
+                .iter()
+                .enumerate()
+                .map(|(i, utxo)| UtxoWithProof {
+                    utxo: utxo.clone(),
+                    proof_hashes: tree.proof(i as u64),
+                    leaf_index: i,
+                })
+                .collect(),
+            ..input
+        }
+    }
+
+    /// EIP-191 signature of `message`, as produced by `personal_sign`
+    fn sign(key: &k256::ecdsa::SigningKey, message: impl AsRef<[u8]>) -> Signature {
+        let hash = alloy_primitives::eip191_hash_message(message);
+        Signature::from(key.sign_prehash_recoverable(hash.as_slice()).unwrap())
+    }
+
     #[test]
     fn test_utxo_id_generation() {
         let order = Order {
@@ -610,6 +2535,7 @@ mod tests {
             owner: Address::ZERO,
             nonce: 1,
             expiry_batch: 100,
+            ..Default::default()
         };
 
         let utxo = Utxo::new(order.clone());
@@ -626,11 +2552,56 @@ mod tests {
             owner: Address::ZERO,
             nonce: 1,
             expiry_batch: 50,
+            ..Default::default()
         };
 
         let utxo = Utxo::new(order);
-        assert!(!utxo.is_expired(50));
-        assert!(utxo.is_expired(51));
+        assert!(!utxo.is_expired(50, 0));
+        assert!(utxo.is_expired(51, 0));
+    }
+
+    #[test]
+    fn test_timestamp_expiry_sweeps_like_batch_expiry() {
+        let good_till_time = Order {
+            expiry_timestamp: 1_700_000_000,
+            ..order(Side::Sell, 100, 10, ALICE, 1)
+        };
+        let utxo = Utxo::new(good_till_time.clone());
+        assert!(!utxo.is_expired(0, 1_700_000_000));
+        assert!(utxo.is_expired(0, 1_700_000_001));
+        assert!(!Utxo::new(order(Side::Sell, 100, 10, ALICE, 1)).is_expired(0, u64::MAX));
+
+        // Resting from an earlier block, the sell is consumed once the block is past its expiry
+        let input = resting_batch(
+            std::slice::from_ref(&utxo),
+            BatchInput {
+                block_timestamp: 1_700_000_012,
+                ..new_orders_batch(vec![
+                    Order {
+                        nonce: 2,
+                        ..good_till_time
+                    },
+                    order(Side::Buy, 100, 10, BOB, 3),
+                ])
+            },
+        );
+        let output = match_orders(input.clone()).unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(output.consumed_utxo_ids, vec![utxo.id]);
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.owner, BOB);
+
+        assert_eq!(input.verify_block_timestamp(1_700_000_012), Ok(()));
+        assert_eq!(
+            input.verify_block_timestamp(1_700_000_000),
+            Err(MatchError::BlockTimestampMismatch {
+                expected: 1_700_000_000,
+                actual: 1_700_000_012,
+            })
+        );
+        let decoded = BatchInput::from_sol(&input.to_sol()).unwrap();
+        assert_eq!(decoded.block_timestamp, 1_700_000_012);
+        assert_eq!(decoded.new_orders[0].expiry_timestamp, 1_700_000_000);
     }
 
     #[test]
@@ -642,6 +2613,7 @@ mod tests {
             owner: Address::ZERO,
             nonce: 1,
             expiry_batch: 100,
+            ..Default::default()
         };
         let order2 = Order {
             side: Side::Sell,
@@ -650,20 +2622,20 @@ mod tests {
             owner: Address::ZERO,
             nonce: 2,
             expiry_batch: 100,
+            ..Default::default()
         };
 
         let utxo1 = Utxo::new(order1);
         let utxo2 = Utxo::new(order2);
-        let utxos = vec![utxo1.clone(), utxo2.clone()];
-
-        let (tree, root) = build_utxo_merkle_tree(&utxos);
+        let tree = UtxoTree::from_leaves(&[utxo1.id, utxo2.id]);
+        let root = tree.root();
 
         // Verify root is not zero
         assert_ne!(root, FixedBytes::ZERO);
 
         // Generate and verify proofs
-        let proof1 = generate_utxo_proof(&tree, 0).unwrap();
-        let proof2 = generate_utxo_proof(&tree, 1).unwrap();
+        let proof1 = tree.proof(0);
+        let proof2 = tree.proof(1);
 
         let uwp1 = UtxoWithProof {
             utxo: utxo1,
@@ -676,8 +2648,8 @@ mod tests {
             leaf_index: 1,
         };
 
-        assert!(uwp1.verify(&root, 2));
-        assert!(uwp2.verify(&root, 2));
+        assert!(uwp1.verify(&root));
+        assert!(uwp2.verify(&root));
     }
 
     #[test]
@@ -689,12 +2661,10 @@ mod tests {
             owner: Address::ZERO,
             nonce: 1,
             expiry_batch: 100,
+            ..Default::default()
         };
 
         let utxo = Utxo::new(order);
-        let utxos = vec![utxo.clone()];
-
-        let (_tree, _root) = build_utxo_merkle_tree(&utxos);
 
         // Try with wrong root
         let wrong_root = FixedBytes::from_slice(&[1u8; 32]);
@@ -704,6 +2674,1636 @@ mod tests {
             leaf_index: 0,
         };
 
-        assert!(!uwp.verify(&wrong_root, 1));
+        assert!(!uwp.verify(&wrong_root));
+    }
+
+    #[test]
+    fn test_match_orders_reports_invalid_proof() {
+        let order = Order {
+            side: Side::Sell,
+            price: 100,
+            quantity: 10,
+            owner: Address::ZERO,
+            nonce: 1,
+            expiry_batch: 100,
+            ..Default::default()
+        };
+        let utxo = Utxo::new(order);
+
+        let mut input = resting_batch(std::slice::from_ref(&utxo), BatchInput::default());
+        input.existing_utxos_with_proofs[0].proof_hashes = vec![[1u8; 32]];
+
+        let err = match_orders(input).unwrap_err();
+        assert_eq!(
+            err,
+            MatchError::InvalidProof {
+                leaf_index: 0,
+                utxo_id: utxo.id
+            }
+        );
+    }
+
+    #[test]
+    fn test_match_orders_rejects_tampered_utxo() {
+        let order = Order {
+            side: Side::Sell,
+            price: 100,
+            quantity: 10,
+            owner: Address::ZERO,
+            nonce: 1,
+            expiry_batch: 100,
+            ..Default::default()
+        };
+        let utxo = Utxo::new(order);
+        // Proof is valid for the ID, but the quantity no longer matches it
+        let mut tampered = utxo.clone();
+        tampered.order.quantity = 1_000;
+
+        let input = resting_batch(std::slice::from_ref(&tampered), BatchInput::default());
+
+        assert!(matches!(
+            match_orders(input),
+            Err(MatchError::MalformedUtxo { leaf_index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_sol_reports_malformed_order() {
+        let order = Order {
+            side: Side::Buy,
+            price: 100,
+            quantity: 10,
+            owner: Address::ZERO,
+            nonce: 1,
+            expiry_batch: 100,
+            ..Default::default()
+        };
+        let mut sol = BatchInput {
+            batch_index: 3,
+            utxo_merkle_root: FixedBytes::ZERO,
+            existing_utxos_with_proofs: vec![],
+            new_orders: vec![order.clone(), order],
+            ..Default::default()
+        }
+        .to_sol();
+        sol.newOrders[1].side = 7;
+
+        let err = BatchInput::from_sol(&sol).unwrap_err();
+        assert_eq!(
+            err,
+            MatchError::MalformedOrder {
+                index: 1,
+                reason: "invalid side"
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_chain_state() {
+        let input = BatchInput {
+            batch_index: 3,
+            utxo_merkle_root: FixedBytes::ZERO,
+            existing_utxos_with_proofs: vec![],
+            new_orders: vec![],
+            ..Default::default()
+        };
+
+        assert!(input
+            .verify_chain_state(FixedBytes::ZERO, 3, 1, 0, 0, 0)
+            .is_ok());
+        assert_eq!(
+            input.verify_chain_state(FixedBytes::ZERO, 4, 1, 0, 0, 0),
+            Err(MatchError::BatchIndexMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            input.verify_chain_state(FixedBytes::ZERO, 3, 2, 0, 0, 0),
+            Err(MatchError::MarketParamMismatch {
+                param: "self_trade_prevention"
+            })
+        );
+        assert_eq!(
+            input.verify_chain_state(FixedBytes::ZERO, 3, 1, 0, 1, 0),
+            Err(MatchError::MarketParamMismatch {
+                param: "post_only_policy"
+            })
+        );
+        assert_eq!(
+            input.verify_chain_state(FixedBytes::ZERO, 3, 1, 0, 0, 105),
+            Err(MatchError::LastTradePriceMismatch {
+                expected: 105,
+                actual: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_self_trade_cancel_newest_is_reported() {
+        let resting = order(Side::Sell, 100, 10, ALICE, 1);
+        let taker = order(Side::Buy, 100, 4, ALICE, 2);
+        let taker_id = taker.compute_utxo_id();
+
+        let output = match_orders(new_orders_batch(vec![resting.clone(), taker])).unwrap();
+
+        assert!(output.fills.is_empty());
+        assert_eq!(
+            output.cancelled_orders,
+            vec![CancelledOrder {
+                utxo_id: taker_id,
+                owner: ALICE,
+                quantity: 4,
+                reason: CancelReason::SelfTrade,
+            }]
+        );
+        // The resting quote survives
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].id, resting.compute_utxo_id());
+    }
+
+    #[test]
+    fn test_self_trade_cancel_oldest_lets_taker_trade() {
+        let stale = order(Side::Sell, 100, 10, ALICE, 1);
+        let bob_sell = order(Side::Sell, 101, 10, BOB, 2);
+        let taker = Order {
+            self_trade_prevention: Some(SelfTradePrevention::CancelOldest),
+            ..order(Side::Buy, 101, 6, ALICE, 3)
+        };
+
+        let output = match_orders(new_orders_batch(vec![stale, bob_sell, taker])).unwrap();
+
+        assert_eq!(output.cancelled_orders.len(), 1);
+        assert_eq!(output.cancelled_orders[0].quantity, 10);
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].maker, BOB);
+        assert_eq!(output.fills[0].quantity, 6);
+    }
+
+    #[test]
+    fn test_self_trade_decrement_and_cancel() {
+        let resting = order(Side::Sell, 100, 10, ALICE, 1);
+        let taker = order(Side::Buy, 100, 4, ALICE, 2);
+        let input = BatchInput {
+            self_trade_prevention: SelfTradePrevention::DecrementAndCancel,
+            ..new_orders_batch(vec![resting, taker])
+        };
+
+        let output = match_orders(input).unwrap();
+
+        assert_eq!(output.cancelled_orders.len(), 2);
+        assert!(output.cancelled_orders.iter().all(|c| c.quantity == 4));
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.quantity, 6);
+    }
+
+    #[test]
+    fn test_self_trade_cancels_existing_utxo() {
+        let resting = Utxo::new(order(Side::Sell, 100, 10, ALICE, 1));
+        let input = resting_batch(
+            std::slice::from_ref(&resting),
+            BatchInput {
+                self_trade_prevention: SelfTradePrevention::CancelOldest,
+                ..new_orders_batch(vec![order(Side::Buy, 100, 4, ALICE, 2)])
+            },
+        );
+
+        let output = match_orders(input).unwrap();
+
+        assert_eq!(output.consumed_utxo_ids, vec![resting.id]);
+        assert_eq!(output.cancelled_orders[0].utxo_id, resting.id);
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.side, Side::Buy);
+    }
+
+    #[test]
+    fn test_immediate_or_cancel_remainder_is_reported() {
+        let taker = Order {
+            time_in_force: TimeInForce::ImmediateOrCancel,
+            ..order(Side::Buy, 100, 25, BOB, 2)
+        };
+        let taker_id = taker.compute_utxo_id();
+
+        let output = match_orders(new_orders_batch(vec![
+            order(Side::Sell, 100, 10, ALICE, 1),
+            taker,
+        ]))
+        .unwrap();
+
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].quantity, 10);
+        assert!(output.new_utxos.is_empty());
+        assert_eq!(
+            output.cancelled_orders,
+            vec![CancelledOrder {
+                utxo_id: taker_id,
+                owner: BOB,
+                quantity: 15,
+                reason: CancelReason::ImmediateOrCancel,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fill_or_kill_is_all_or_nothing() {
+        let resting = order(Side::Sell, 100, 10, ALICE, 1);
+        let fok = |quantity, nonce| Order {
+            time_in_force: TimeInForce::FillOrKill,
+            ..order(Side::Buy, 100, quantity, BOB, nonce)
+        };
+
+        // Not enough liquidity: nothing fills and the whole order is cancelled
+        let output = match_orders(new_orders_batch(vec![resting.clone(), fok(25, 2)])).unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(output.cancelled_orders.len(), 1);
+        assert_eq!(output.cancelled_orders[0].quantity, 25);
+        assert_eq!(output.cancelled_orders[0].reason, CancelReason::FillOrKill);
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.quantity, 10);
+
+        // Enough liquidity: fills completely
+        let output = match_orders(new_orders_batch(vec![resting.clone(), fok(10, 2)])).unwrap();
+        assert_eq!(output.fills.len(), 1);
+        assert!(output.cancelled_orders.is_empty());
+        assert!(output.new_utxos.is_empty());
+
+        // The killed attempt is rolled back before the rest of the batch matches again,
+        // so the resting UTXO is consumed once, by the fill that remains
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let utxo = Utxo::new(resting);
+        let output = match_orders(resting_batch(
+            std::slice::from_ref(&utxo),
+            new_orders_batch(vec![fok(25, 2), order(Side::Buy, 100, 4, CAROL, 3)]),
+        ))
+        .unwrap();
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].taker, CAROL);
+        assert_eq!(output.consumed_utxo_ids, vec![utxo.id]);
+        assert_eq!(output.cancelled_orders.len(), 1);
+        assert_eq!(output.new_utxos[0].order.quantity, 6);
+    }
+
+    #[test]
+    fn test_post_only_crossing_order_is_rejected() {
+        let maker = Order {
+            post_only: true,
+            ..order(Side::Buy, 101, 10, BOB, 2)
+        };
+        let maker_id = maker.compute_utxo_id();
+
+        let output = match_orders(new_orders_batch(vec![
+            order(Side::Sell, 100, 10, ALICE, 1),
+            maker,
+        ]))
+        .unwrap();
+
+        assert!(output.fills.is_empty());
+        assert_eq!(output.cancelled_orders[0].reason, CancelReason::PostOnly);
+        assert_eq!(
+            output.post_only_results,
+            vec![PostOnlyResult {
+                utxo_id: maker_id,
+                new_utxo_id: FixedBytes::ZERO,
+                owner: BOB,
+                outcome: PostOnlyOutcome::Rejected,
+                price: 101,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_post_only_crossing_order_is_repriced() {
+        let input = BatchInput {
+            post_only_policy: PostOnlyPolicy::Reprice,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 10, ALICE, 1),
+                Order {
+                    post_only: true,
+                    ..order(Side::Buy, 101, 10, BOB, 2)
+                },
+                Order {
+                    post_only: true,
+                    ..order(Side::Sell, 90, 5, BOB, 3)
+                },
+            ])
+        };
+
+        let output = match_orders(input).unwrap();
+
+        let outcomes: Vec<_> = output
+            .post_only_results
+            .iter()
+            .map(|r| (r.outcome, r.price))
+            .collect();
+        // The buy moves below the 100 ask, the sell then sits above the repriced 99 bid
+        assert_eq!(
+            outcomes,
+            vec![
+                (PostOnlyOutcome::Repriced, 99),
+                (PostOnlyOutcome::Repriced, 100)
+            ]
+        );
+        assert!(output.cancelled_orders.is_empty());
+        assert!(output.fills.is_empty());
+        // Each result names the UTXO the order rests under at its new price
+        for result in &output.post_only_results {
+            assert_ne!(result.new_utxo_id, result.utxo_id);
+            assert!(output.new_utxos.iter().any(|u| u.id == result.new_utxo_id));
+        }
+        assert!(output
+            .new_utxos
+            .iter()
+            .any(|u| u.order.owner == BOB && u.order.price == 99));
+    }
+
+    #[test]
+    fn test_market_order_sweeps_to_worst_price() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let market = Order {
+            order_type: OrderType::Market,
+            ..order(Side::Buy, 102, 50, CAROL, 1)
+        };
+        let market_id = market.compute_utxo_id();
+
+        // The market order is older than the asks but still takes at their prices
+        let output = match_orders(new_orders_batch(vec![
+            market,
+            order(Side::Sell, 100, 10, ALICE, 2),
+            order(Side::Sell, 102, 15, BOB, 3),
+            order(Side::Sell, 103, 20, ALICE, 4),
+        ]))
+        .unwrap();
+
+        let fills: Vec<_> = output
+            .fills
+            .iter()
+            .map(|f| (f.price, f.quantity, f.taker))
+            .collect();
+        assert_eq!(fills, vec![(100, 10, CAROL), (102, 15, CAROL)]);
+        assert_eq!(
+            output.cancelled_orders,
+            vec![CancelledOrder {
+                utxo_id: market_id,
+                owner: CAROL,
+                quantity: 25,
+                reason: CancelReason::MarketRemainder,
+            }]
+        );
+        // Only the ask beyond the worst price rests
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.price, 103);
+    }
+
+    #[test]
+    fn test_min_fill_skips_dust_without_losing_priority() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        const DAVE: Address = Address::repeat_byte(0xd0);
+        let block = Order {
+            min_fill_quantity: 50,
+            ..order(Side::Buy, 100, 100, BOB, 3)
+        };
+
+        let output = match_orders(new_orders_batch(vec![
+            order(Side::Sell, 100, 10, ALICE, 1),
+            order(Side::Sell, 100, 60, CAROL, 2),
+            block,
+            order(Side::Buy, 100, 10, DAVE, 4),
+        ]))
+        .unwrap();
+
+        // The block buy passes over the 10 lot, which then goes to the next buyer
+        let fills: Vec<_> = output
+            .fills
+            .iter()
+            .map(|f| (f.maker, f.taker, f.quantity))
+            .collect();
+        assert_eq!(fills, vec![(CAROL, BOB, 60), (ALICE, DAVE, 10)]);
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.quantity, 40);
+    }
+
+    #[test]
+    fn test_all_or_none_fills_whole_quantity_only() {
+        let aon = Order {
+            all_or_none: true,
+            ..order(Side::Sell, 100, 30, ALICE, 1)
+        };
+
+        let output = match_orders(new_orders_batch(vec![
+            aon.clone(),
+            order(Side::Buy, 100, 20, BOB, 2),
+        ]))
+        .unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(output.new_utxos.len(), 2);
+
+        let output = match_orders(new_orders_batch(vec![
+            aon,
+            order(Side::Buy, 100, 45, BOB, 2),
+        ]))
+        .unwrap();
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].quantity, 30);
+    }
+
+    #[test]
+    fn test_iceberg_refresh_loses_priority_and_hides_reserve() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let iceberg = Order {
+            hidden_quantity: 25,
+            display_quantity: 10,
+            salt: FixedBytes::repeat_byte(0x5a),
+            ..order(Side::Sell, 100, 10, ALICE, 1)
+        };
+        assert_ne!(
+            iceberg.compute_utxo_id(),
+            iceberg.displayed().compute_utxo_id()
+        );
+        // Without the salt, the published order and ID would confirm a guessed reserve
+        let guess = Order {
+            hidden_quantity: 25,
+            display_quantity: 10,
+            ..iceberg.displayed()
+        };
+        assert_ne!(iceberg.compute_utxo_id(), guess.compute_utxo_id());
+
+        let output = match_orders(new_orders_batch(vec![
+            iceberg,
+            order(Side::Sell, 100, 5, BOB, 2),
+            order(Side::Buy, 100, 10, CAROL, 3),
+        ]))
+        .unwrap();
+
+        // Only the displayed slice trades, then the next slice moves behind BOB
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].maker, ALICE);
+        assert_eq!(output.fills[0].quantity, 10);
+        let refreshed = output
+            .new_utxos
+            .iter()
+            .find(|u| u.order.owner == ALICE)
+            .unwrap();
+        assert_eq!(refreshed.order.quantity, 10);
+        assert_eq!(refreshed.order.hidden_quantity, 15);
+        assert_eq!(refreshed.order.nonce, 4);
+
+        // The public views show only the displayed quantity
+        assert_eq!(
+            book_snapshot(&output.new_utxos),
+            vec![BookLevel {
+                side: Side::Sell,
+                price: 100,
+                quantity: 15,
+            }]
+        );
+        let public = refreshed.to_public_sol();
+        assert_eq!(public.id, refreshed.id);
+        assert_eq!(public.order.hiddenQuantity, 0);
+        assert_eq!(public.order.salt, FixedBytes::ZERO);
+        assert_eq!(refreshed.order.salt, FixedBytes::repeat_byte(0x5a));
+    }
+
+    #[test]
+    fn test_iceberg_refreshes_within_batch_behind_its_level() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let iceberg = Order {
+            hidden_quantity: 25,
+            display_quantity: 10,
+            ..order(Side::Sell, 100, 10, ALICE, 1)
+        };
+        let orders = vec![iceberg.clone(), order(Side::Sell, 100, 5, BOB, 2)];
+
+        // The next slice trades in the same batch, but only after BOB
+        let output = match_orders(new_orders_batch(
+            [orders.clone(), vec![order(Side::Buy, 100, 20, CAROL, 3)]].concat(),
+        ))
+        .unwrap();
+        let fills: Vec<(Address, u64)> = output
+            .fills
+            .iter()
+            .map(|fill| (fill.maker, fill.quantity))
+            .collect();
+        assert_eq!(fills, vec![(ALICE, 10), (BOB, 5), (ALICE, 5)]);
+        let alice: Vec<&Utxo> = output
+            .new_utxos
+            .iter()
+            .filter(|u| u.order.owner == ALICE)
+            .collect();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].order.quantity, 5);
+        assert_eq!(alice[0].order.hidden_quantity, 15);
+        assert_eq!(alice[0].order.nonce, 4);
+        assert_eq!(alice[0].filled_quantity, 15);
+
+        // A killed fill-or-kill takes back the refreshed slice with its fills
+        let fok = Order {
+            time_in_force: TimeInForce::FillOrKill,
+            ..order(Side::Buy, 100, 50, CAROL, 3)
+        };
+        let output = match_orders(new_orders_batch([orders, vec![fok]].concat())).unwrap();
+        assert!(output.fills.is_empty());
+        let alice: Vec<&Utxo> = output
+            .new_utxos
+            .iter()
+            .filter(|u| u.order.owner == ALICE)
+            .collect();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].id, iceberg.compute_utxo_id());
+    }
+
+    #[test]
+    fn test_stop_order_wakes_on_last_trade_and_trades_next_batch() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let stop = Order {
+            order_type: OrderType::Market,
+            trigger_price: 105,
+            ..order(Side::Buy, 0, 10, CAROL, 1)
+        };
+
+        // A trade at 106 crosses the trigger, but the stop only wakes up for the next batch
+        let output = match_orders(BatchInput {
+            last_trade_price: 100,
+            ..new_orders_batch(vec![
+                stop,
+                order(Side::Sell, 106, 5, ALICE, 2),
+                order(Side::Buy, 106, 5, BOB, 3),
+            ])
+        })
+        .unwrap();
+        assert_eq!(output.last_trade_price, 106);
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.new_utxos.len(), 1);
+        let woken = output.new_utxos[0].clone();
+        assert!(woken.order.triggered);
+
+        let output = match_orders(resting_batch(
+            std::slice::from_ref(&woken),
+            BatchInput {
+                batch_index: 1,
+                last_trade_price: 106,
+                ..new_orders_batch(vec![order(Side::Sell, 107, 10, ALICE, 4)])
+            },
+        ))
+        .unwrap();
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].taker, CAROL);
+        assert_eq!(output.fills[0].price, 107);
+        assert_eq!(output.consumed_utxo_ids, vec![woken.id]);
+        assert!(output.new_utxos.is_empty());
+    }
+
+    #[test]
+    fn test_dormant_stop_order_rests_untouched() {
+        let stop_limit = Order {
+            trigger_price: 95,
+            ..order(Side::Sell, 94, 10, ALICE, 1)
+        };
+        assert!(stop_limit.is_triggered_by(95));
+        assert!(!stop_limit.is_triggered_by(96));
+        assert!(!stop_limit.is_triggered_by(0));
+
+        // The dormant sell would cross the buy, yet takes no part until triggered
+        let output = match_orders(BatchInput {
+            last_trade_price: 100,
+            ..new_orders_batch(vec![stop_limit.clone(), order(Side::Buy, 100, 10, BOB, 2)])
+        })
+        .unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(output.last_trade_price, 100);
+        assert!(output
+            .new_utxos
+            .iter()
+            .any(|u| u.id == stop_limit.compute_utxo_id()));
+    }
+
+    #[test]
+    fn test_pegged_orders_priced_from_oracle_round() {
+        let round = OracleRound {
+            round_id: U80::from(7),
+            price: 100,
+        };
+        let pegged = |side, offset, owner, nonce| Order {
+            pegged: true,
+            peg_offset: offset,
+            ..order(side, 0, 10, owner, nonce)
+        };
+
+        // The bid pegged 1 above the oracle crosses the ask at 100, the one 2 below rests
+        let output = match_orders(BatchInput {
+            oracle_round: Some(round),
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 10, ALICE, 1),
+                pegged(Side::Buy, 1, BOB, 2),
+                pegged(Side::Buy, -2, BOB, 3),
+            ])
+        })
+        .unwrap();
+        assert_eq!(output.oracle_round, Some(round));
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].price, 100);
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.price, 98);
+
+        // Without an oracle round pegged orders sit out with their last price
+        let output = match_orders(new_orders_batch(vec![
+            order(Side::Sell, 100, 10, ALICE, 1),
+            Order {
+                price: 105,
+                ..pegged(Side::Buy, 1, BOB, 2)
+            },
+        ]))
+        .unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(output.new_utxos.len(), 2);
+
+        assert_eq!(
+            pegged(Side::Sell, -100, ALICE, 1).pegged_price(100, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_repriced_pegged_post_only_order_never_takes() {
+        let resting = vec![
+            Utxo::new(order(Side::Sell, 105, 10, ALICE, 1)),
+            Utxo::new(Order {
+                pegged: true,
+                post_only: true,
+                ..order(Side::Buy, 100, 10, BOB, 2)
+            }),
+        ];
+        let pegged_id = resting[1].id;
+        // The oracle moves the bid to 110, through the older ask at 105
+        let input = |post_only_policy| {
+            resting_batch(
+                &resting,
+                BatchInput {
+                    oracle_round: Some(OracleRound {
+                        round_id: U80::from(7),
+                        price: 110,
+                    }),
+                    post_only_policy,
+                    ..new_orders_batch(vec![])
+                },
+            )
+        };
+
+        let output = match_orders(input(PostOnlyPolicy::Reject)).unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(
+            output.post_only_results[0].outcome,
+            PostOnlyOutcome::Rejected
+        );
+        assert_eq!(output.cancelled_orders[0].utxo_id, pegged_id);
+        assert_eq!(output.cancelled_orders[0].reason, CancelReason::PostOnly);
+
+        // Repriced one tick behind the ask, as the successor of the resting UTXO
+        let output = match_orders(input(PostOnlyPolicy::Reprice)).unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(
+            output.post_only_results,
+            vec![PostOnlyResult {
+                utxo_id: pegged_id,
+                new_utxo_id: pegged_id,
+                owner: BOB,
+                outcome: PostOnlyOutcome::Repriced,
+                price: 104,
+            }]
+        );
+        assert!(output.consumed_utxo_ids.contains(&pegged_id));
+        let bid = output
+            .new_utxos
+            .iter()
+            .find(|utxo| utxo.order.owner == BOB)
+            .unwrap();
+        assert_eq!((bid.order.price, bid.parent_utxo_id), (104, pegged_id));
+    }
+
+    #[test]
+    fn test_oracle_answer_is_scaled_and_unusable_rounds_rejected() {
+        const NOW: u64 = 1_700_000_000;
+        let round = |round_id: u64, answer: i64, updated_at: u64, decimals| {
+            OracleRound::from_answer(
+                U80::from(round_id),
+                I256::try_from(answer).unwrap(),
+                U256::from(updated_at),
+                decimals,
+                -2,
+                NOW,
+            )
+        };
+
+        // 2500.12345678 with 8 decimals is 250012 at exponent -2, and 25 with none is 2500
+        assert_eq!(
+            round(7, 250_012_345_678, NOW - 60, 8).unwrap().price,
+            250_012
+        );
+        assert_eq!(round(7, 25, NOW, 0).unwrap().price, 2_500);
+
+        assert_eq!(round(0, 25, NOW, 0), Err("oracle round ID is zero"));
+        assert_eq!(
+            round(7, 25, NOW - MAX_ORACLE_AGE - 1, 0),
+            Err("oracle round is stale")
+        );
+        assert_eq!(round(7, 25, 0, 0), Err("oracle round is stale"));
+        assert_eq!(round(7, -5, NOW, 0), Err("oracle answer is not positive"));
+        assert_eq!(round(7, 5, NOW, 8), Err("oracle answer rounds to zero"));
+        assert_eq!(
+            round(7, i64::MAX, NOW, 0),
+            Err("oracle answer out of range")
+        );
+    }
+
+    #[test]
+    fn test_owner_cancellation_removes_utxo_before_matching() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let owner = Address::from_private_key(&key);
+
+        let resting = Utxo::new(order(Side::Sell, 100, 10, owner, 1));
+        let input = |cancellation| {
+            resting_batch(
+                std::slice::from_ref(&resting),
+                BatchInput {
+                    cancellations: vec![cancellation],
+                    ..new_orders_batch(vec![order(Side::Buy, 100, 10, BOB, 2)])
+                },
+            )
+        };
+
+        let output = match_orders(input(Cancellation {
+            utxo_id: resting.id,
+            signature: sign(&key, resting.id),
+        }))
+        .unwrap();
+        assert!(output.fills.is_empty());
+        assert_eq!(output.consumed_utxo_ids, vec![resting.id]);
+        assert_eq!(
+            output.cancelled_orders,
+            vec![CancelledOrder {
+                utxo_id: resting.id,
+                owner,
+                quantity: 10,
+                reason: CancelReason::OwnerRequest,
+            }]
+        );
+
+        // Anyone but the owner is refused, without holding up the rest of the batch
+        let other = k256::ecdsa::SigningKey::from_slice(&[8u8; 32]).unwrap();
+        let forged = input(Cancellation {
+            utxo_id: resting.id,
+            signature: sign(&other, resting.id),
+        });
+        let output = match_orders(forged.clone()).unwrap();
+        assert_eq!(output.fills.len(), 1);
+        assert!(output.cancelled_orders.is_empty());
+        assert_eq!(
+            output.rejected_intents,
+            vec![RejectedIntent {
+                utxo_id: resting.id,
+                kind: IntentKind::Cancellation,
+                reason: IntentRejection::BadSignature,
+            }]
+        );
+
+        // As is a second cancellation of the same UTXO
+        let mut twice = input(Cancellation {
+            utxo_id: resting.id,
+            signature: sign(&key, resting.id),
+        });
+        twice.cancellations.push(twice.cancellations[0].clone());
+        let output = match_orders(twice).unwrap();
+        assert_eq!(output.cancelled_orders.len(), 1);
+        assert_eq!(output.rejected_intents[0].reason, IntentRejection::NotOpen);
+        assert_eq!(output.to_sol().rejectedIntents.len(), 1);
+        let decoded = BatchInput::from_sol(&forged.to_sol()).unwrap();
+        assert_eq!(decoded.cancellations, forged.cancellations);
+    }
+
+    #[test]
+    fn test_amendment_keeps_priority_only_on_size_reduction() {
+        let key = k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let owner = Address::from_private_key(&key);
+
+        let first = Utxo::new(order(Side::Sell, 100, 10, owner, 1));
+        let second = Utxo::new(order(Side::Sell, 100, 10, BOB, 2));
+        let resting = vec![first.clone(), second.clone()];
+        let amend = |price, quantity| {
+            let amendment = Amendment {
+                utxo_id: first.id,
+                price,
+                quantity,
+                signature: sign(&key, (first.id, price, quantity).abi_encode_packed()),
+            };
+            resting_batch(
+                &resting,
+                BatchInput {
+                    amendments: vec![amendment],
+                    ..new_orders_batch(vec![order(Side::Buy, 100, 5, ALICE, 3)])
+                },
+            )
+        };
+
+        // Reducing the size keeps the original nonce, so it still fills first
+        let output = match_orders(amend(100, 6)).unwrap();
+        // The replacement follows the amended UTXO
+        let replacement =
+            Utxo::with_lineage(order(Side::Sell, 100, 6, owner, 1), first.id, 6, 0, 0);
+        assert_eq!(
+            output.amended_orders,
+            vec![AmendedOrder {
+                old_utxo_id: first.id,
+                new_utxo_id: replacement.id,
+                owner,
+                priority_kept: true,
+            }]
+        );
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].maker_utxo_id, replacement.id);
+        assert!(output.consumed_utxo_ids.contains(&first.id));
+        assert!(!output.consumed_utxo_ids.contains(&second.id));
+
+        // Growing the size goes behind every order in the batch
+        let output = match_orders(amend(100, 20)).unwrap();
+        assert!(!output.amended_orders[0].priority_kept);
+        assert_eq!(output.fills[0].maker_utxo_id, second.id);
+        let requeued = output
+            .new_utxos
+            .iter()
+            .find(|utxo| utxo.order.owner == owner)
+            .unwrap();
+        assert_eq!((requeued.order.quantity, requeued.order.nonce), (20, 4));
+
+        // The signature covers the new price and quantity; a tampered amendment is
+        // dropped and the original UTXO trades as it was
+        let mut tampered = amend(100, 6);
+        tampered.amendments[0].quantity = 1;
+        let output = match_orders(tampered).unwrap();
+        assert!(output.amended_orders.is_empty());
+        assert_eq!(output.fills[0].maker_utxo_id, first.id);
+        assert_eq!(
+            output.rejected_intents,
+            vec![RejectedIntent {
+                utxo_id: first.id,
+                kind: IntentKind::Amendment,
+                reason: IntentRejection::BadSignature,
+            }]
+        );
+
+        // As is one that changes nothing
+        let output = match_orders(amend(100, 10)).unwrap();
+        assert_eq!(output.fills[0].maker_utxo_id, first.id);
+        assert_eq!(
+            output.rejected_intents[0].reason,
+            IntentRejection::InvalidAmendment
+        );
+    }
+
+    #[test]
+    fn test_fee_rounds_down_and_never_overflows() {
+        let fees = FeeSchedule {
+            maker_fee_bps: 10,
+            taker_fee_bps: 25,
+        };
+        // 1_000_003 * 10 / 10_000 = 1000.003
+        assert_eq!(fees.maker_fee(U256::from(1_000_003)), U256::from(1_000));
+        // 21 * 25 / 10_000 = 0.0525, too small to charge
+        assert_eq!(fees.taker_fee(U256::from(21)), U256::ZERO);
+        assert_eq!(fees.taker_fee(U256::from(400)), U256::from(1));
+        assert_eq!(fee_on(U256::MAX, MAX_FEE_BPS), U256::MAX);
+        assert_eq!(fee_on(U256::MAX, 25), U256::MAX / U256::from(400));
+
+        let output = match_orders(BatchInput {
+            fee_schedule: fees,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 1_000, 7, ALICE, 1),
+                order(Side::Buy, 1_000, 7, BOB, 2),
+            ])
+        })
+        .unwrap();
+        assert_eq!(
+            (output.fills[0].maker_fee, output.fills[0].taker_fee),
+            (U256::from(7), U256::from(17))
+        );
+    }
+
+    #[test]
+    fn test_net_transfers_settle_fills_per_account() {
+        let output = match_orders(BatchInput {
+            fee_schedule: FeeSchedule {
+                maker_fee_bps: 10,
+                taker_fee_bps: 20,
+            },
+            ..new_orders_batch(vec![
+                order(Side::Sell, 10, 1_000, ALICE, 1),
+                order(Side::Sell, 10, 1_000, ALICE, 2),
+                order(Side::Sell, 11, 1_000, ALICE, 3),
+                order(Side::Buy, 11, 3_000, BOB, 4),
+            ])
+        })
+        .unwrap();
+        assert_eq!(output.fills.len(), 3);
+
+        // Notional 31_000; ALICE pays 31 in maker fees, BOB 62 in taker fees
+        let signed = |value: i64| I256::try_from(value).unwrap();
+        assert_eq!(
+            output.net_transfers,
+            vec![
+                NetTransfer {
+                    account: ALICE,
+                    asset_a: signed(-3_000),
+                    asset_b: signed(31_000 - 31),
+                },
+                NetTransfer {
+                    account: BOB,
+                    asset_a: signed(3_000),
+                    asset_b: signed(-31_000 - 62),
+                },
+            ]
+        );
+        let sol = output.to_sol();
+        assert_eq!(sol.netTransfers.len(), 2);
+        assert_eq!(sol.netTransfers[1].assetB, signed(-31_062));
+
+        assert!(net_transfers(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_fixed_point_notional() {
+        // 0.000321 AssetB per AssetA
+        assert_eq!(notional(321, 1_000_000, -6), U256::from(321));
+        // 0.4815 rounds down to nothing
+        assert_eq!(notional(321, 1_500, -6), U256::ZERO);
+        assert_eq!(
+            notional(321, 10u64.pow(18), -6),
+            U256::from(321) * U256::from(10u64.pow(12))
+        );
+        assert_eq!(notional(5, 3, 2), U256::from(1_500));
+        assert_eq!(
+            notional(u64::MAX, u64::MAX, MAX_PRICE_EXPONENT),
+            U256::from(u64::MAX) * U256::from(u64::MAX) * U256::from(10u64.pow(18))
+        );
+
+        let market_params = MarketParams {
+            price_exponent: -6,
+            ..Default::default()
+        };
+        let priced = |side, quantity, owner, nonce| Order {
+            price_exponent: -6,
+            ..order(side, 321, quantity, owner, nonce)
+        };
+        let output = match_orders(BatchInput {
+            market_params,
+            ..new_orders_batch(vec![
+                priced(Side::Sell, 3_000_000, ALICE, 1),
+                priced(Side::Buy, 3_000_000, BOB, 2),
+                // Not in the market's exponent
+                order(Side::Buy, 321, 3_000_000, BOB, 3),
+            ])
+        })
+        .unwrap();
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].price_exponent, -6);
+        assert_eq!(output.fills[0].notional, U256::from(963));
+        assert_eq!(
+            output.cancelled_orders[0].reason,
+            CancelReason::PriceExponent
+        );
+    }
+
+    #[test]
+    fn test_fills_never_round_to_zero_notional() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let priced = |side, quantity, owner, nonce| Order {
+            price_exponent: -6,
+            ..order(side, 321, quantity, owner, nonce)
+        };
+        let output = match_orders(BatchInput {
+            market_params: MarketParams {
+                price_exponent: -6,
+                ..Default::default()
+            },
+            ..new_orders_batch(vec![
+                priced(Side::Sell, 10_000, ALICE, 1),
+                // 3_000 at 0.000321 is worth less than one unit of AssetB
+                priced(Side::Buy, 3_000, BOB, 2),
+                priced(Side::Buy, 4_000, CAROL, 3),
+            ])
+        })
+        .unwrap();
+
+        // BOB's dust fill is skipped and CAROL trades past it
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].taker, CAROL);
+        assert_eq!(output.fills[0].notional, U256::from(1));
+        let bob = output
+            .new_utxos
+            .iter()
+            .find(|u| u.order.owner == BOB)
+            .unwrap();
+        assert_eq!(bob.order.quantity, 3_000);
+    }
+
+    #[test]
+    fn test_repriced_and_pegged_orders_stay_on_tick() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        let market_params = MarketParams {
+            tick_size: 5,
+            min_notional: 1_000,
+            ..Default::default()
+        };
+        let post_only = |order: Order| Order {
+            post_only: true,
+            ..order
+        };
+        let output = match_orders(BatchInput {
+            market_params,
+            post_only_policy: PostOnlyPolicy::Reprice,
+            ..new_orders_batch(vec![
+                order(Side::Sell, 100, 10, ALICE, 1),
+                post_only(order(Side::Buy, 105, 20, BOB, 2)),
+                post_only(order(Side::Buy, 100, 10, CAROL, 3)),
+            ])
+        })
+        .unwrap();
+
+        // Both buys move a whole tick below the ask, where CAROL's is below the minimum notional
+        let outcomes: Vec<_> = output
+            .post_only_results
+            .iter()
+            .map(|r| (r.owner, r.outcome, r.price))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (BOB, PostOnlyOutcome::Repriced, 95),
+                (CAROL, PostOnlyOutcome::Rejected, 100)
+            ]
+        );
+        assert_eq!(output.cancelled_orders.len(), 1);
+        assert_eq!(
+            output.cancelled_orders[0].reason,
+            CancelReason::BelowMinNotional
+        );
+
+        // An oracle price between ticks pegs a bid down and an ask up
+        let pegged = |side, offset| Order {
+            pegged: true,
+            peg_offset: offset,
+            ..order(side, 0, 10, ALICE, 1)
+        };
+        let output = match_orders(BatchInput {
+            market_params,
+            oracle_round: Some(OracleRound {
+                round_id: U80::from(1),
+                price: 102,
+            }),
+            ..new_orders_batch(vec![pegged(Side::Buy, 0), pegged(Side::Sell, 5)])
+        })
+        .unwrap();
+        let mut prices: Vec<u64> = output
+            .new_utxos
+            .iter()
+            .map(|utxo| utxo.order.price)
+            .collect();
+        prices.sort_unstable();
+        assert_eq!(prices, vec![100, 110]);
+        assert_eq!(pegged(Side::Buy, -100).pegged_price(102, 5), None);
+    }
+
+    #[test]
+    fn test_market_params_reject_non_conforming_new_orders() {
+        let market_params = MarketParams {
+            tick_size: 5,
+            lot_size: 10,
+            min_quantity: 20,
+            min_notional: 5_000,
+            price_exponent: 0,
+        };
+        let rejected = [
+            (order(Side::Buy, 252, 20, ALICE, 2), CancelReason::OffTick),
+            (order(Side::Buy, 250, 25, ALICE, 3), CancelReason::OffLot),
+            (
+                order(Side::Buy, 250, 10, ALICE, 4),
+                CancelReason::BelowMinQuantity,
+            ),
+            (
+                order(Side::Buy, 200, 20, ALICE, 5),
+                CancelReason::BelowMinNotional,
+            ),
+            (
+                Order {
+                    hidden_quantity: 20,
+                    display_quantity: 15,
+                    ..order(Side::Buy, 250, 20, ALICE, 6)
+                },
+                CancelReason::OffLot,
+            ),
+            (
+                Order {
+                    min_fill_quantity: 15,
+                    ..order(Side::Buy, 250, 20, ALICE, 7)
+                },
+                CancelReason::OffLot,
+            ),
+        ];
+        let mut new_orders = vec![order(Side::Sell, 250, 20, ALICE, 1)];
+        new_orders.extend(rejected.iter().map(|(order, _)| order.clone()));
+        new_orders.push(order(Side::Buy, 250, 20, BOB, 8));
+        let input = BatchInput {
+            market_params,
+            ..new_orders_batch(new_orders)
+        };
+
+        let output = match_orders(input.clone()).unwrap();
+        assert_eq!(
+            output.cancelled_orders,
+            rejected
+                .iter()
+                .map(|(order, reason)| CancelledOrder {
+                    utxo_id: order.compute_utxo_id(),
+                    owner: ALICE,
+                    quantity: order.total_quantity(),
+                    reason: *reason,
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].taker, BOB);
+
+        // Empty orders never rest, whatever the market parameters
+        let output = match_orders(new_orders_batch(vec![order(Side::Buy, 100, 0, ALICE, 1)]));
+        assert_eq!(
+            output.unwrap().cancelled_orders[0].reason,
+            CancelReason::BelowMinQuantity
+        );
+
+        assert_eq!(input.verify_market_params(market_params), Ok(()));
+        assert_eq!(
+            input.verify_market_params(MarketParams {
+                lot_size: 1,
+                ..market_params
+            }),
+            Err(MatchError::MarketParamMismatch { param: "lot_size" })
+        );
+        let decoded = BatchInput::from_sol(&input.to_sol()).unwrap();
+        assert_eq!(decoded.market_params, market_params);
+    }
+
+    #[test]
+    fn test_price_band_rejects_orders_outside_it() {
+        let price_band = PriceBand {
+            band_bps: 1_000,
+            policy: PriceBandPolicy::RejectOrders,
+        };
+        assert_eq!(price_band.bounds(100), Some((90, 110)));
+        assert_eq!(price_band.bounds(0), None);
+
+        // A sell resting from before the band was set sits out of the batch
+        let stale = Utxo::new(order(Side::Sell, 120, 10, ALICE, 1));
+        let fat_finger = order(Side::Buy, 150, 10, BOB, 3);
+        let input = resting_batch(
+            std::slice::from_ref(&stale),
+            BatchInput {
+                last_trade_price: 100,
+                price_band,
+                ..new_orders_batch(vec![
+                    order(Side::Sell, 105, 10, ALICE, 2),
+                    fat_finger.clone(),
+                    Order {
+                        order_type: OrderType::Market,
+                        ..order(Side::Buy, 0, 20, BOB, 4)
+                    },
+                ])
+            },
+        );
+
+        let output = match_orders(input.clone()).unwrap();
+        assert!(!output.halted);
+        assert_eq!(output.fills.len(), 1);
+        assert_eq!(output.fills[0].price, 105);
+        assert_eq!(
+            output.cancelled_orders[0].utxo_id,
+            fat_finger.compute_utxo_id()
+        );
+        assert_eq!(output.cancelled_orders[0].reason, CancelReason::PriceBand);
+        assert_eq!(
+            output.cancelled_orders[1].reason,
+            CancelReason::MarketRemainder
+        );
+        // The stale sell was not touched, so it keeps its UTXO
+        assert!(output.new_utxos.is_empty());
+        assert!(output.consumed_utxo_ids.is_empty());
+        let next = output.next_utxo_set(&[(0, stale.clone())]);
+        assert_eq!(next.len(), 1);
+        assert_eq!((next[0].0, next[0].1.id), (0, stale.id));
+        assert_eq!(output.new_utxo_merkle_root, input.utxo_merkle_root);
+
+        assert_eq!(input.verify_price_band(1_000, 0), Ok(()));
+        assert_eq!(
+            input.verify_price_band(1_000, 1),
+            Err(MatchError::MarketParamMismatch {
+                param: "price_band_policy"
+            })
+        );
+        let decoded = BatchInput::from_sol(&input.to_sol()).unwrap();
+        assert_eq!(decoded.price_band, price_band);
+    }
+
+    #[test]
+    fn test_price_band_halts_matching() {
+        let input = BatchInput {
+            last_trade_price: 100,
+            price_band: PriceBand {
+                band_bps: 1_000,
+                policy: PriceBandPolicy::HaltMatching,
+            },
+            ..new_orders_batch(vec![
+                order(Side::Sell, 105, 10, ALICE, 1),
+                order(Side::Sell, 150, 10, ALICE, 2),
+                order(Side::Buy, 150, 20, BOB, 3),
+            ])
+        };
+
+        // The buy would fill at 150, so nothing fills and every order rests
+        let output = match_orders(input.clone()).unwrap();
+        assert!(output.halted);
+        assert!(output.fills.is_empty());
+        assert!(output.cancelled_orders.is_empty());
+        assert_eq!(output.new_utxos.len(), 3);
+        assert_eq!(output.last_trade_price, 100);
+        assert!(output.to_sol().halted);
+
+        // Within the band the batch matches as usual
+        let output = match_orders(BatchInput {
+            new_orders: input.new_orders[..1]
+                .iter()
+                .cloned()
+                .chain([order(Side::Buy, 105, 10, BOB, 3)])
+                .collect(),
+            ..input
+        })
+        .unwrap();
+        assert!(!output.halted);
+        assert_eq!(output.fills.len(), 1);
+    }
+
+    #[test]
+    fn test_match_markets_clears_each_market_separately() {
+        const ETH_USDC: Address = Address::repeat_byte(0xe1);
+        const WBTC_USDC: Address = Address::repeat_byte(0xb1);
+        let in_market = |market, order| Order { market, ..order };
+        let eth = BatchInput {
+            market: ETH_USDC,
+            ..new_orders_batch(vec![
+                in_market(ETH_USDC, order(Side::Sell, 100, 10, ALICE, 1)),
+                in_market(ETH_USDC, order(Side::Buy, 100, 10, BOB, 2)),
+            ])
+        };
+        let btc = BatchInput {
+            market: WBTC_USDC,
+            matching_engine: EngineKind::BatchAuction,
+            ..new_orders_batch(vec![
+                in_market(WBTC_USDC, order(Side::Sell, 100, 5, ALICE, 1)),
+                in_market(WBTC_USDC, order(Side::Buy, 90, 5, BOB, 2)),
+            ])
+        };
+
+        // The same price and nonce give different UTXOs in different markets
+        let outputs = match_markets(vec![eth.clone(), btc.clone()]).unwrap();
+        assert_eq!(outputs[0].market, ETH_USDC);
+        assert_eq!(outputs[0].fills.len(), 1);
+        assert_eq!(outputs[1].market, WBTC_USDC);
+        assert!(outputs[1].fills.is_empty());
+        assert_eq!(outputs[1].new_utxos.len(), 2);
+        assert_ne!(
+            outputs[1].new_utxos[0].id,
+            Utxo::new(order(Side::Sell, 100, 5, ALICE, 1)).id
+        );
+
+        let commitment = Commitment {
+            id: U256::ZERO,
+            digest: FixedBytes::ZERO,
+            configID: FixedBytes::ZERO,
+        };
+        let journal = to_journal(&outputs, commitment);
+        assert_eq!(journal.markets.len(), 2);
+        assert_eq!(journal.markets[1].market, WBTC_USDC);
+        assert_eq!(journal.markets[1].matchingEngine, 1);
+
+        assert_eq!(
+            match_markets(vec![eth.clone(), eth.clone()]).unwrap_err(),
+            MatchError::DuplicateMarket { market: ETH_USDC }
+        );
+        // An order can only trade in the market it was placed in
+        let mut misrouted = btc;
+        misrouted.new_orders.push(eth.new_orders[0].clone());
+        assert_eq!(
+            match_markets(vec![eth, misrouted]).unwrap_err(),
+            MatchError::InMarket {
+                market: WBTC_USDC,
+                error: Box::new(MatchError::MalformedOrder {
+                    index: 2,
+                    reason: "order belongs to another market",
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_batch_auction_uniform_price() {
+        let input = BatchInput {
+            matching_engine: EngineKind::BatchAuction,
+            ..new_orders_batch(vec![
+                order(Side::Buy, 105, 100, ALICE, 1),
+                order(Side::Buy, 103, 50, ALICE, 2),
+                order(Side::Sell, 99, 75, BOB, 3),
+                order(Side::Sell, 101, 150, BOB, 4),
+            ])
+        };
+
+        let output = match_orders(input).unwrap();
+
+        // 101 and 103 both clear 150 with the same imbalance, so the midpoint wins
+        assert!(output.fills.iter().all(|f| f.price == 102));
+        assert_eq!(output.fills.iter().map(|f| f.quantity).sum::<u64>(), 150);
+        assert_eq!(output.new_utxos.len(), 1);
+        assert_eq!(output.new_utxos[0].order.price, 101);
+        assert_eq!(output.new_utxos[0].order.quantity, 75);
+    }
+
+    #[test]
+    fn test_untouched_utxos_are_left_out_of_the_output() {
+        let previous = vec![
+            Utxo::new(order(Side::Sell, 105, 10, ALICE, 1)),
+            Utxo::new(order(Side::Sell, 101, 10, ALICE, 2)),
+            Utxo::new(order(Side::Buy, 95, 10, ALICE, 3)),
+        ];
+        let input = resting_batch(
+            &previous,
+            new_orders_batch(vec![
+                order(Side::Buy, 101, 4, BOB, 4),
+                order(Side::Buy, 90, 5, BOB, 5),
+            ]),
+        );
+
+        let output = match_orders(input).unwrap();
+
+        // Only the partly filled sell is consumed and re-created, next to the new bid
+        assert_eq!(output.consumed_utxo_ids, vec![previous[1].id]);
+        let created: Vec<(u64, u64)> = output
+            .new_utxos
+            .iter()
+            .map(|utxo| (utxo.order.price, utxo.order.quantity))
+            .collect();
+        assert_eq!(created, vec![(90, 5), (101, 6)]);
+
+        // Untouched UTXOs keep their leaves, the consumed one leaves a tombstone and
+        // the new ones are appended
+        let previous: Vec<(u64, Utxo)> = (0..).zip(previous).collect();
+        let next = output.next_utxo_set(&previous);
+        let next_leaves: Vec<(u64, FixedBytes<32>)> =
+            next.iter().map(|(index, utxo)| (*index, utxo.id)).collect();
+        assert_eq!(
+            next_leaves,
+            vec![
+                (0, previous[0].1.id),
+                (2, previous[2].1.id),
+                (3, output.new_utxos[0].id),
+                (4, output.new_utxos[1].id),
+            ]
+        );
+        assert_eq!(output.new_utxo_leaf_count, 5);
+        let tree = UtxoTree::from_leaves(&[
+            previous[0].1.id,
+            FixedBytes::ZERO,
+            previous[2].1.id,
+            output.new_utxos[0].id,
+            output.new_utxos[1].id,
+        ]);
+        assert_eq!(output.new_utxo_merkle_root, tree.root());
+    }
+
+    #[test]
+    fn test_utxo_tree_updates_from_proofs() {
+        let leaf = |byte| FixedBytes::repeat_byte(byte);
+        let full = UtxoTree::from_leaves(&[leaf(1), leaf(2), leaf(3), FixedBytes::ZERO, leaf(5)]);
+
+        // A tree that only knows the paths of leaves 1 and 3 and of the free leaf
+        let mut partial = UtxoTree::from_root(full.root(), full.leaf_count());
+        assert!(partial.insert_proof(1, leaf(2), &full.proof(1)));
+        assert!(partial.insert_proof(2, leaf(3), &full.proof(2)));
+        assert!(partial.insert_proof(5, FixedBytes::ZERO, &full.proof(5)));
+        assert!(!partial.insert_proof(4, leaf(6), &full.proof(4)));
+        assert!(!partial.insert_proof(0, leaf(1), &full.proof(1)));
+
+        partial.remove(1);
+        partial.remove(2);
+        assert_eq!(partial.push(leaf(6)), 5);
+        assert_eq!(partial.push(leaf(7)), 6);
+
+        let expected = UtxoTree::from_leaves(&[
+            leaf(1),
+            FixedBytes::ZERO,
+            FixedBytes::ZERO,
+            FixedBytes::ZERO,
+            leaf(5),
+            leaf(6),
+            leaf(7),
+        ]);
+        assert_eq!(partial.root(), expected.root());
+        assert_eq!(partial.leaf_count(), 7);
+
+        // Leaves nothing touched keep their index and still verify
+        let mut untouched = partial.clone();
+        assert!(untouched.insert_proof(4, leaf(5), &expected.proof(4)));
+
+        // Once every UTXO is consumed the root is zero again, as for an empty book
+        let mut emptied = expected.clone();
+        for leaf_index in [0, 4, 5, 6] {
+            emptied.remove(leaf_index);
+        }
+        assert_eq!(emptied.root(), FixedBytes::ZERO);
+        assert_eq!(UtxoTree::default().root(), FixedBytes::ZERO);
+    }
+
+    #[test]
+    fn test_append_proof_must_show_a_free_leaf() {
+        let resting = Utxo::new(order(Side::Sell, 100, 10, ALICE, 1));
+        let input = resting_batch(
+            std::slice::from_ref(&resting),
+            new_orders_batch(vec![order(Side::Buy, 90, 10, BOB, 2)]),
+        );
+        assert_eq!(input.verify_utxo_leaf_count(1), Ok(()));
+        assert_eq!(
+            input.verify_utxo_leaf_count(2),
+            Err(MatchError::LeafCountMismatch {
+                expected: 2,
+                actual: 1
+            })
+        );
+
+        // Claiming fewer leaves would append over the resting UTXO
+        let output = match_orders(BatchInput {
+            utxo_leaf_count: 0,
+            append_proof: vec![],
+            ..input.clone()
+        });
+        assert_eq!(
+            output.unwrap_err(),
+            MatchError::InvalidAppendProof { leaf_index: 0 }
+        );
+
+        let output = match_orders(input).unwrap();
+        assert_eq!(output.new_utxo_leaf_count, 2);
+        assert_eq!(
+            output.new_utxo_merkle_root,
+            UtxoTree::from_leaves(&[resting.id, output.new_utxos[0].id]).root()
+        );
+    }
+
+    #[test]
+    fn test_remainders_follow_their_order_across_batches() {
+        let batch = |previous: &[Utxo], new_orders| {
+            match_orders(resting_batch(previous, new_orders_batch(new_orders))).unwrap()
+        };
+
+        // A new sell partly fills, and its remainder follows the submitted order
+        let sell = order(Side::Sell, 100, 10, ALICE, 1);
+        let output = batch(&[], vec![sell.clone(), order(Side::Buy, 100, 4, BOB, 2)]);
+        let first = output.new_utxos[0].clone();
+        assert_eq!(first.parent_utxo_id, sell.compute_utxo_id());
+        assert_eq!((first.original_quantity, first.filled_quantity), (10, 4));
+        assert_eq!(first.order.quantity, 6);
+
+        // The next fill adds up, and the lineage is covered by the ID
+        let output = batch(
+            std::slice::from_ref(&first),
+            vec![order(Side::Buy, 100, 3, BOB, 3)],
+        );
+        let second = &output.new_utxos[0];
+        assert_eq!(second.parent_utxo_id, first.id);
+        assert_eq!((second.original_quantity, second.filled_quantity), (10, 7));
+        assert_eq!(second.id, second.compute_id());
+
+        let forged = Utxo {
+            filled_quantity: 0,
+            ..first
+        };
+        let input = resting_batch(std::slice::from_ref(&forged), BatchInput::default());
+        assert!(matches!(
+            match_orders(input),
+            Err(MatchError::MalformedUtxo { .. })
+        ));
+    }
+
+    #[test]
+    fn test_quote_buy_spends_budget_at_each_fill_price() {
+        let quote_buy = Order {
+            quote_quantity: 1_000,
+            ..order(Side::Buy, 10, 0, BOB, 3)
+        };
+        let output = match_orders(new_orders_batch(vec![
+            order(Side::Sell, 8, 30, ALICE, 1),
+            order(Side::Sell, 10, 50, ALICE, 2),
+            quote_buy,
+        ]))
+        .unwrap();
+
+        // 240 buys 30 at 8, and the remaining 760 would buy 76 at 10 but 50 are offered
+        let notionals: Vec<U256> = output.fills.iter().map(|fill| fill.notional).collect();
+        assert_eq!(notionals, vec![U256::from(240), U256::from(500)]);
+        assert_eq!(output.new_utxos.len(), 1);
+        let remainder = &output.new_utxos[0].order;
+        assert_eq!((remainder.quote_quantity, remainder.quantity), (260, 26));
+
+        // The notional is rounded down: 36 at 0.3 costs 10.8, paid as 10
+        let budget = Order {
+            quote_quantity: 10,
+            price_exponent: -1,
+            ..order(Side::Buy, 3, 0, BOB, 1)
+        };
+        assert_eq!(budget.quantity_at(3, 0), 36);
+        assert_eq!(budget.quantity_at(3, 5), 35);
+        assert_eq!(budget.quantity_at(0, 0), 0);
+
+        let sell = SolOrder {
+            side: Side::Sell.into(),
+            ..SolOrder::from(&budget)
+        };
+        assert!(Order::try_from(&sell).is_err());
+    }
+
+    #[test]
+    fn test_quote_buy_lineage_records_its_budget() {
+        // 1,000 buys 100 at the limit price of 10, but 120 from the ask at 5
+        let quote_buy = Order {
+            quote_quantity: 1_000,
+            ..order(Side::Buy, 10, 0, BOB, 2)
+        };
+        let output = match_orders(new_orders_batch(vec![
+            order(Side::Sell, 5, 120, ALICE, 1),
+            quote_buy,
+        ]))
+        .unwrap();
+        assert_eq!(output.fills[0].quantity, 120);
+
+        // The remainder follows the budget, and its fills are counted in AssetA
+        let remainder = &output.new_utxos[0];
+        assert_eq!(remainder.original_quantity, 0);
+        assert_eq!(remainder.original_quote_quantity, 1_000);
+        assert_eq!(remainder.filled_quantity, 120);
+        assert_eq!(remainder.order.quote_quantity, 400);
+
+        // The budget is covered by the ID and published with the remainder
+        let public = remainder.to_public_sol();
+        assert_eq!(public.originalQuoteQuantity, 1_000);
+        assert_eq!(Utxo::try_from(&public).unwrap().compute_id(), remainder.id);
+        let forged = Utxo {
+            original_quote_quantity: 2_000,
+            ..remainder.clone()
+        };
+        assert_ne!(forged.compute_id(), remainder.id);
+    }
+
+    #[test]
+    fn test_directed_order_is_skipped_by_other_counterparties() {
+        const CAROL: Address = Address::repeat_byte(0xc0);
+        const DAVE: Address = Address::repeat_byte(0xd0);
+        let directed = Order {
+            counterparties: vec![CAROL],
+            ..order(Side::Sell, 100, 10, ALICE, 1)
+        };
+        assert_ne!(
+            directed.compute_utxo_id(),
+            order(Side::Sell, 100, 10, ALICE, 1).compute_utxo_id()
+        );
+
+        let output = match_orders(new_orders_batch(vec![
+            directed,
+            order(Side::Sell, 101, 10, DAVE, 2),
+            order(Side::Buy, 101, 20, BOB, 3),
+            order(Side::Buy, 100, 5, CAROL, 4),
+        ]))
+        .unwrap();
+
+        // BOB passes over the better priced sell, which still trades with CAROL
+        let fills: Vec<(Address, Address, u64, u64)> = output
+            .fills
+            .iter()
+            .map(|fill| (fill.maker, fill.taker, fill.price, fill.quantity))
+            .collect();
+        assert_eq!(fills, vec![(DAVE, BOB, 101, 10), (ALICE, CAROL, 100, 5)]);
+        let remainder = output
+            .new_utxos
+            .iter()
+            .find(|utxo| utxo.order.owner == ALICE)
+            .unwrap();
+        assert_eq!(remainder.order.counterparties, vec![CAROL]);
+
+        let crowded = SolOrder {
+            counterparties: vec![CAROL; MAX_COUNTERPARTIES + 1],
+            ..SolOrder::from(&order(Side::Sell, 100, 10, ALICE, 1))
+        };
+        assert!(Order::try_from(&crowded).is_err());
     }
 }
@@ -0,0 +1,26 @@
+use alloy_sol_types::SolValue;
+use orderbook::{match_markets, BatchInput, SolMultiBatchInput};
+use risc0_zkvm::guest::env;
+
+// Matching only, for cycle benchmarks: no chain state is read or verified, so the
+// book can hold any number of resting UTXOs
+fn main() {
+    // Read the ABI-encoded batch input of every market
+    let input_bytes: Vec<u8> = env::read();
+    let sol_input =
+        <SolMultiBatchInput>::abi_decode(&input_bytes).expect("failed to ABI-decode batch input");
+
+    let inputs = sol_input
+        .markets
+        .iter()
+        .map(BatchInput::from_sol)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|err| panic!("{err}"));
+
+    // Run each market's matching engine (this also verifies Merkle proofs for UTXOs)
+    let outputs = match_markets(inputs).unwrap_or_else(|err| panic!("{err}"));
+
+    // Commit the fill count so the benchmark can check the book crossed
+    let fills: u64 = outputs.iter().map(|output| output.fills.len() as u64).sum();
+    env::commit(&fills);
+}
@@ -1,14 +1,52 @@
 use alloy_primitives::Address;
 use alloy_sol_types::{sol, SolValue};
-use orderbook::{match_orders, BatchInput, SolBatchInput};
-use risc0_steel::{ethereum::EthEvmInput, ethereum::ETH_SEPOLIA_CHAIN_SPEC, Contract};
+use orderbook::{
+    match_markets, to_journal, BatchInput, MarketParams, OracleRound, SolMultiBatchInput,
+};
+use risc0_steel::{
+    ethereum::EthEvmInput, ethereum::ETH_SEPOLIA_CHAIN_SPEC, Contract, EvmBlockHeader,
+};
 use risc0_zkvm::guest::env;
 
 // Define the OrderBook contract interface for Steel calls
 sol! {
     interface IOrderBook {
         function utxoMerkleRoot() external view returns (bytes32);
+        function utxoLeafCount() external view returns (uint64);
         function currentBatchIndex() external view returns (uint64);
+        function selfTradePrevention() external view returns (uint8);
+        function matchingEngine() external view returns (uint8);
+        function postOnlyPolicy() external view returns (uint8);
+        function lastTradePrice() external view returns (uint64);
+        function oracle() external view returns (address);
+        function makerFeeBps() external view returns (uint16);
+        function takerFeeBps() external view returns (uint16);
+        function marketParams()
+            external
+            view
+            returns (
+                uint64 tickSize,
+                uint64 lotSize,
+                uint64 minQuantity,
+                uint128 minNotional,
+                int8 priceExponent
+            );
+        function priceBand() external view returns (uint16 bandBps, uint8 policy);
+    }
+
+    /// Chainlink price feed interface
+    interface IAggregatorV3 {
+        function latestRoundData()
+            external
+            view
+            returns (
+                uint80 roundId,
+                int256 answer,
+                uint256 startedAt,
+                uint256 updatedAt,
+                uint80 answeredInRound
+            );
+        function decimals() external view returns (uint8);
     }
 }
 
@@ -16,44 +54,123 @@ fn main() {
     // Read the Steel EVM input
     let evm_input: EthEvmInput = env::read();
 
-    // Read the OrderBook contract address
-    let order_book_address: Address = env::read();
-
-    // Read the ABI-encoded batch input
+    // Read the ABI-encoded batch input of every market cleared in this execution
     let input_bytes: Vec<u8> = env::read();
-    let sol_input = <SolBatchInput>::abi_decode(&input_bytes).unwrap();
+    let sol_input =
+        <SolMultiBatchInput>::abi_decode(&input_bytes).expect("failed to ABI-decode batch input");
 
-    // Create Steel environment and contract
+    // Create Steel environment
     let evm_env = evm_input.into_env(&ETH_SEPOLIA_CHAIN_SPEC);
-    let contract = Contract::new(order_book_address, &evm_env);
-
-    // Query on-chain state
-    let on_chain_merkle_root = contract
-        .call_builder(&IOrderBook::utxoMerkleRootCall {})
-        .call();
-    let on_chain_batch_index = contract
-        .call_builder(&IOrderBook::currentBatchIndexCall {})
-        .call();
-
-    // Verify input matches on-chain state
-    assert_eq!(
-        sol_input.utxoMerkleRoot, on_chain_merkle_root,
-        "UTXO Merkle root mismatch"
-    );
-    assert_eq!(
-        sol_input.batchIndex, on_chain_batch_index,
-        "Batch index mismatch"
-    );
-
-    // Convert to internal types
-    let input = BatchInput::from_sol(&sol_input);
-
-    // Run the matching engine (this also verifies Merkle proofs for UTXOs)
-    let output = match_orders(input);
+    let block_timestamp = evm_env.header().timestamp();
+
+    let mut inputs = Vec::with_capacity(sol_input.markets.len());
+    for sol_market in &sol_input.markets {
+        // Convert to internal types
+        let input = BatchInput::from_sol(sol_market).unwrap_or_else(|err| panic!("{err}"));
+
+        // The market is the OrderBook contract its on-chain state is read from
+        let contract = Contract::new(input.market, &evm_env);
+
+        // Query on-chain state
+        let on_chain_merkle_root = contract
+            .call_builder(&IOrderBook::utxoMerkleRootCall {})
+            .call();
+        let on_chain_leaf_count = contract
+            .call_builder(&IOrderBook::utxoLeafCountCall {})
+            .call();
+        let on_chain_batch_index = contract
+            .call_builder(&IOrderBook::currentBatchIndexCall {})
+            .call();
+        let on_chain_self_trade_prevention = contract
+            .call_builder(&IOrderBook::selfTradePreventionCall {})
+            .call();
+        let on_chain_matching_engine = contract
+            .call_builder(&IOrderBook::matchingEngineCall {})
+            .call();
+        let on_chain_post_only_policy = contract
+            .call_builder(&IOrderBook::postOnlyPolicyCall {})
+            .call();
+        let on_chain_last_trade_price = contract
+            .call_builder(&IOrderBook::lastTradePriceCall {})
+            .call();
+        let on_chain_maker_fee_bps = contract
+            .call_builder(&IOrderBook::makerFeeBpsCall {})
+            .call();
+        let on_chain_taker_fee_bps = contract
+            .call_builder(&IOrderBook::takerFeeBpsCall {})
+            .call();
+        let on_chain_market_params = contract
+            .call_builder(&IOrderBook::marketParamsCall {})
+            .call();
+        let on_chain_price_band = contract.call_builder(&IOrderBook::priceBandCall {}).call();
+        let oracle_address = contract.call_builder(&IOrderBook::oracleCall {}).call();
+
+        // Read the latest oracle round in the same EVM environment (markets without an oracle have none).
+        // A stale or unusable round prices nothing, so pegged orders sit out instead of failing the proof.
+        let oracle_round = (oracle_address != Address::ZERO)
+            .then(|| {
+                let oracle = Contract::new(oracle_address, &evm_env);
+                let round = oracle
+                    .call_builder(&IAggregatorV3::latestRoundDataCall {})
+                    .call();
+                let decimals = oracle.call_builder(&IAggregatorV3::decimalsCall {}).call();
+                OracleRound::from_answer(
+                    round.roundId,
+                    round.answer,
+                    round.updatedAt,
+                    decimals,
+                    on_chain_market_params.priceExponent,
+                    block_timestamp,
+                )
+                .ok()
+            })
+            .flatten();
+
+        // Verify input matches on-chain state
+        input
+            .verify_chain_state(
+                on_chain_merkle_root,
+                on_chain_batch_index,
+                on_chain_self_trade_prevention,
+                on_chain_matching_engine,
+                on_chain_post_only_policy,
+                on_chain_last_trade_price,
+            )
+            .unwrap_or_else(|err| panic!("{err}"));
+        input
+            .verify_utxo_leaf_count(on_chain_leaf_count)
+            .unwrap_or_else(|err| panic!("{err}"));
+        input
+            .verify_block_timestamp(block_timestamp)
+            .unwrap_or_else(|err| panic!("{err}"));
+        input
+            .verify_oracle_round(oracle_round)
+            .unwrap_or_else(|err| panic!("{err}"));
+        input
+            .verify_fee_schedule(on_chain_maker_fee_bps, on_chain_taker_fee_bps)
+            .unwrap_or_else(|err| panic!("{err}"));
+        input
+            .verify_market_params(MarketParams {
+                tick_size: on_chain_market_params.tickSize,
+                lot_size: on_chain_market_params.lotSize,
+                min_quantity: on_chain_market_params.minQuantity,
+                min_notional: on_chain_market_params.minNotional,
+                price_exponent: on_chain_market_params.priceExponent,
+            })
+            .unwrap_or_else(|err| panic!("{err}"));
+        input
+            .verify_price_band(on_chain_price_band.bandBps, on_chain_price_band.policy)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        inputs.push(input);
+    }
+
+    // Run each market's matching engine (this also verifies Merkle proofs for UTXOs)
+    let outputs = match_markets(inputs).unwrap_or_else(|err| panic!("{err}"));
 
     // Get the Steel commitment and create journal
     let commitment = evm_env.into_commitment();
-    let journal = output.to_journal(commitment);
+    let journal = to_journal(&outputs, commitment);
 
     // Commit the journal (ABI-encoded for Solidity)
     env::commit_slice(&journal.abi_encode());